@@ -0,0 +1,73 @@
+//! Throughput of `Database::execute` and `Database::transaction` on the compiled pool.
+//!
+//! Needs a reachable database, compare the backends with
+//! `DATABASE_URL=postgres://... cargo bench` and the same with `--features async-pool`.
+use axum_boilerplate::database::Database;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use diesel::{sql_query, RunQueryDsl};
+use futures_util::future::try_join_all;
+use tokio::runtime::Runtime;
+
+// Concurrent calls per iteration, the largest one exceeds the pool
+const CONCURRENCY: [usize; 3] = [1, 64, 512];
+
+#[cfg(not(feature = "async-pool"))]
+const BACKEND: &str = "r2d2";
+#[cfg(feature = "async-pool")]
+const BACKEND: &str = "deadpool";
+
+async fn select(db: &Database) -> anyhow::Result<()> {
+    db.execute(|conn| {
+        sql_query("SELECT 1").execute(conn)?;
+        Ok(())
+    })
+    .await
+}
+
+async fn transaction(db: &Database) -> anyhow::Result<()> {
+    db.transaction(|conn| {
+        sql_query("SELECT 1").execute(conn)?;
+        sql_query("SELECT 2").execute(conn)?;
+        Ok(())
+    })
+    .await
+}
+
+fn database(c: &mut Criterion) {
+    let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL_REQUIRED");
+    let runtime = Runtime::new().expect("RUNTIME_FAILED");
+    let db = runtime
+        .block_on(async { Database::new(&database_url) })
+        .expect("DATABASE_CONNECTION_FAILED");
+
+    let mut group = c.benchmark_group("execute");
+    for concurrency in CONCURRENCY {
+        group.throughput(Throughput::Elements(concurrency as u64));
+        group.bench_with_input(
+            BenchmarkId::new(BACKEND, concurrency),
+            &concurrency,
+            |b, &n| {
+                b.to_async(&runtime)
+                    .iter(|| try_join_all((0..n).map(|_| select(&db))))
+            },
+        );
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("transaction");
+    for concurrency in CONCURRENCY {
+        group.throughput(Throughput::Elements(concurrency as u64));
+        group.bench_with_input(
+            BenchmarkId::new(BACKEND, concurrency),
+            &concurrency,
+            |b, &n| {
+                b.to_async(&runtime)
+                    .iter(|| try_join_all((0..n).map(|_| transaction(&db))))
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, database);
+criterion_main!(benches);
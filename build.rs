@@ -0,0 +1,4 @@
+// Rebuild when a migration changes, they are embedded with diesel_migrations
+fn main() {
+    println!("cargo:rerun-if-changed=migrations");
+}
@@ -0,0 +1,10 @@
+-- Passwordless login links, a row is consumed at most once
+CREATE TABLE magic_links (
+    id BIGINT PRIMARY KEY,
+    email VARCHAR(255) NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL,
+    consumed_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX magic_links_email_created_at_idx ON magic_links (email, created_at);
@@ -0,0 +1,11 @@
+ALTER DEFAULT PRIVILEGES IN SCHEMA public
+    REVOKE USAGE, SELECT ON SEQUENCES FROM app_rls_bypass;
+ALTER DEFAULT PRIVILEGES IN SCHEMA public
+    REVOKE SELECT, INSERT, UPDATE, DELETE ON TABLES FROM app_rls_bypass;
+REVOKE USAGE, SELECT ON ALL SEQUENCES IN SCHEMA public FROM app_rls_bypass;
+REVOKE SELECT, INSERT, UPDATE, DELETE ON ALL TABLES IN SCHEMA public FROM app_rls_bypass;
+
+-- The role is shared by the databases of the cluster and kept
+
+DROP TABLE organization_members;
+DROP TABLE organizations;
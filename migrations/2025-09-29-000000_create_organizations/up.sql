@@ -0,0 +1,53 @@
+-- Tenants, every tenant scoped row references an organization
+CREATE TABLE organizations (
+    id BIGINT PRIMARY KEY,
+    name VARCHAR(255) NOT NULL,
+    -- Resolves the tenant from the X-Tenant-Id header or the request subdomain
+    slug VARCHAR(63) NOT NULL UNIQUE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE organization_members (
+    organization_id BIGINT NOT NULL REFERENCES organizations (id) ON DELETE CASCADE,
+    user_id BIGINT NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    -- owner, admin or member
+    role VARCHAR(16) NOT NULL DEFAULT 'member',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (organization_id, user_id)
+);
+
+CREATE INDEX organization_members_user_id_idx ON organization_members (user_id);
+
+-- Row level security: rows of tenant scoped tables are only visible inside
+-- Database::tenant_transaction, which SET LOCAL app.tenant_id, or to the bypass role
+-- below. An unset or reset setting compares NULL and hides every row.
+--
+-- Cross tenant work (the organizations of a user, personal data exports and erasures) runs
+-- in Database::unscoped_transaction, which SET LOCAL ROLE app_rls_bypass. The role cannot
+-- log in, it is granted to the role running the migrations, which the application uses.
+-- Policies are checked against current_user so the grant alone does not bypass them.
+DO $$
+BEGIN
+    IF NOT EXISTS (SELECT FROM pg_roles WHERE rolname = 'app_rls_bypass') THEN
+        CREATE ROLE app_rls_bypass NOLOGIN;
+    END IF;
+END
+$$;
+GRANT app_rls_bypass TO CURRENT_USER;
+
+-- The role acts on every table of the application, including the ones created later
+GRANT SELECT, INSERT, UPDATE, DELETE ON ALL TABLES IN SCHEMA public TO app_rls_bypass;
+GRANT USAGE, SELECT ON ALL SEQUENCES IN SCHEMA public TO app_rls_bypass;
+ALTER DEFAULT PRIVILEGES IN SCHEMA public
+    GRANT SELECT, INSERT, UPDATE, DELETE ON TABLES TO app_rls_bypass;
+ALTER DEFAULT PRIVILEGES IN SCHEMA public
+    GRANT USAGE, SELECT ON SEQUENCES TO app_rls_bypass;
+
+ALTER TABLE organization_members ENABLE ROW LEVEL SECURITY;
+ALTER TABLE organization_members FORCE ROW LEVEL SECURITY;
+CREATE POLICY organization_members_tenant_isolation ON organization_members
+    USING (
+        organization_id = NULLIF(current_setting('app.tenant_id', true), '')::BIGINT
+        OR current_user = 'app_rls_bypass'
+    );
@@ -0,0 +1,26 @@
+-- Pending invitations to join an organization, accepting creates or links the account
+CREATE TABLE invitations (
+    id BIGINT PRIMARY KEY,
+    organization_id BIGINT NOT NULL REFERENCES organizations (id) ON DELETE CASCADE,
+    email VARCHAR(255) NOT NULL,
+    -- Membership role granted on accept
+    role VARCHAR(16) NOT NULL,
+    invited_by BIGINT REFERENCES users (id) ON DELETE SET NULL,
+    expires_at TIMESTAMPTZ NOT NULL,
+    accepted_at TIMESTAMPTZ,
+    revoked_at TIMESTAMPTZ,
+    sent_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- One open invitation per email and organization, resend it instead of inviting again
+CREATE UNIQUE INDEX invitations_pending_key ON invitations (organization_id, email)
+    WHERE accepted_at IS NULL AND revoked_at IS NULL;
+
+ALTER TABLE invitations ENABLE ROW LEVEL SECURITY;
+ALTER TABLE invitations FORCE ROW LEVEL SECURITY;
+CREATE POLICY invitations_tenant_isolation ON invitations
+    USING (
+        organization_id = NULLIF(current_setting('app.tenant_id', true), '')::BIGINT
+        OR current_user = 'app_rls_bypass'
+    );
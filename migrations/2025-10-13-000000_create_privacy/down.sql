@@ -0,0 +1,3 @@
+DROP TABLE erasure_requests;
+DROP TABLE data_exports;
+DROP TABLE audit_logs;
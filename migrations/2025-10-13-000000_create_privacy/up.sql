@@ -0,0 +1,46 @@
+-- Append only trail of sensitive operations, details never hold personal data
+CREATE TABLE audit_logs (
+    id BIGINT PRIMARY KEY,
+    -- NULL when a background task acted
+    actor_id BIGINT,
+    action VARCHAR(64) NOT NULL,
+    subject_type VARCHAR(64) NOT NULL,
+    subject_id BIGINT NOT NULL,
+    details JSONB NOT NULL DEFAULT '{}',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX audit_logs_subject_idx ON audit_logs (subject_type, subject_id, created_at);
+
+-- Personal data export jobs, the archive is kept until expires_at
+CREATE TABLE data_exports (
+    id BIGINT PRIMARY KEY,
+    user_id BIGINT NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    -- json or zip
+    format VARCHAR(8) NOT NULL,
+    -- pending, ready or failed
+    status VARCHAR(16) NOT NULL DEFAULT 'pending',
+    file_key VARCHAR(128),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    completed_at TIMESTAMPTZ,
+    expires_at TIMESTAMPTZ
+);
+
+CREATE INDEX data_exports_user_id_idx ON data_exports (user_id, created_at);
+
+-- Right to erasure, executed once the grace period is over unless cancelled
+CREATE TABLE erasure_requests (
+    id BIGINT PRIMARY KEY,
+    user_id BIGINT NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    -- pending, cancelled or completed
+    status VARCHAR(16) NOT NULL DEFAULT 'pending',
+    execute_after TIMESTAMPTZ NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    cancelled_at TIMESTAMPTZ,
+    completed_at TIMESTAMPTZ
+);
+
+CREATE UNIQUE INDEX erasure_requests_pending_key ON erasure_requests (user_id)
+    WHERE status = 'pending';
+CREATE INDEX erasure_requests_due_idx ON erasure_requests (execute_after)
+    WHERE status = 'pending';
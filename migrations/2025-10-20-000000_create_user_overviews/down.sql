@@ -0,0 +1,2 @@
+DROP FUNCTION get_user_overview(BIGINT);
+DROP VIEW user_overviews;
@@ -0,0 +1,20 @@
+-- Profile summary of live users, read through the typed view in src/schema/view.rs
+CREATE VIEW user_overviews AS
+SELECT
+    u.id,
+    u.full_name,
+    u.email,
+    u.role,
+    u.created_at,
+    (SELECT COUNT(*) FROM organization_members m WHERE m.user_id = u.id) AS organization_count,
+    (SELECT COUNT(*) FROM user_identities i WHERE i.user_id = u.id) AS identity_count
+FROM users u
+WHERE u.deleted_at IS NULL;
+
+-- Called through database::function, no row when the user does not exist or is deleted
+CREATE FUNCTION get_user_overview(p_user_id BIGINT)
+RETURNS SETOF user_overviews
+LANGUAGE sql STABLE
+AS $$
+    SELECT * FROM user_overviews WHERE id = p_user_id
+$$;
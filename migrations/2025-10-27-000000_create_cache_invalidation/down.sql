@@ -0,0 +1,2 @@
+DROP TRIGGER organizations_cache_invalidation ON organizations;
+DROP FUNCTION notify_tenant_cache_invalidation();
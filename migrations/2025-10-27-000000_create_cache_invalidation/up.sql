@@ -0,0 +1,18 @@
+-- Announce changed rows on the cache_invalidation channel, the payload names the cache key
+-- the listener drops. Sent on commit, never for rolled back changes.
+--
+-- The tenant middleware caches organizations under `tenant:<id>` and `tenant:<slug>`.
+-- Renaming the slug or deleting the organization announces both keys of the old row.
+CREATE FUNCTION notify_tenant_cache_invalidation() RETURNS trigger
+LANGUAGE plpgsql
+AS $$
+BEGIN
+    PERFORM pg_notify('cache_invalidation', 'tenant:' || OLD.id);
+    PERFORM pg_notify('cache_invalidation', 'tenant:' || OLD.slug);
+    RETURN NULL;
+END;
+$$;
+
+CREATE TRIGGER organizations_cache_invalidation
+AFTER UPDATE OF slug OR DELETE ON organizations
+FOR EACH ROW EXECUTE FUNCTION notify_tenant_cache_invalidation();
@@ -0,0 +1,20 @@
+-- Domain events written in the transaction of the change they announce, the relay
+-- delivers them to the sinks after commit, at least once
+CREATE TABLE outbox_events (
+    id BIGINT PRIMARY KEY,
+    -- e.g. user.registered
+    event_type VARCHAR(64) NOT NULL,
+    aggregate_type VARCHAR(64) NOT NULL,
+    aggregate_id BIGINT NOT NULL,
+    payload JSONB NOT NULL DEFAULT '{}',
+    -- pending, delivered or dead (gave up after the last attempt)
+    status VARCHAR(16) NOT NULL DEFAULT 'pending',
+    attempts INT NOT NULL DEFAULT 0,
+    last_error TEXT,
+    -- Also leases a claimed event, it becomes due again if the relay dies mid delivery
+    next_attempt_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    delivered_at TIMESTAMPTZ
+);
+
+CREATE INDEX outbox_events_pending_idx ON outbox_events (next_attempt_at, id) WHERE status = 'pending';
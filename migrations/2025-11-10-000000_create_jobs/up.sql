@@ -0,0 +1,25 @@
+-- Background job queue, workers claim due rows with FOR UPDATE SKIP LOCKED
+CREATE TABLE jobs (
+    id BIGINT PRIMARY KEY,
+    queue VARCHAR(64) NOT NULL DEFAULT 'default',
+    job_type VARCHAR(64) NOT NULL,
+    payload JSONB NOT NULL DEFAULT '{}',
+    -- pending, running, completed or failed (gave up after max_attempts)
+    status VARCHAR(16) NOT NULL DEFAULT 'pending',
+    attempts INT NOT NULL DEFAULT 0,
+    max_attempts INT NOT NULL DEFAULT 5,
+    last_error TEXT,
+    unique_key VARCHAR(255),
+    -- Not claimed before, also the time of the next retry
+    run_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    -- Lease of a running job, claimed again once it passed
+    locked_until TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    completed_at TIMESTAMPTZ
+);
+
+CREATE INDEX jobs_pending_idx ON jobs (queue, run_at, id) WHERE status = 'pending';
+CREATE INDEX jobs_running_idx ON jobs (queue, locked_until) WHERE status = 'running';
+-- One live job per key, finished ones do not block a new one
+CREATE UNIQUE INDEX jobs_unique_key_idx ON jobs (job_type, unique_key)
+    WHERE unique_key IS NOT NULL AND status IN ('pending', 'running');
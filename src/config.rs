@@ -6,10 +6,14 @@ pub enum AppEnv {
 }
 #[derive(clap::Parser, Debug, Clone)]
 pub struct Config {
+    #[command(subcommand)]
+    pub command: Option<Command>,
     #[clap(long, env = "APP_ENV", default_value = "development")]
     pub app_env: AppEnv,
     #[clap(long, env = "SECRET")]
     pub secret: String,
+    #[clap(long, env = "REFRESH_SECRET")]
+    pub refresh_secret: String,
     #[clap(long, env = "PORT", default_value = "8080")]
     pub port: u16,
     #[clap(long, env = "TIMEOUT", default_value = "300")] // 5 Minutes
@@ -26,9 +30,131 @@ pub struct Config {
     pub llm_api_key: String,
     #[clap(long, env = "DATABASE_URL", default_value = "")]
     pub database_url: String,
+    #[clap(long, env = "DATABASE_POOL_MAX_SIZE", default_value = "32")]
+    pub database_pool_max_size: u32,
+    // Idle connections kept open, also for every replica
+    #[clap(long, env = "DATABASE_POOL_MIN_IDLE", default_value = "8")]
+    pub database_pool_min_idle: u32,
+    // Seconds to wait for a free pooled connection
+    #[clap(long, env = "DATABASE_CONNECTION_TIMEOUT", default_value = "60")]
+    pub database_connection_timeout: u64,
+    #[clap(long, env = "DATABASE_IDLE_TIMEOUT", default_value = "600")] // 10 Minutes
+    pub database_idle_timeout: u64,
+    #[clap(long, env = "DATABASE_MAX_LIFETIME", default_value = "3600")] // 1 Hour
+    pub database_max_lifetime: u64,
+    // Seconds a statement may run, defaults to the request TIMEOUT
+    #[clap(long, env = "DATABASE_STATEMENT_TIMEOUT")]
+    pub database_statement_timeout: Option<u64>,
+    // Milliseconds after which a query is logged as SLOW_QUERY
+    #[clap(long, env = "DATABASE_SLOW_QUERY_MS", default_value = "500")]
+    pub database_slow_query_ms: u64,
+    // Apply pending embedded migrations before serving, under an advisory lock
+    #[clap(long, env = "RUN_MIGRATIONS", default_value = "false", action = clap::ArgAction::Set)]
+    pub run_migrations: bool,
+    // Read replica urls separated by comma, read-only queries are spread over them
+    #[clap(long, env = "DATABASE_REPLICA_URLS", default_value = "")]
+    pub database_replica_urls: String,
+    // Replicas further behind the primary than this many seconds stop serving reads
+    #[clap(long, env = "DATABASE_REPLICA_MAX_LAG", default_value = "10")]
+    pub database_replica_max_lag: u64,
+    #[clap(long, env = "DATABASE_REPLICA_CHECK_INTERVAL", default_value = "5")]
+    pub database_replica_check_interval: u64,
+    // NOTIFY channels separated by comma whose payloads invalidate cache keys, empty disables
+    #[clap(
+        long,
+        env = "CACHE_INVALIDATION_CHANNELS",
+        default_value = "cache_invalidation"
+    )]
+    pub cache_invalidation_channels: String,
     #[clap(long, env = "MAIL_SMTP", default_value = "")]
     pub mail_smtp: String,
+    #[clap(long, env = "MAIL_FROM", default_value = "no-reply@localhost")]
+    pub mail_from: String,
+    // Public base url used to build redirect and callback links
+    #[clap(long, env = "APP_URL", default_value = "http://localhost:8080")]
+    pub app_url: String,
+    // NAME;ISSUER;CLIENT_ID;CLIENT_SECRET separated by comma
+    #[clap(long, env = "OIDC_PROVIDERS", default_value = "")]
+    pub oidc_providers: String,
+    #[clap(long, env = "MAGIC_LINK_TTL", default_value = "900")] // 15 Minutes
+    pub magic_link_ttl: u64,
+    // Maximum magic links sent to one email address per hour
+    #[clap(long, env = "MAGIC_LINK_RATE_LIMIT", default_value = "5")]
+    pub magic_link_rate_limit: i64,
+    // Soft deleted rows are purged for good after this many days
+    #[clap(long, env = "SOFT_DELETE_RETENTION_DAYS", default_value = "30")]
+    pub soft_delete_retention_days: i64,
+    #[clap(long, env = "SOFT_DELETE_PURGE_INTERVAL", default_value = "3600")] // 1 Hour
+    pub soft_delete_purge_interval: u64,
+    // Erasure requests run after this many days, the user can cancel until then
+    #[clap(long, env = "ERASURE_GRACE_DAYS", default_value = "14")]
+    pub erasure_grace_days: i64,
+    // Due erasures and expired export archives are handled on this interval
+    #[clap(long, env = "PRIVACY_TASK_INTERVAL", default_value = "3600")] // 1 Hour
+    pub privacy_task_interval: u64,
+    // Milliseconds between outbox relay rounds
+    #[clap(long, env = "OUTBOX_POLL_INTERVAL", default_value = "1000")]
+    pub outbox_poll_interval: u64,
+    #[clap(long, env = "OUTBOX_BATCH_SIZE", default_value = "20")]
+    pub outbox_batch_size: i64,
+    // Deliveries tried before an event is dead-lettered
+    #[clap(long, env = "OUTBOX_MAX_ATTEMPTS", default_value = "10")]
+    pub outbox_max_attempts: i32,
+    // Delivered events are purged after this many days
+    #[clap(long, env = "OUTBOX_RETENTION_DAYS", default_value = "7")]
+    pub outbox_retention_days: i64,
+    // Webhook urls separated by comma, every outbox event is POSTed to each
+    #[clap(long, env = "OUTBOX_WEBHOOK_URLS", default_value = "")]
+    pub outbox_webhook_urls: String,
+    // Signs webhook bodies in x-outbox-signature when set
+    #[clap(long, env = "OUTBOX_WEBHOOK_SECRET", default_value = "")]
+    pub outbox_webhook_secret: String,
+    // Job queues to work as name:concurrency separated by comma, empty runs no workers
+    #[clap(long, env = "JOB_QUEUES", default_value = "default:4")]
+    pub job_queues: String,
+    // Milliseconds a worker waits before looking at an empty queue again
+    #[clap(long, env = "JOB_POLL_INTERVAL", default_value = "1000")]
+    pub job_poll_interval: u64,
+    // Seconds running jobs get to finish on shutdown
+    #[clap(long, env = "JOB_DRAIN_TIMEOUT", default_value = "30")]
+    pub job_drain_timeout: u64,
+    // Completed jobs are purged after this many days
+    #[clap(long, env = "JOB_RETENTION_DAYS", default_value = "7")]
+    pub job_retention_days: i64,
+    #[clap(long, env = "INVITATION_TTL", default_value = "604800")] // 7 Days
+    pub invitation_ttl: u64,
+    // Client page the invitation email links to, it posts the token to /invitations/accept
+    #[clap(
+        long,
+        env = "INVITATION_URL",
+        default_value = "http://localhost:8080/invitations/accept"
+    )]
+    pub invitation_url: String,
+    // Requests to <slug>.TENANT_BASE_DOMAIN act on that organization, empty disables it
+    #[clap(long, env = "TENANT_BASE_DOMAIN", default_value = "")]
+    pub tenant_base_domain: String,
+}
+// Commands run instead of the server, e.g. `axum_boilerplate migrate up`
+#[derive(clap::Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Manage the migrations embedded in the binary
+    #[command(subcommand)]
+    Migrate(MigrateCommand),
+}
+
+#[derive(clap::Subcommand, Debug, Clone, Copy)]
+pub enum MigrateCommand {
+    /// Run every pending migration
+    Up,
+    /// Revert the latest applied migrations
+    Down {
+        #[arg(long, default_value = "1")]
+        steps: usize,
+    },
+    /// List the migrations and whether they are applied
+    Status,
 }
+
 impl Config {
     pub fn load() -> Self {
         Config::parse()
@@ -1,6 +1,61 @@
 use axum::http::{header, HeaderName, Method};
 
 pub const CACHE_TIMEOUT: u64 = 3600; // 1 hour default cache
-pub const METHOD_ALLOW: [Method; 4] = [Method::GET, Method::POST, Method::PUT, Method::DELETE];
-pub const HEADER_ALLOW: [HeaderName; 2] = [header::CONTENT_TYPE, header::ACCEPT];
+                                     // Cache keys announced by the cache_invalidation triggers, dropped when the listener reconnects
+pub const CACHE_INVALIDATED_KEYS: [&str; 1] = ["tenant:*"];
+pub const METHOD_ALLOW: [Method; 5] = [
+    Method::GET,
+    Method::POST,
+    Method::PUT,
+    Method::PATCH,
+    Method::DELETE,
+];
+pub const HEADER_ALLOW: [HeaderName; 6] = [
+    header::CONTENT_TYPE,
+    header::ACCEPT,
+    header::IF_MATCH,
+    header::IF_NONE_MATCH,
+    HEADER_TENANT,
+    HEADER_READ_YOUR_WRITES,
+];
+pub const HEADER_EXPOSE: [HeaderName; 1] = [header::ETAG];
 pub const CORS_WHITELIST: [&str; 2] = ["http://localhost:5000", "http://localhost:8080"];
+
+// Organization id or slug the request acts on, a tenant claim in the token takes precedence
+pub const HEADER_TENANT: HeaderName = HeaderName::from_static("x-tenant-id");
+// `true` serves a read request from the primary database instead of a replica
+pub const HEADER_READ_YOUR_WRITES: HeaderName = HeaderName::from_static("x-read-your-writes");
+
+// Values of organization_members.role, by increasing privilege
+pub const MEMBER_ROLE_MEMBER: &str = "member";
+pub const MEMBER_ROLE_ADMIN: &str = "admin";
+pub const MEMBER_ROLE_OWNER: &str = "owner";
+pub const MEMBER_ROLES: [&str; 3] = [MEMBER_ROLE_MEMBER, MEMBER_ROLE_ADMIN, MEMBER_ROLE_OWNER];
+
+// Role switched to by Database::unscoped_transaction, its policies see every tenant
+pub const RLS_BYPASS_ROLE: &str = "app_rls_bypass";
+
+// Values of users.role
+pub const ROLE_USER: &str = "user";
+pub const ROLE_ADMIN: &str = "admin";
+
+// Avatar uploads
+pub const AVATAR_MAX_BYTES: usize = 5 * 1024 * 1024; // 5 MiB
+pub const AVATAR_MAX_DIMENSION: u32 = 4096;
+pub const AVATAR_SIZES: [u32; 3] = [64, 128, 256];
+pub const AVATAR_CACHE_CONTROL: &str = "public, max-age=86400"; // 1 day, urls carry the version
+
+// Bulk user import
+pub const IMPORT_MAX_BYTES: usize = 50 * 1024 * 1024; // 50 MiB
+pub const IMPORT_BATCH_SIZE: usize = 500;
+pub const IMPORT_MAX_REPORTED_ERRORS: usize = 1000;
+
+// Personal data exports
+pub const DATA_EXPORT_TTL_HOURS: i64 = 72; // archives are deleted afterwards
+pub const DATA_EXPORT_STALE_MINUTES: i64 = 60; // a pending job older than this has died
+
+// Postgres advisory lock held while migrations run, so instances starting together apply them once
+pub const MIGRATION_LOCK_KEY: i64 = 0x006d_6967_7261_7465; // "migrate" in ASCII
+
+// Class of the advisory locks serializing the magic link requests of one email address
+pub const MAGIC_LINK_LOCK_CLASS: i32 = 0x6d61_676c; // "magl" in ASCII
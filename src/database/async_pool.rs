@@ -0,0 +1,121 @@
+//! Async backend of [`Database`], enabled with the `async-pool` cargo feature.
+//!
+//! The connections are Diesel `PgConnection`s in a deadpool pool. Waiting for a free
+//! connection only suspends the task, a blocking thread is taken once the connection is
+//! checked out, while r2d2 holds one for the whole call, including that wait.
+use super::{apply_session_settings, PoolSettings};
+use anyhow::Result;
+use deadpool::managed::{self, Metrics, RecycleError, RecycleResult};
+use deadpool::Runtime;
+use diesel::pg::PgConnection;
+use diesel::r2d2::R2D2Connection;
+use diesel::{Connection, ConnectionError, ConnectionResult};
+use std::time::Duration;
+
+pub(super) type PooledConnection = managed::Object<Manager>;
+
+// The pool is Debug only for Debug connections, which PgConnection is not
+#[derive(Clone)]
+pub(super) struct AsyncPool(managed::Pool<Manager>);
+
+impl std::ops::Deref for AsyncPool {
+    type Target = managed::Pool<Manager>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl std::fmt::Debug for AsyncPool {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        f.debug_tuple("AsyncPool").field(&self.0.status()).finish()
+    }
+}
+
+/// Opens the connections of [`AsyncPool`], with the same session settings as r2d2.
+#[derive(Clone, Debug)]
+pub struct Manager {
+    database_url: String,
+    statement_timeout: Option<Duration>,
+    idle_timeout: Duration,
+    max_lifetime: Duration,
+}
+
+impl Manager {
+    // Blocking, like every use of the connection
+    fn connect(&self) -> ConnectionResult<PgConnection> {
+        let mut conn = PgConnection::establish(&self.database_url)?;
+        apply_session_settings(&mut conn, self.statement_timeout)
+            .map_err(ConnectionError::CouldntSetupConfiguration)?;
+        Ok(conn)
+    }
+}
+
+impl managed::Manager for Manager {
+    type Type = PgConnection;
+    type Error = ConnectionError;
+
+    async fn create(&self) -> ConnectionResult<PgConnection> {
+        let manager = self.clone();
+        tokio::task::spawn_blocking(move || manager.connect())
+            .await
+            .map_err(|err| ConnectionError::BadConnection(err.to_string()))?
+    }
+
+    // Only the checks that need no round trip, the ping runs in the blocking thread
+    async fn recycle(
+        &self,
+        conn: &mut PgConnection,
+        metrics: &Metrics,
+    ) -> RecycleResult<ConnectionError> {
+        if conn.is_broken() {
+            return Err(RecycleError::message("CONNECTION_BROKEN"));
+        }
+        if metrics.age() > self.max_lifetime || metrics.last_used() > self.idle_timeout {
+            return Err(RecycleError::message("CONNECTION_EXPIRED"));
+        }
+        Ok(())
+    }
+}
+
+pub(super) fn build(
+    database_url: &str,
+    settings: &PoolSettings,
+) -> Result<AsyncPool> {
+    let manager = Manager {
+        database_url: database_url.to_string(),
+        statement_timeout: settings.statement_timeout,
+        idle_timeout: settings.idle_timeout,
+        max_lifetime: settings.max_lifetime,
+    };
+    let pool = managed::Pool::builder(manager)
+        .max_size(settings.max_size as usize)
+        .wait_timeout(Some(settings.connection_timeout))
+        .create_timeout(Some(settings.connection_timeout))
+        .runtime(Runtime::Tokio1)
+        .build()?;
+    Ok(AsyncPool(pool))
+}
+
+/// Ping a checked out connection and replace it when the server went away, as r2d2 does
+/// with `test_on_check_out`. Runs in the blocking thread of the operation.
+pub(super) fn validate(conn: &mut PooledConnection) -> Result<()> {
+    if conn.ping().is_err() {
+        let pool = PooledConnection::pool(conn).ok_or(ConnectionError::BadConnection(
+            "CONNECTION_POOL_CLOSED".to_string(),
+        ))?;
+        **conn = pool.manager().connect()?;
+    }
+    Ok(())
+}
+
+/// Check out a connection from a blocking thread, which the runtime allows to block on the
+/// async checkout.
+pub(super) fn get_blocking(pool: &AsyncPool) -> Result<PooledConnection> {
+    let mut conn = tokio::runtime::Handle::current().block_on(pool.get())?;
+    validate(&mut conn)?;
+    Ok(conn)
+}
@@ -0,0 +1,220 @@
+//! Calls of Postgres functions whose result is read back as JSON.
+//!
+//! The function runs in `FROM`, so scalar, composite and set returning functions all work:
+//! each result row is turned into JSON with `to_json` and deserialized into the caller's
+//! type. A function returning `json`/`jsonb` yields that document, one returning a row
+//! type or `SETOF` a view yields an object per row keyed by column name.
+//!
+//! The `Database` methods below set no tenant, so row level security hides the rows of
+//! tenant scoped tables from the function. Such calls run [`FunctionCall::get_result`]
+//! inside `Database::tenant_transaction` or `Database::unscoped_transaction` instead.
+//!
+//! # Example
+//!
+//! ```rust
+//! let call = FunctionCall::new("get_user_overview").arg::<BigInt, _>(user_id);
+//! let overview: UserOverview = db
+//!     .unscoped_transaction(move |conn| Ok(call.get_result(conn)?))
+//!     .await?;
+//! ```
+use super::instrumentation::CountedRunQueryDsl;
+use super::Database;
+use crate::dto::SQLJsonResult;
+use crate::utils::errors::HttpError;
+use diesel::pg::{Pg, PgConnection};
+use diesel::query_builder::{BoxedSqlQuery, SqlQuery};
+use diesel::result::Error as DieselError;
+use diesel::serialize::ToSql;
+use diesel::sql_types::HasSqlType;
+use diesel::{sql_query, QueryResult, RunQueryDsl};
+use serde::de::DeserializeOwned;
+use std::sync::Arc;
+
+type Query = BoxedSqlQuery<'static, Pg, SqlQuery>;
+type Bind = Arc<dyn Fn(Query) -> Query + Send + Sync>;
+
+/// Call of a named Postgres function with typed arguments.
+///
+/// Cheap to clone, so the same call can be retried by [`Database::call_function`].
+#[derive(Clone)]
+pub struct FunctionCall {
+    name: &'static str,
+    binds: Vec<Bind>,
+}
+
+impl FunctionCall {
+    /// Call of `name`, optionally schema qualified like `reporting.get_totals`.
+    ///
+    /// # Panics
+    ///
+    /// When `name` is not a plain (optionally qualified) identifier, it is part of the SQL
+    /// text and never comes from user input.
+    pub fn new(name: &'static str) -> Self {
+        assert!(is_function_name(name), "invalid function name {name:?}");
+        Self {
+            name,
+            binds: Vec::new(),
+        }
+    }
+
+    /// Append the next argument, bound as `ST` like [`diesel::query_builder::SqlQuery::bind`].
+    pub fn arg<ST, V>(
+        mut self,
+        value: V,
+    ) -> Self
+    where
+        Pg: HasSqlType<ST>,
+        ST: Send + 'static,
+        V: ToSql<ST, Pg> + Clone + Send + Sync + 'static,
+    {
+        self.binds.push(Arc::new(move |query: Query| {
+            query.bind::<ST, _>(value.clone())
+        }));
+        self
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// `SELECT to_json(t) AS data FROM name($1, ..) AS t`, the column `SQLJsonResult` reads.
+    pub fn sql(&self) -> String {
+        let placeholders: Vec<String> = (1..=self.binds.len())
+            .map(|index| format!("${index}"))
+            .collect();
+        format!(
+            "SELECT to_json(t) AS data FROM {}({}) AS t",
+            self.name,
+            placeholders.join(", ")
+        )
+    }
+
+    fn query(&self) -> Query {
+        self.binds
+            .iter()
+            .fold(sql_query(self.sql()).into_boxed(), |query, bind| {
+                bind(query)
+            })
+    }
+
+    /// First result row, `NotFound` when the function returned no row or NULL.
+    pub fn get_result<T: DeserializeOwned>(
+        &self,
+        conn: &mut PgConnection,
+    ) -> QueryResult<T> {
+        let row: SQLJsonResult = self.query().get_result(conn)?;
+        self.deserialize(row)
+    }
+
+    /// Every result row of a set returning function, rows that are NULL are skipped.
+    pub fn load<T: DeserializeOwned>(
+        &self,
+        conn: &mut PgConnection,
+    ) -> QueryResult<Vec<T>> {
+        let rows: Vec<SQLJsonResult> = self.query().load_counted(conn)?;
+        rows.into_iter()
+            .filter(|row| row.data.is_some())
+            .map(|row| self.deserialize(row))
+            .collect()
+    }
+
+    fn deserialize<T: DeserializeOwned>(
+        &self,
+        row: SQLJsonResult,
+    ) -> QueryResult<T> {
+        let data = row.data.ok_or(DieselError::NotFound)?;
+        serde_json::from_value(data).map_err(|err| {
+            DieselError::DeserializationError(
+                format!("result of {} does not match: {err}", self.name).into(),
+            )
+        })
+    }
+}
+
+fn is_function_name(name: &str) -> bool {
+    let mut parts = name.split('.');
+    let valid = |part: &str| {
+        let mut chars = part.chars();
+        chars
+            .next()
+            .is_some_and(|c| c.is_ascii_lowercase() || c == '_')
+            && chars.all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+    };
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(name), None, None) => valid(name),
+        (Some(schema), Some(name), None) => valid(schema) && valid(name),
+        _ => false,
+    }
+}
+
+impl Database {
+    /// Call a read-only (`STABLE`) function, on a replica when configured.
+    ///
+    /// A missing result maps to 404 and a result of the wrong shape to a logged 500, like
+    /// any other database error.
+    pub async fn query_function<T>(
+        &self,
+        call: FunctionCall,
+    ) -> Result<T, HttpError>
+    where
+        T: DeserializeOwned + Send + 'static,
+    {
+        Ok(self.execute(move |conn| Ok(call.get_result(conn)?)).await?)
+    }
+
+    /// [`Database::query_function`] for set returning functions.
+    pub async fn query_function_rows<T>(
+        &self,
+        call: FunctionCall,
+    ) -> Result<Vec<T>, HttpError>
+    where
+        T: DeserializeOwned + Send + 'static,
+    {
+        Ok(self.execute(move |conn| Ok(call.load(conn)?)).await?)
+    }
+
+    /// Call a function that writes, in a transaction on the primary.
+    pub async fn call_function<T>(
+        &self,
+        call: FunctionCall,
+    ) -> Result<T, HttpError>
+    where
+        T: DeserializeOwned + Send + 'static,
+    {
+        Ok(self
+            .transaction(move |conn| Ok(call.get_result(conn)?))
+            .await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use diesel::sql_types::{BigInt, Text};
+
+    #[test]
+    fn builds_placeholders_per_argument() {
+        assert_eq!(
+            FunctionCall::new("now_json").sql(),
+            "SELECT to_json(t) AS data FROM now_json() AS t"
+        );
+        let call = FunctionCall::new("reporting.get_totals")
+            .arg::<BigInt, _>(1_i64)
+            .arg::<Text, _>("month".to_string());
+        assert_eq!(
+            call.sql(),
+            "SELECT to_json(t) AS data FROM reporting.get_totals($1, $2) AS t"
+        );
+    }
+
+    #[test]
+    fn accepts_only_identifiers() {
+        assert!(is_function_name("get_user_overview"));
+        assert!(is_function_name("reporting.get_totals_2"));
+        assert!(!is_function_name(""));
+        assert!(!is_function_name("1st"));
+        assert!(!is_function_name("a.b.c"));
+        assert!(!is_function_name("f(); DROP TABLE users; --"));
+        assert!(!is_function_name("Users"));
+    }
+}
@@ -0,0 +1,389 @@
+//! Per-query tracing through Diesel's connection instrumentation.
+//!
+//! Every statement gets a `db.query` span with its sanitized SQL and duration. Statements
+//! slower than the configured threshold log `SLOW_QUERY` at WARN with the trace id of the
+//! request that sent them, and [`track_queries`] counts the statements of a request.
+//! Diesel does not hand the row count to instrumentation, the queries run through
+//! [`CountedRunQueryDsl`] record it on their span and in the request totals.
+use diesel::connection::{set_default_instrumentation, Instrumentation, InstrumentationEvent};
+use diesel::query_dsl::methods::ExecuteDsl;
+use diesel::query_dsl::LoadQuery;
+use diesel::{Connection, QueryResult, RunQueryDsl};
+use std::cell::{Cell, RefCell};
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::field::Empty;
+use tracing::Span;
+
+/// Longest SQL text kept in spans and logs
+const MAX_SQL_LEN: usize = 1024;
+
+static SLOW_QUERY_MS: AtomicU64 = AtomicU64::new(500);
+
+/// Queries sent on behalf of one request.
+struct RequestQueries {
+    trace_id: String,
+    count: AtomicU64,
+    rows: AtomicU64,
+}
+
+/// Statements and rows of one request so far, see [`track_queries`].
+#[derive(Clone)]
+pub struct QueryTotals(Arc<RequestQueries>);
+
+impl QueryTotals {
+    pub fn queries(&self) -> u64 {
+        self.0.count.load(Ordering::Relaxed)
+    }
+
+    pub fn rows(&self) -> u64 {
+        self.0.rows.load(Ordering::Relaxed)
+    }
+}
+
+tokio::task_local! {
+    static REQUEST: Arc<RequestQueries>;
+}
+
+thread_local! {
+    // Set while a blocking database call runs for a request
+    static BLOCKING_REQUEST: RefCell<Option<Arc<RequestQueries>>> = const { RefCell::new(None) };
+    // Set while a counted query runs, its span waits there for the row count
+    static COUNTING: Cell<bool> = const { Cell::new(false) };
+    static COUNTED_QUERY: RefCell<Option<Span>> = const { RefCell::new(None) };
+}
+
+/// Instrument every connection established from now on, including pooled ones.
+///
+/// Must run before the pools are built, since connections keep the instrumentation they
+/// were opened with.
+pub fn install(slow_query: Duration) -> QueryResult<()> {
+    SLOW_QUERY_MS.store(slow_query.as_millis() as u64, Ordering::Relaxed);
+    set_default_instrumentation(|| Some(Box::new(QueryTracer::default())))
+}
+
+/// Run `future` as the request `trace_id`, returning its output and the totals of the
+/// queries it sent.
+///
+/// Blocking work started within a [`QueryScope`] keeps adding to the totals after the
+/// future returned, e.g. the query of a streamed export.
+pub async fn track_queries<F: Future>(
+    trace_id: impl Into<String>,
+    future: F,
+) -> (F::Output, QueryTotals) {
+    let request = Arc::new(RequestQueries {
+        trace_id: trace_id.into(),
+        count: AtomicU64::new(0),
+        rows: AtomicU64::new(0),
+    });
+    let output = REQUEST.scope(request.clone(), future).await;
+    (output, QueryTotals(request))
+}
+
+/// Request context of the calling task, carried into `spawn_blocking`.
+///
+/// [`crate::database::Database`] calls carry it themselves, code taking a connection with
+/// `get_connection` captures it before spawning.
+pub struct QueryScope {
+    span: Span,
+    request: Option<Arc<RequestQueries>>,
+}
+
+impl QueryScope {
+    /// Capture the current span and request, call before leaving the async task.
+    pub fn capture() -> Self {
+        Self {
+            span: Span::current(),
+            request: REQUEST.try_with(Arc::clone).ok(),
+        }
+    }
+
+    /// Run `operation` on the blocking thread within the captured context.
+    pub fn run<T>(
+        self,
+        operation: impl FnOnce() -> T,
+    ) -> T {
+        struct Reset;
+        impl Drop for Reset {
+            fn drop(&mut self) {
+                BLOCKING_REQUEST.with(|request| request.borrow_mut().take());
+            }
+        }
+
+        let _entered = self.span.enter();
+        BLOCKING_REQUEST.with(|request| *request.borrow_mut() = self.request.clone());
+        let _reset = Reset;
+        operation()
+    }
+}
+
+fn current_request() -> Option<Arc<RequestQueries>> {
+    REQUEST
+        .try_with(Arc::clone)
+        .ok()
+        .or_else(|| BLOCKING_REQUEST.with(|request| request.borrow().clone()))
+}
+
+/// [`RunQueryDsl`] calls that record the rows they changed or loaded, as `rows` on the
+/// `db.query` span and in the request totals of [`track_queries`].
+pub trait CountedRunQueryDsl<Conn>: RunQueryDsl<Conn> + Sized {
+    /// [`RunQueryDsl::execute`], counting the affected rows.
+    fn execute_counted(
+        self,
+        conn: &mut Conn,
+    ) -> QueryResult<usize>
+    where
+        Conn: Connection,
+        Self: ExecuteDsl<Conn>,
+    {
+        counted(|| RunQueryDsl::execute(self, conn), |rows| *rows)
+    }
+
+    /// [`RunQueryDsl::load`], counting the loaded rows.
+    fn load_counted<'query, U>(
+        self,
+        conn: &mut Conn,
+    ) -> QueryResult<Vec<U>>
+    where
+        Self: LoadQuery<'query, Conn, U>,
+    {
+        counted(|| RunQueryDsl::load(self, conn), Vec::len)
+    }
+
+    /// [`RunQueryDsl::load_iter`], counting the rows once the iterator is dropped.
+    fn load_iter_counted<'conn, 'query: 'conn, U, B>(
+        self,
+        conn: &'conn mut Conn,
+    ) -> QueryResult<CountedRows<<Self as LoadQuery<'query, Conn, U, B>>::RowIter<'conn>>>
+    where
+        U: 'conn,
+        Self: LoadQuery<'query, Conn, U, B> + 'conn,
+    {
+        let (result, span) = run_counted(|| RunQueryDsl::load_iter(self, conn));
+        result.map(|rows| CountedRows {
+            rows,
+            count: 0,
+            span,
+            request: current_request(),
+        })
+    }
+}
+
+impl<T: RunQueryDsl<Conn>, Conn> CountedRunQueryDsl<Conn> for T {}
+
+/// Rows of [`CountedRunQueryDsl::load_iter_counted`], recording how many were read.
+pub struct CountedRows<I> {
+    rows: I,
+    count: u64,
+    span: Option<Span>,
+    request: Option<Arc<RequestQueries>>,
+}
+
+impl<I: Iterator> Iterator for CountedRows<I> {
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let row = self.rows.next();
+        if row.is_some() {
+            self.count += 1;
+        }
+        row
+    }
+}
+
+impl<I> Drop for CountedRows<I> {
+    fn drop(&mut self) {
+        record_rows(self.span.take(), self.request.as_deref(), self.count);
+    }
+}
+
+// Run a query with the span of its statement kept for the row count
+fn run_counted<T>(query: impl FnOnce() -> QueryResult<T>) -> (QueryResult<T>, Option<Span>) {
+    COUNTING.with(|counting| counting.set(true));
+    let result = query();
+    COUNTING.with(|counting| counting.set(false));
+    let span = COUNTED_QUERY.with(|span| span.borrow_mut().take());
+    (result, span)
+}
+
+fn counted<T>(
+    query: impl FnOnce() -> QueryResult<T>,
+    rows: impl FnOnce(&T) -> usize,
+) -> QueryResult<T> {
+    let (result, span) = run_counted(query);
+    if let Ok(value) = &result {
+        record_rows(span, current_request().as_deref(), rows(value) as u64);
+    }
+    result
+}
+
+fn record_rows(
+    span: Option<Span>,
+    request: Option<&RequestQueries>,
+    rows: u64,
+) {
+    if let Some(span) = span {
+        span.record("rows", rows);
+    }
+    if let Some(request) = request {
+        request.rows.fetch_add(rows, Ordering::Relaxed);
+    }
+}
+
+struct RunningQuery {
+    span: Span,
+    sql: String,
+    started: Instant,
+}
+
+#[derive(Default)]
+struct QueryTracer {
+    running: Option<RunningQuery>,
+}
+
+impl Instrumentation for QueryTracer {
+    fn on_connection_event(
+        &mut self,
+        event: InstrumentationEvent<'_>,
+    ) {
+        match event {
+            InstrumentationEvent::StartQuery { query, .. } => {
+                let sql = sanitize_sql(&query.to_string());
+                let span = tracing::debug_span!(
+                    "db.query",
+                    sql = %sql,
+                    duration_ms = Empty,
+                    rows = Empty
+                );
+                self.running = Some(RunningQuery {
+                    span,
+                    sql,
+                    started: Instant::now(),
+                });
+            }
+            InstrumentationEvent::FinishQuery { error, .. } => {
+                let Some(query) = self.running.take() else {
+                    return;
+                };
+                let elapsed = query.started.elapsed();
+                let duration_ms = elapsed.as_secs_f64() * 1000.0;
+                query.span.record("duration_ms", duration_ms);
+
+                let request = current_request();
+                if let Some(request) = &request {
+                    request.count.fetch_add(1, Ordering::Relaxed);
+                }
+                let trace_id = request.as_ref().map(|request| request.trace_id.as_str());
+                if elapsed.as_millis() as u64 >= SLOW_QUERY_MS.load(Ordering::Relaxed) {
+                    tracing::warn!(
+                        parent: &query.span,
+                        trace_id,
+                        duration_ms,
+                        sql = %query.sql,
+                        "SLOW_QUERY"
+                    );
+                } else if let Some(error) = error {
+                    tracing::debug!(parent: &query.span, trace_id, error = %error, "QUERY_FAILED");
+                } else {
+                    tracing::debug!(parent: &query.span, trace_id, "QUERY_FINISHED");
+                }
+                // Kept open until the counted call has its rows
+                if COUNTING.with(Cell::get) {
+                    COUNTED_QUERY.with(|span| *span.borrow_mut() = Some(query.span));
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Strip bind values and literals from a statement so logs never carry user data.
+pub fn sanitize_sql(query: &str) -> String {
+    // Diesel appends the bind values as `-- binds: [..]`
+    let query = query.split(" -- binds:").next().unwrap_or_default();
+
+    let mut sanitized = String::with_capacity(query.len().min(MAX_SQL_LEN));
+    let mut chars = query.chars().peekable();
+    let mut space = false;
+    while let Some(c) = chars.next() {
+        if c.is_whitespace() {
+            space = !sanitized.is_empty();
+            continue;
+        }
+        if space {
+            sanitized.push(' ');
+            space = false;
+        }
+        if c == '\'' {
+            // Skip the literal, a doubled quote is an escaped one
+            while let Some(c) = chars.next() {
+                if c == '\'' && chars.next_if_eq(&'\'').is_none() {
+                    break;
+                }
+            }
+            sanitized.push_str("'?'");
+        } else {
+            sanitized.push(c);
+        }
+        if sanitized.len() >= MAX_SQL_LEN {
+            sanitized.push_str("...");
+            break;
+        }
+    }
+    sanitized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sanitize_drops_binds_literals_and_whitespace() {
+        assert_eq!(
+            sanitize_sql(
+                "SELECT \"users\".\"id\" FROM \"users\"\n  WHERE \"users\".\"email\" = $1 -- binds: [\"a@b.c\"]"
+            ),
+            "SELECT \"users\".\"id\" FROM \"users\" WHERE \"users\".\"email\" = $1"
+        );
+        assert_eq!(
+            sanitize_sql("SET LOCAL app.tenant_id = '42'"),
+            "SET LOCAL app.tenant_id = '?'"
+        );
+        assert_eq!(sanitize_sql("SELECT 'it''s', 1"), "SELECT '?', 1");
+    }
+
+    #[test]
+    fn counts_the_rows_of_the_request() {
+        let request = Arc::new(RequestQueries {
+            trace_id: "trace".to_string(),
+            count: AtomicU64::new(0),
+            rows: AtomicU64::new(0),
+        });
+        let scope = QueryScope {
+            span: Span::none(),
+            request: Some(request.clone()),
+        };
+        scope.run(|| {
+            counted(|| Ok(vec![1, 2, 3]), Vec::len).unwrap();
+            counted(|| Ok(2), |rows| *rows).unwrap();
+            counted(
+                || Err::<usize, _>(diesel::result::Error::NotFound),
+                |rows| *rows,
+            )
+            .unwrap_err();
+        });
+        // Outside the scope the rows belong to no request
+        counted(|| Ok(4), |rows| *rows).unwrap();
+        assert_eq!(QueryTotals(request).rows(), 5);
+    }
+
+    #[test]
+    fn sanitize_truncates_long_statements() {
+        let sql = format!("SELECT {}", "x, ".repeat(1000));
+        let sanitized = sanitize_sql(&sql);
+        assert!(sanitized.ends_with("..."));
+        assert!(sanitized.len() < MAX_SQL_LEN + 8);
+    }
+}
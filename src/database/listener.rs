@@ -0,0 +1,375 @@
+//! Postgres LISTEN/NOTIFY subscriber on a dedicated connection.
+//!
+//! Diesel connections never surface notifications, so the listener keeps its own
+//! `tokio-postgres` connection outside the pools. Payloads are dispatched to the handlers
+//! registered for their channel, in the order they were sent. When the connection drops
+//! it reconnects with a capped exponential backoff and LISTENs again; notifications sent
+//! in between are lost, so the keys announced to caches registered with
+//! [`Listener::invalidate`] are dropped after every reconnect.
+//!
+//! The url is parsed by `tokio-postgres`, which connects over TLS for `sslmode=prefer` and
+//! `require` and rejects the other modes when the listener is spawned.
+//!
+//! # Example
+//!
+//! ```rust
+//! Listener::new(&config.database_url)
+//!     .invalidate("cache_invalidation", cache.clone(), &["tenant:*"])
+//!     .on("user_events", |notification| async move {
+//!         tracing::info!(payload = %notification.payload, "USER_EVENT");
+//!     })
+//!     .spawn()?;
+//! ```
+mod tls;
+
+use crate::utils::cache::Cache;
+use anyhow::Result;
+use futures_util::future::BoxFuture;
+use futures_util::{stream, StreamExt};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tls::MakeTlsConnector;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio_postgres::{AsyncMessage, Config};
+
+/// Longest wait between two reconnect attempts
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+/// A silently dropped connection is noticed by the next ping
+const PING_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Notification received on a channel.
+#[derive(Clone, Debug)]
+pub struct Notification {
+    pub channel: String,
+    pub payload: String,
+    /// Backend process that sent it
+    pub process_id: i32,
+}
+
+type Handler = Arc<dyn Fn(Notification) -> BoxFuture<'static, ()> + Send + Sync>;
+
+/// Builder of the LISTEN task, see the module docs.
+pub struct Listener {
+    database_url: String,
+    handlers: HashMap<String, Vec<Handler>>,
+    /// Caches with the key patterns their channels announce
+    caches: Vec<(Cache, Vec<String>)>,
+}
+
+impl Listener {
+    pub fn new(database_url: &str) -> Self {
+        Self {
+            database_url: database_url.to_string(),
+            handlers: HashMap::new(),
+            caches: Vec::new(),
+        }
+    }
+
+    /// Run `handler` for every notification on `channel`.
+    ///
+    /// Handlers run one at a time on the listener task, so slow work should be spawned.
+    ///
+    /// # Panics
+    ///
+    /// When `channel` is not a lowercase identifier, it is sent as part of `LISTEN`.
+    pub fn on<F, Fut>(
+        mut self,
+        channel: &str,
+        handler: F,
+    ) -> Self
+    where
+        F: Fn(Notification) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        assert!(is_channel_name(channel), "invalid channel name {channel:?}");
+        let handler: Handler = Arc::new(move |notification| Box::pin(handler(notification)));
+        self.handlers
+            .entry(channel.to_string())
+            .or_default()
+            .push(handler);
+        self
+    }
+
+    /// Invalidate `cache` entries named by the payloads on `channel`.
+    ///
+    /// The payload is a key (`tenant:42`), a prefix ending with `*` (`tenant:*`), or empty or
+    /// `*` to clear the whole cache. `announced` lists the patterns of the keys the channel
+    /// names, those entries are dropped after a reconnect while the others, such as pending
+    /// OIDC logins, are kept.
+    pub fn invalidate(
+        mut self,
+        channel: &str,
+        cache: Cache,
+        announced: &[&str],
+    ) -> Self {
+        let announced = announced
+            .iter()
+            .map(|pattern| pattern.to_string())
+            .collect();
+        self.caches.push((cache.clone(), announced));
+        self.on(channel, move |notification| {
+            let cache = cache.clone();
+            async move {
+                let removed = cache.invalidate(&notification.payload).await;
+                tracing::debug!(
+                    channel = %notification.channel,
+                    payload = %notification.payload,
+                    removed,
+                    "CACHE_INVALIDATED"
+                );
+            }
+        })
+    }
+
+    /// Channels with at least one handler.
+    pub fn channels(&self) -> Vec<&str> {
+        let mut channels: Vec<&str> = self.handlers.keys().map(String::as_str).collect();
+        channels.sort_unstable();
+        channels
+    }
+
+    /// Start listening in the background until the runtime shuts down.
+    ///
+    /// Fails when the url is not one `tokio-postgres` can connect with, rather than retrying
+    /// it forever.
+    pub fn spawn(self) -> Result<JoinHandle<()>> {
+        let config: Config = self.database_url.parse()?;
+        let tls = MakeTlsConnector::new()?;
+        Ok(tokio::spawn(async move {
+            let mut attempt = 0;
+            let mut connected_before = false;
+            loop {
+                let result = self
+                    .listen(&config, tls.clone(), || {
+                        attempt = 0;
+                        let reconnected = connected_before;
+                        connected_before = true;
+                        reconnected
+                    })
+                    .await;
+                let delay = reconnect_backoff(attempt);
+                attempt += 1;
+                match result {
+                    Ok(()) => tracing::warn!(?delay, "LISTENER_DISCONNECTED"),
+                    Err(err) => tracing::warn!(error = ?err, ?delay, "LISTENER_DISCONNECTED"),
+                }
+                tokio::time::sleep(delay).await;
+            }
+        }))
+    }
+
+    /// Connect, LISTEN and dispatch until the connection ends.
+    ///
+    /// `on_connected` runs once LISTEN succeeded and tells whether this is a reconnect.
+    async fn listen(
+        &self,
+        config: &Config,
+        tls: MakeTlsConnector,
+        on_connected: impl FnOnce() -> bool,
+    ) -> Result<(), tokio_postgres::Error> {
+        let (client, mut connection) = config.connect(tls).await?;
+
+        // The connection yields notifications only while it is polled, next to the client
+        let (sender, mut notifications) = mpsc::unbounded_channel();
+        let driver = tokio::spawn(async move {
+            let mut messages = stream::poll_fn(move |cx| connection.poll_message(cx));
+            while let Some(message) = messages.next().await {
+                match message? {
+                    AsyncMessage::Notification(notification) => {
+                        let _ = sender.send(Notification {
+                            channel: notification.channel().to_string(),
+                            payload: notification.payload().to_string(),
+                            process_id: notification.process_id(),
+                        });
+                    }
+                    AsyncMessage::Notice(notice) => {
+                        tracing::debug!(notice = %notice, "LISTENER_NOTICE");
+                    }
+                    _ => {}
+                }
+            }
+            Ok::<_, tokio_postgres::Error>(())
+        });
+
+        let channels = self.channels();
+        let statements: Vec<String> = channels
+            .iter()
+            .map(|channel| format!("LISTEN {channel};"))
+            .collect();
+        client.batch_execute(&statements.join(" ")).await?;
+        tracing::info!(?channels, "LISTENER_CONNECTED");
+        if on_connected() {
+            self.drop_announced().await;
+        }
+
+        let mut ping = tokio::time::interval(PING_INTERVAL);
+        ping.tick().await;
+        loop {
+            tokio::select! {
+                notification = notifications.recv() => match notification {
+                    Some(notification) => self.dispatch(notification).await,
+                    None => break,
+                },
+                _ = ping.tick() => {
+                    if let Err(err) = client.simple_query("SELECT 1").await {
+                        driver.abort();
+                        return Err(err);
+                    }
+                }
+            }
+        }
+        match driver.await {
+            Ok(result) => result,
+            Err(_) => Ok(()),
+        }
+    }
+
+    // Whatever changed while disconnected was never announced
+    async fn drop_announced(&self) {
+        for (cache, announced) in &self.caches {
+            for pattern in announced {
+                let removed = cache.invalidate(pattern).await;
+                tracing::debug!(pattern = %pattern, removed, "CACHE_INVALIDATED");
+            }
+        }
+    }
+
+    async fn dispatch(
+        &self,
+        notification: Notification,
+    ) {
+        tracing::debug!(
+            channel = %notification.channel,
+            process_id = notification.process_id,
+            "NOTIFICATION_RECEIVED"
+        );
+        for handler in self
+            .handlers
+            .get(&notification.channel)
+            .into_iter()
+            .flatten()
+        {
+            handler(notification.clone()).await;
+        }
+    }
+}
+
+fn is_channel_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_lowercase() || c == '_')
+        && chars.all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+}
+
+/// 1s doubling on each failed attempt, capped at [`MAX_BACKOFF`].
+fn reconnect_backoff(attempt: u32) -> Duration {
+    Duration::from_secs(1 << attempt.min(6)).min(MAX_BACKOFF)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn notification(
+        channel: &str,
+        payload: &str,
+    ) -> Notification {
+        Notification {
+            channel: channel.to_string(),
+            payload: payload.to_string(),
+            process_id: 1,
+        }
+    }
+
+    #[tokio::test]
+    async fn dispatches_to_the_channel_handlers() {
+        let cache = Cache::new(Duration::from_secs(60));
+        for key in ["tenant:1", "tenant:acme", "OIDC_STATE:1"] {
+            cache.set(key.to_string(), json!(key)).await;
+        }
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let listener = Listener::new("postgres://localhost/app")
+            .invalidate("cache_invalidation", cache.clone(), &["tenant:*"])
+            .on("user_events", move |_| {
+                counter.fetch_add(1, Ordering::Relaxed);
+                async {}
+            });
+        assert_eq!(
+            listener.channels(),
+            vec!["cache_invalidation", "user_events"]
+        );
+
+        listener
+            .dispatch(notification("cache_invalidation", "tenant:1"))
+            .await;
+        assert!(cache.get("tenant:1").await.is_none());
+        assert!(cache.get("tenant:acme").await.is_some());
+
+        listener.dispatch(notification("user_events", "{}")).await;
+        listener.dispatch(notification("other", "")).await;
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+
+        listener
+            .dispatch(notification("cache_invalidation", ""))
+            .await;
+        assert!(cache.get("OIDC_STATE:1").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn drops_only_announced_keys_after_reconnect() {
+        let cache = Cache::new(Duration::from_secs(60));
+        for key in [
+            "tenant:1",
+            "tenant:acme",
+            "OIDC_STATE:1",
+            "OIDC_METADATA:google",
+        ] {
+            cache.set(key.to_string(), json!(key)).await;
+        }
+        let listener = Listener::new("postgres://localhost/app").invalidate(
+            "cache_invalidation",
+            cache.clone(),
+            &["tenant:*"],
+        );
+
+        listener.drop_announced().await;
+        assert!(cache.get("tenant:1").await.is_none());
+        assert!(cache.get("tenant:acme").await.is_none());
+        assert!(cache.get("OIDC_STATE:1").await.is_some());
+        assert!(cache.get("OIDC_METADATA:google").await.is_some());
+    }
+
+    #[tokio::test]
+    async fn rejects_urls_it_cannot_connect_with() {
+        let listener = Listener::new("postgres://localhost/app?sslmode=verify-full").invalidate(
+            "cache_invalidation",
+            Cache::new(Duration::from_secs(60)),
+            &[],
+        );
+        assert!(listener.spawn().is_err());
+
+        let listener = Listener::new("postgres://localhost/app?sslmode=require").invalidate(
+            "cache_invalidation",
+            Cache::new(Duration::from_secs(60)),
+            &[],
+        );
+        listener.spawn().unwrap().abort();
+    }
+
+    #[test]
+    fn validates_channels_and_caps_backoff() {
+        assert!(is_channel_name("cache_invalidation"));
+        assert!(!is_channel_name("cache-invalidation"));
+        assert!(!is_channel_name("x; DROP TABLE users"));
+        assert_eq!(reconnect_backoff(0), Duration::from_secs(1));
+        assert_eq!(reconnect_backoff(3), Duration::from_secs(8));
+        assert_eq!(reconnect_backoff(20), MAX_BACKOFF);
+    }
+}
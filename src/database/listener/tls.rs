@@ -0,0 +1,122 @@
+//! TLS of the listener connection over native-tls.
+//!
+//! `tokio-postgres` only handles `sslmode=disable`, `prefer` and `require`, and in the last
+//! two libpq, which the pools connect with, encrypts without verifying the server
+//! certificate. The connector does the same so both accept the same urls.
+use futures_util::future::BoxFuture;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio_postgres::tls::{self, ChannelBinding};
+
+#[derive(Clone)]
+pub(super) struct MakeTlsConnector(tokio_native_tls::TlsConnector);
+
+impl MakeTlsConnector {
+    pub(super) fn new() -> Result<Self, native_tls::Error> {
+        let connector = native_tls::TlsConnector::builder()
+            .danger_accept_invalid_certs(true)
+            .danger_accept_invalid_hostnames(true)
+            .build()?;
+        Ok(Self(connector.into()))
+    }
+}
+
+impl<S> tls::MakeTlsConnect<S> for MakeTlsConnector
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    type Stream = TlsStream<S>;
+    type TlsConnect = TlsConnector;
+    type Error = native_tls::Error;
+
+    fn make_tls_connect(
+        &mut self,
+        domain: &str,
+    ) -> Result<TlsConnector, native_tls::Error> {
+        Ok(TlsConnector {
+            connector: self.0.clone(),
+            domain: domain.to_string(),
+        })
+    }
+}
+
+pub(super) struct TlsConnector {
+    connector: tokio_native_tls::TlsConnector,
+    domain: String,
+}
+
+impl<S> tls::TlsConnect<S> for TlsConnector
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    type Stream = TlsStream<S>;
+    type Error = native_tls::Error;
+    type Future = BoxFuture<'static, Result<TlsStream<S>, native_tls::Error>>;
+
+    fn connect(
+        self,
+        stream: S,
+    ) -> Self::Future {
+        Box::pin(async move {
+            let stream = self.connector.connect(&self.domain, stream).await?;
+            Ok(TlsStream(stream))
+        })
+    }
+}
+
+pub(super) struct TlsStream<S>(tokio_native_tls::TlsStream<S>);
+
+impl<S> AsyncRead for TlsStream<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_read(cx, buf)
+    }
+}
+
+impl<S> AsyncWrite for TlsStream<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_flush(cx)
+    }
+
+    fn poll_shutdown(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_shutdown(cx)
+    }
+}
+
+impl<S> tls::TlsStream for TlsStream<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    // Lets SCRAM bind the authentication to this TLS session
+    fn channel_binding(&self) -> ChannelBinding {
+        match self.0.get_ref().tls_server_end_point() {
+            Ok(Some(hash)) => ChannelBinding::tls_server_end_point(hash),
+            _ => ChannelBinding::none(),
+        }
+    }
+}
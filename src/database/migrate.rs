@@ -0,0 +1,188 @@
+//! Migrations of `migrations/`, embedded in the binary at build time.
+use super::Database;
+use crate::config::MigrateCommand;
+use crate::constant;
+use anyhow::{anyhow, Result};
+use diesel::migration::MigrationSource;
+use diesel::pg::{Pg, PgConnection};
+use diesel::sql_types::BigInt;
+use diesel::{sql_query, Connection, RunQueryDsl};
+use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
+
+pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations");
+
+/// Row of `migrate status`.
+#[derive(Debug)]
+pub struct MigrationStatus {
+    pub name: String,
+    pub applied: bool,
+}
+
+// Run `operation` holding the migration advisory lock. The lock belongs to the session,
+// it is released before the connection closes whatever the outcome.
+fn with_migration_lock<T>(
+    conn: &mut PgConnection,
+    operation: impl FnOnce(&mut PgConnection) -> Result<T>,
+) -> Result<T> {
+    sql_query("SELECT pg_advisory_lock($1)")
+        .bind::<BigInt, _>(constant::MIGRATION_LOCK_KEY)
+        .execute(conn)?;
+    let result = operation(conn);
+    let unlocked = sql_query("SELECT pg_advisory_unlock($1)")
+        .bind::<BigInt, _>(constant::MIGRATION_LOCK_KEY)
+        .execute(conn);
+    let value = result?;
+    unlocked?;
+    Ok(value)
+}
+
+impl Database {
+    // Connection of the migration commands, outside the pool. The pool connections carry
+    // the request statement timeout, which would cancel the wait for the lock of another
+    // instance running migrations and any long DDL.
+    fn migration_connection(&self) -> Result<PgConnection> {
+        let mut conn = PgConnection::establish(&self.database_url)?;
+        sql_query("SET statement_timeout = 0").execute(&mut conn)?;
+        Ok(conn)
+    }
+
+    /// Apply the pending migrations, returns their versions.
+    ///
+    /// Instances starting at the same time wait on an advisory lock, the first one runs
+    /// the migrations and the others find nothing pending.
+    pub async fn migrate_up(&self) -> Result<Vec<String>> {
+        let db = self.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut conn = db.migration_connection()?;
+            with_migration_lock(&mut conn, |conn| {
+                let applied = conn
+                    .run_pending_migrations(MIGRATIONS)
+                    .map_err(|err| anyhow!("MIGRATION_FAILED: {err}"))?;
+                Ok(applied.iter().map(ToString::to_string).collect())
+            })
+        })
+        .await?
+    }
+
+    /// Revert the latest `steps` applied migrations, returns their versions.
+    pub async fn migrate_down(
+        &self,
+        steps: usize,
+    ) -> Result<Vec<String>> {
+        let db = self.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut conn = db.migration_connection()?;
+            with_migration_lock(&mut conn, |conn| {
+                let applied = conn
+                    .applied_migrations()
+                    .map_err(|err| anyhow!("MIGRATION_STATUS_FAILED: {err}"))?;
+                let mut reverted = Vec::new();
+                for _ in 0..steps.min(applied.len()) {
+                    let version = conn
+                        .revert_last_migration(MIGRATIONS)
+                        .map_err(|err| anyhow!("MIGRATION_REVERT_FAILED: {err}"))?;
+                    reverted.push(version.to_string());
+                }
+                Ok(reverted)
+            })
+        })
+        .await?
+    }
+
+    /// Every embedded migration in order, with whether it is applied.
+    pub async fn migration_status(&self) -> Result<Vec<MigrationStatus>> {
+        let db = self.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut conn = db.migration_connection()?;
+            let applied = conn
+                .applied_migrations()
+                .map_err(|err| anyhow!("MIGRATION_STATUS_FAILED: {err}"))?;
+            let migrations = MigrationSource::<Pg>::migrations(&MIGRATIONS)
+                .map_err(|err| anyhow!("MIGRATION_STATUS_FAILED: {err}"))?;
+            Ok(migrations
+                .iter()
+                .map(|migration| MigrationStatus {
+                    name: migration.name().to_string(),
+                    applied: applied.contains(&migration.name().version()),
+                })
+                .collect())
+        })
+        .await?
+    }
+
+    /// Run a `migrate` subcommand, printing what it did.
+    pub async fn run_migrate_command(
+        &self,
+        command: MigrateCommand,
+    ) -> Result<()> {
+        match command {
+            MigrateCommand::Up => {
+                let applied = self.migrate_up().await?;
+                if applied.is_empty() {
+                    println!("No pending migrations");
+                }
+                for version in applied {
+                    println!("Applied {version}");
+                }
+            }
+            MigrateCommand::Down { steps } => {
+                let reverted = self.migrate_down(steps).await?;
+                if reverted.is_empty() {
+                    println!("No applied migrations");
+                }
+                for version in reverted {
+                    println!("Reverted {version}");
+                }
+            }
+            MigrateCommand::Status => {
+                for migration in self.migration_status().await? {
+                    let state = if migration.applied {
+                        "applied"
+                    } else {
+                        "pending"
+                    };
+                    println!("[{state:>7}] {}", migration.name);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn embeds_migrations_in_order() {
+        let migrations = MigrationSource::<Pg>::migrations(&MIGRATIONS).unwrap();
+        assert!(!migrations.is_empty());
+        let names: Vec<String> = migrations.iter().map(|m| m.name().to_string()).collect();
+        let mut sorted = names.clone();
+        sorted.sort();
+        assert_eq!(names, sorted);
+    }
+
+    #[derive(diesel::QueryableByName)]
+    struct Setting {
+        #[diesel(sql_type = diesel::sql_types::Text)]
+        statement_timeout: String,
+    }
+
+    #[test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    fn migrates_without_the_pool_statement_timeout() {
+        let url = std::env::var("TEST_DATABASE_URL").expect("TEST_DATABASE_URL is not set");
+        let settings = crate::database::PoolSettings {
+            min_idle: 1,
+            statement_timeout: Some(std::time::Duration::from_secs(1)),
+            ..Default::default()
+        };
+        let db = Database::connect(&url, settings).unwrap();
+        let mut conn = db.migration_connection().unwrap();
+        let setting = sql_query("SHOW statement_timeout")
+            .get_result::<Setting>(&mut conn)
+            .unwrap();
+        assert_eq!(setting.statement_timeout, "0");
+    }
+}
@@ -0,0 +1,209 @@
+//! Read replicas of [`Database`].
+//!
+//! [`Database::execute`] sends its read-only operations to the healthy replicas in turn,
+//! falling back to the primary when none is. A replica is healthy once a check found it
+//! reachable and no further behind the primary than the configured lag.
+use super::{get_blocking, ConnectionPool, Database, PoolSettings};
+use diesel::sql_types::{Double, Nullable};
+use diesel::{sql_query, QueryableByName, RunQueryDsl};
+use futures_util::future::join_all;
+use std::future::Future;
+use std::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+// Values of Replica::state, unchecked replicas do not serve reads either
+const STATE_UNCHECKED: u8 = 0;
+const STATE_UP: u8 = 1;
+const STATE_DOWN: u8 = 2;
+
+// Short so a replica that went away fails over to the primary quickly
+const REPLICA_CONNECTION_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Seconds of replay lag, 0 when everything received is replayed, an idle primary
+// otherwise looks like growing lag. NULL before the replica replayed anything, and when
+// no WAL receiver runs: a replica cut off from the primary has replayed all it received
+// and would look current forever. pg_stat_wal_receiver has a row only while one runs,
+// whatever the privileges of the user.
+const REPLICA_LAG_QUERY: &str = "SELECT CASE \
+    WHEN NOT pg_is_in_recovery() THEN 0 \
+    WHEN NOT EXISTS (SELECT 1 FROM pg_stat_wal_receiver) THEN NULL \
+    WHEN pg_last_wal_receive_lsn() = pg_last_wal_replay_lsn() THEN 0 \
+    ELSE EXTRACT(EPOCH FROM now() - pg_last_xact_replay_timestamp()) \
+    END::FLOAT8 AS lag";
+
+tokio::task_local! {
+    static READ_PRIMARY: ();
+}
+
+/// Run `future` with every [`Database::execute`] call inside it reading from the primary.
+///
+/// Used for requests that must see their own writes, which replicas may not have replayed yet.
+pub async fn read_primary<F: Future>(future: F) -> F::Output {
+    READ_PRIMARY.scope((), future).await
+}
+
+fn reads_primary() -> bool {
+    READ_PRIMARY.try_with(|_| ()).is_ok()
+}
+
+// Unchecked so an unreachable replica does not hold up startup
+#[cfg(not(feature = "async-pool"))]
+fn build_replica_pool(
+    url: &str,
+    settings: &PoolSettings,
+) -> ConnectionPool {
+    settings
+        .pool_builder()
+        .build_unchecked(diesel::r2d2::ConnectionManager::new(url))
+}
+
+// Deadpool connects on first use anyway, building fails only without a runtime
+#[cfg(feature = "async-pool")]
+fn build_replica_pool(
+    url: &str,
+    settings: &PoolSettings,
+) -> ConnectionPool {
+    super::async_pool::build(url, settings).expect("DATABASE_REPLICA_POOL_FAILED")
+}
+
+#[derive(QueryableByName)]
+struct ReplicaLag {
+    #[diesel(sql_type = Nullable<Double>)]
+    lag: Option<f64>,
+}
+
+#[derive(Debug)]
+pub(super) struct Replica {
+    // Host part of the url, credentials stay out of the logs
+    name: String,
+    pub(super) pool: ConnectionPool,
+    state: AtomicU8,
+}
+
+impl Replica {
+    fn new(
+        url: &str,
+        settings: &PoolSettings,
+    ) -> Self {
+        let name = url
+            .rsplit('@')
+            .next()
+            .and_then(|host| host.split('/').next())
+            .unwrap_or_default()
+            .trim_start_matches("postgresql://")
+            .trim_start_matches("postgres://")
+            .to_string();
+        let settings = PoolSettings {
+            connection_timeout: settings.connection_timeout.min(REPLICA_CONNECTION_TIMEOUT),
+            ..*settings
+        };
+        Self {
+            name,
+            pool: build_replica_pool(url, &settings),
+            state: AtomicU8::new(STATE_UNCHECKED),
+        }
+    }
+
+    /// Take the replica out of rotation until the next successful check.
+    pub(super) fn mark_down(
+        &self,
+        reason: &str,
+    ) {
+        if self.state.swap(STATE_DOWN, Ordering::Relaxed) != STATE_DOWN {
+            tracing::warn!(replica = %self.name, reason, "DATABASE_REPLICA_DOWN");
+        }
+    }
+
+    fn mark_up(&self) {
+        if self.state.swap(STATE_UP, Ordering::Relaxed) != STATE_UP {
+            tracing::info!(replica = %self.name, "DATABASE_REPLICA_UP");
+        }
+    }
+
+    fn lag(&self) -> anyhow::Result<Option<f64>> {
+        let mut conn = get_blocking(&self.pool)?;
+        let row = sql_query(REPLICA_LAG_QUERY).get_result::<ReplicaLag>(&mut *conn)?;
+        Ok(row.lag)
+    }
+}
+
+#[derive(Debug, Default)]
+pub(super) struct Replicas {
+    list: Vec<Arc<Replica>>,
+    next: AtomicUsize,
+    max_lag: Duration,
+}
+
+impl Replicas {
+    /// Next healthy replica in round-robin order, `None` to read from the primary.
+    pub(super) fn pick(&self) -> Option<Arc<Replica>> {
+        if self.list.is_empty() || reads_primary() {
+            return None;
+        }
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        (0..self.list.len())
+            .map(|offset| &self.list[(start + offset) % self.list.len()])
+            .find(|replica| replica.state.load(Ordering::Relaxed) == STATE_UP)
+            .cloned()
+    }
+}
+
+impl Database {
+    /// Route [`Database::execute`] to these replicas, see [`Database::check_replicas`].
+    pub fn with_replicas<S: AsRef<str>>(
+        mut self,
+        urls: &[S],
+        max_lag: Duration,
+    ) -> Self {
+        self.replicas = Arc::new(Replicas {
+            list: urls
+                .iter()
+                .map(|url| Arc::new(Replica::new(url.as_ref(), &self.settings)))
+                .collect(),
+            next: AtomicUsize::new(0),
+            max_lag,
+        });
+        self
+    }
+
+    pub fn has_replicas(&self) -> bool {
+        !self.replicas.list.is_empty()
+    }
+
+    /// Check the reachability and lag of every replica, returns how many are healthy.
+    pub async fn check_replicas(&self) -> usize {
+        let max_lag = self.replicas.max_lag.as_secs_f64();
+        let checks = self
+            .replicas
+            .list
+            .iter()
+            .cloned()
+            .map(|replica| async move {
+                let checked = Arc::clone(&replica);
+                let lag = tokio::task::spawn_blocking(move || checked.lag())
+                    .await
+                    .map_err(anyhow::Error::from)
+                    .and_then(|lag| lag);
+                match lag {
+                    Ok(Some(lag)) if lag <= max_lag => {
+                        replica.mark_up();
+                        true
+                    }
+                    Ok(Some(lag)) => {
+                        replica.mark_down(&format!("LAG_{lag:.1}S"));
+                        false
+                    }
+                    Ok(None) => {
+                        replica.mark_down("NOT_REPLAYING");
+                        false
+                    }
+                    Err(err) => {
+                        replica.mark_down(&err.to_string());
+                        false
+                    }
+                }
+            });
+        join_all(checks).await.into_iter().filter(|up| *up).count()
+    }
+}
@@ -1,20 +1,130 @@
+use crate::modules::auth::auth_controller::{
+    __path_magic_link_callback, __path_oidc_authorize, __path_oidc_callback, __path_refresh_token,
+    __path_request_magic_link,
+};
+use crate::modules::auth::auth_model::{AuthToken, MagicLinkRequest, RefreshTokenRequest};
+use crate::modules::invitation::invitation_controller::{
+    __path_accept_invitation, __path_create_invitation, __path_list_invitations,
+    __path_resend_invitation, __path_revoke_invitation,
+};
+use crate::modules::invitation::invitation_model::{
+    AcceptInvitationRequest, CreateInvitationRequest, InvitationData,
+};
+use crate::modules::organization::organization_controller::{
+    __path_create_organization, __path_get_current_organization, __path_issue_tenant_token,
+    __path_list_members, __path_list_organizations, __path_remove_member, __path_update_member,
+};
+use crate::modules::organization::organization_model::{
+    CreateOrganizationRequest, MemberData, OrganizationData, UpdateMemberRequest,
+};
+use crate::modules::privacy::privacy_controller::{
+    __path_cancel_erasure, __path_download_export, __path_get_erasure, __path_get_export,
+    __path_request_erasure, __path_request_export,
+};
+use crate::modules::privacy::privacy_model::{ArchiveFormat, DataExportData, ErasureData};
 use crate::modules::user::user_controller::{
-    __path_get_all_users_use_json, __path_get_all_users_use_struct,
+    __path_create_user, __path_delete_user, __path_export_users, __path_get_avatar,
+    __path_get_overview, __path_get_user, __path_import_users, __path_list_users,
+    __path_patch_user, __path_restore_user, __path_update_avatar, __path_update_user,
 };
-use crate::modules::user::user_model::UserData;
-use utoipa::OpenApi;
+use crate::modules::user::user_model::{
+    AvatarUpload, CreateUserRequest, ImportReport, ImportRowError, UpdateUserRequest, UserData,
+    UserOverviewData,
+};
+use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi};
 
 #[derive(OpenApi)]
 #[openapi(
     paths(
-        get_all_users_use_struct,
-        get_all_users_use_json,
+        oidc_authorize,
+        oidc_callback,
+        refresh_token,
+        request_magic_link,
+        magic_link_callback,
+        list_users,
+        get_user,
+        get_overview,
+        create_user,
+        update_user,
+        patch_user,
+        delete_user,
+        restore_user,
+        update_avatar,
+        get_avatar,
+        import_users,
+        export_users,
+        list_organizations,
+        create_organization,
+        get_current_organization,
+        list_members,
+        update_member,
+        remove_member,
+        issue_tenant_token,
+        list_invitations,
+        create_invitation,
+        resend_invitation,
+        revoke_invitation,
+        accept_invitation,
+        request_export,
+        get_export,
+        download_export,
+        request_erasure,
+        get_erasure,
+        cancel_erasure,
     ),
     components(
-        schemas(UserData)
+        schemas(
+            AuthToken,
+            MagicLinkRequest,
+            RefreshTokenRequest,
+            UserData,
+            UserOverviewData,
+            CreateUserRequest,
+            UpdateUserRequest,
+            AvatarUpload,
+            ImportReport,
+            ImportRowError,
+            OrganizationData,
+            MemberData,
+            CreateOrganizationRequest,
+            UpdateMemberRequest,
+            InvitationData,
+            CreateInvitationRequest,
+            AcceptInvitationRequest,
+            ArchiveFormat,
+            DataExportData,
+            ErasureData
+        )
     ),
+    modifiers(&SecurityAddon),
     tags(
-        (name = "users", description = "User management endpoints")
+        (name = "auth", description = "Authentication endpoints"),
+        (name = "users", description = "User management endpoints"),
+        (name = "organizations", description = "Organizations (tenants) and their members"),
+        (name = "invitations", description = "Invitations to join an organization"),
+        (name = "privacy", description = "Personal data export and erasure of the current user")
     )
 )]
 pub struct ApiDoc;
+
+// Bearer access token issued by the auth endpoints
+struct SecurityAddon;
+impl Modify for SecurityAddon {
+    fn modify(
+        &self,
+        openapi: &mut utoipa::openapi::OpenApi,
+    ) {
+        if let Some(components) = openapi.components.as_mut() {
+            components.add_security_scheme(
+                "bearer_auth",
+                SecurityScheme::Http(
+                    HttpBuilder::new()
+                        .scheme(HttpAuthScheme::Bearer)
+                        .bearer_format("JWT")
+                        .build(),
+                ),
+            );
+        }
+    }
+}
@@ -1,7 +1,10 @@
 use diesel::QueryableByName;
 
+/// Row of `SELECT .. AS data`, the JSON a Postgres function returned.
+///
+/// NULL when the function returned NULL, see [`crate::database::function`].
 #[derive(Debug, QueryableByName)]
 pub struct SQLJsonResult {
-    #[diesel(sql_type = diesel::sql_types::Json)]
-    pub data: serde_json::Value,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Json>)]
+    pub data: Option<serde_json::Value>,
 }
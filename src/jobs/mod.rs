@@ -0,0 +1,333 @@
+//! Background jobs stored in Postgres.
+//!
+//! A [`Job`] is a serializable payload with the code that runs it. [`enqueue`] inserts it,
+//! inside the caller's transaction so the job exists exactly when the change commits, and
+//! the [`worker::WorkerPool`] started from `main.rs` claims due jobs with
+//! `FOR UPDATE SKIP LOCKED`. A failing job is retried with an exponential backoff until
+//! its `MAX_ATTEMPTS`, then kept as `failed`. Jobs run at least once: a worker that dies
+//! mid job leaves it to be claimed again when its lease ends, or failed when that was its
+//! last attempt.
+//!
+//! # Example
+//!
+//! ```rust
+//! db.transaction(move |conn| {
+//!     let export = insert_export(conn)?;
+//!     jobs::enqueue(conn, &DataExportJob { export_id: export.id })?;
+//!     Ok(export)
+//! })
+//! .await?;
+//! ```
+pub mod worker;
+
+use crate::database::instrumentation::CountedRunQueryDsl;
+use crate::schema::table::jobs;
+use crate::utils::generator;
+use crate::AppState;
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use diesel::dsl::{Eq, Filter, Find};
+use diesel::prelude::*;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::sync::Arc;
+
+// Values of jobs.status
+pub const JOB_PENDING: &str = "pending";
+pub const JOB_RUNNING: &str = "running";
+pub const JOB_COMPLETED: &str = "completed";
+pub const JOB_FAILED: &str = "failed";
+
+pub const DEFAULT_QUEUE: &str = "default";
+
+// last_error of a job whose worker died during its last attempt
+const JOB_LEASE_EXPIRED: &str = "JOB_LEASE_EXPIRED";
+
+/// Typed job payload and the work it stands for.
+#[async_trait]
+pub trait Job: Serialize + DeserializeOwned + Send + Sync + 'static {
+    /// Stored with the payload to find the job type again, keep it stable
+    const JOB_TYPE: &'static str;
+    /// Queue whose workers run the job
+    const QUEUE: &'static str = DEFAULT_QUEUE;
+    const MAX_ATTEMPTS: i32 = 5;
+
+    /// Jobs sharing a key are not enqueued while one of them is pending or running
+    fn unique_key(&self) -> Option<String> {
+        None
+    }
+
+    /// Do the work, an error schedules a retry. May run more than once for the same job.
+    async fn run(
+        self,
+        state: Arc<AppState>,
+    ) -> anyhow::Result<()>;
+}
+
+#[derive(Debug, Clone, Queryable, Selectable)]
+#[diesel(table_name = jobs)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct JobRow {
+    pub id: i64,
+    pub queue: String,
+    pub job_type: String,
+    pub payload: Value,
+    pub status: String,
+    /// Runs started so far, including the current one
+    pub attempts: i32,
+    pub max_attempts: i32,
+    pub last_error: Option<String>,
+    pub unique_key: Option<String>,
+    pub run_at: DateTime<Utc>,
+    pub locked_until: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = jobs)]
+struct NewJob<'a> {
+    id: i64,
+    queue: &'a str,
+    job_type: &'a str,
+    payload: Value,
+    max_attempts: i32,
+    unique_key: Option<String>,
+    run_at: DateTime<Utc>,
+}
+
+/// Enqueue `job` to run as soon as a worker is free, see [`schedule`].
+pub fn enqueue<J: Job>(
+    conn: &mut PgConnection,
+    job: &J,
+) -> QueryResult<Option<i64>> {
+    schedule(conn, job, Utc::now())
+}
+
+/// Enqueue `job` to run at `run_at`, returning its id.
+///
+/// `None` when a job with the same unique key is already pending or running.
+pub fn schedule<J: Job>(
+    conn: &mut PgConnection,
+    job: &J,
+    run_at: DateTime<Utc>,
+) -> QueryResult<Option<i64>> {
+    let payload = serde_json::to_value(job)
+        .map_err(|err| diesel::result::Error::SerializationError(Box::new(err)))?;
+    diesel::insert_into(jobs::table)
+        .values(NewJob {
+            id: generator::id() as i64,
+            queue: J::QUEUE,
+            job_type: J::JOB_TYPE,
+            payload,
+            max_attempts: J::MAX_ATTEMPTS,
+            unique_key: job.unique_key(),
+            run_at,
+        })
+        .on_conflict_do_nothing()
+        .returning(jobs::id)
+        .get_result(conn)
+        .optional()
+}
+
+/// Claim the next due job of `queue` for `lease`, if any.
+///
+/// Pending jobs whose `run_at` passed are due, and so are running jobs whose lease ended
+/// because their worker died, unless that was their last attempt: those are failed, so a
+/// job killing its worker does not run forever. Rows locked by another worker are skipped.
+pub fn claim(
+    conn: &mut PgConnection,
+    queue: &str,
+    lease: chrono::Duration,
+) -> QueryResult<Option<JobRow>> {
+    conn.transaction(|conn| {
+        let now = Utc::now();
+        let expired = jobs::status.eq(JOB_RUNNING).and(jobs::locked_until.le(now));
+        let abandoned: Vec<i64> = jobs::table
+            .filter(jobs::queue.eq(queue))
+            .filter(expired)
+            .filter(jobs::attempts.ge(jobs::max_attempts))
+            .select(jobs::id)
+            .for_update()
+            .skip_locked()
+            .load_counted(conn)?;
+        if !abandoned.is_empty() {
+            diesel::update(jobs::table.filter(jobs::id.eq_any(&abandoned)))
+                .set((
+                    jobs::status.eq(JOB_FAILED),
+                    jobs::last_error.eq(JOB_LEASE_EXPIRED),
+                    jobs::completed_at.eq(now),
+                    jobs::locked_until.eq(None::<DateTime<Utc>>),
+                ))
+                .execute_counted(conn)?;
+            tracing::error!(queue, jobs = ?abandoned, "JOB_ABANDONED");
+        }
+
+        let id: Option<i64> = jobs::table
+            .filter(jobs::queue.eq(queue))
+            .filter(
+                jobs::status
+                    .eq(JOB_PENDING)
+                    .and(jobs::run_at.le(now))
+                    .or(expired.and(jobs::attempts.lt(jobs::max_attempts))),
+            )
+            .order((jobs::run_at, jobs::id))
+            .select(jobs::id)
+            .for_update()
+            .skip_locked()
+            .first(conn)
+            .optional()?;
+        let Some(id) = id else {
+            return Ok(None);
+        };
+        diesel::update(jobs::table.find(id))
+            .set((
+                jobs::status.eq(JOB_RUNNING),
+                jobs::attempts.eq(jobs::attempts + 1),
+                jobs::locked_until.eq(now + lease),
+            ))
+            .returning(JobRow::as_returning())
+            .get_result(conn)
+            .map(Some)
+    })
+}
+
+type Owned =
+    Filter<Filter<Find<jobs::table, i64>, Eq<jobs::status, &'static str>>, Eq<jobs::attempts, i32>>;
+
+// The job as long as the run number `attempts` still holds it
+fn owned(
+    job_id: i64,
+    attempts: i32,
+) -> Owned {
+    jobs::table
+        .find(job_id)
+        .filter(jobs::status.eq(JOB_RUNNING))
+        .filter(jobs::attempts.eq(attempts))
+}
+
+/// Extend the lease of a running job, false when it was taken over meanwhile.
+pub fn heartbeat(
+    conn: &mut PgConnection,
+    job_id: i64,
+    attempts: i32,
+    lease: chrono::Duration,
+) -> QueryResult<bool> {
+    let extended = diesel::update(owned(job_id, attempts))
+        .set(jobs::locked_until.eq(Utc::now() + lease))
+        .execute_counted(conn)?;
+    Ok(extended > 0)
+}
+
+/// Mark the run number `attempts` done, unless another worker took the job over.
+pub fn complete(
+    conn: &mut PgConnection,
+    job_id: i64,
+    attempts: i32,
+) -> QueryResult<()> {
+    diesel::update(owned(job_id, attempts))
+        .set((
+            jobs::status.eq(JOB_COMPLETED),
+            jobs::completed_at.eq(Utc::now()),
+            jobs::locked_until.eq(None::<DateTime<Utc>>),
+            jobs::last_error.eq(None::<String>),
+        ))
+        .execute_counted(conn)?;
+    Ok(())
+}
+
+/// Record a failed run, pending again at `retry_at` or failed for good when it is `None`.
+pub fn fail(
+    conn: &mut PgConnection,
+    job_id: i64,
+    attempts: i32,
+    error: &str,
+    retry_at: Option<DateTime<Utc>>,
+) -> QueryResult<()> {
+    let target = owned(job_id, attempts);
+    let unlock = jobs::locked_until.eq(None::<DateTime<Utc>>);
+    match retry_at {
+        Some(retry_at) => diesel::update(target)
+            .set((
+                jobs::status.eq(JOB_PENDING),
+                jobs::last_error.eq(error),
+                jobs::run_at.eq(retry_at),
+                unlock,
+            ))
+            .execute_counted(conn)?,
+        None => diesel::update(target)
+            .set((
+                jobs::status.eq(JOB_FAILED),
+                jobs::last_error.eq(error),
+                jobs::completed_at.eq(Utc::now()),
+                unlock,
+            ))
+            .execute_counted(conn)?,
+    };
+    Ok(())
+}
+
+/// Delete completed jobs finished before `completed_before`, failed ones are kept.
+pub fn purge_completed(
+    conn: &mut PgConnection,
+    completed_before: DateTime<Utc>,
+) -> QueryResult<usize> {
+    diesel::delete(
+        jobs::table
+            .filter(jobs::status.eq(JOB_COMPLETED))
+            .filter(jobs::completed_at.lt(completed_before)),
+    )
+    .execute_counted(conn)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::test_connection;
+
+    fn insert_expired(
+        conn: &mut PgConnection,
+        queue: &str,
+        attempts: i32,
+    ) -> i64 {
+        let id = generator::id() as i64;
+        diesel::insert_into(jobs::table)
+            .values((
+                jobs::id.eq(id),
+                jobs::queue.eq(queue),
+                jobs::job_type.eq("test.crash"),
+                jobs::payload.eq(Value::Null),
+                jobs::status.eq(JOB_RUNNING),
+                jobs::attempts.eq(attempts),
+                jobs::max_attempts.eq(3),
+                jobs::locked_until.eq(Utc::now() - chrono::Duration::minutes(1)),
+            ))
+            .execute(conn)
+            .unwrap();
+        id
+    }
+
+    #[test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    fn fails_expired_jobs_out_of_attempts() {
+        let mut conn = test_connection();
+        conn.begin_test_transaction().unwrap();
+        let queue = format!("test_{}", generator::id());
+        let last = insert_expired(&mut conn, &queue, 3);
+        let retried = insert_expired(&mut conn, &queue, 2);
+        let lease = chrono::Duration::minutes(5);
+
+        let claimed = claim(&mut conn, &queue, lease).unwrap().unwrap();
+        assert_eq!(claimed.id, retried);
+        assert_eq!(claimed.attempts, 3);
+        let failed: JobRow = jobs::table
+            .find(last)
+            .select(JobRow::as_select())
+            .first(&mut conn)
+            .unwrap();
+        assert_eq!(failed.status, JOB_FAILED);
+        assert_eq!(failed.last_error.as_deref(), Some(JOB_LEASE_EXPIRED));
+        assert!(claim(&mut conn, &queue, lease).unwrap().is_none());
+    }
+}
@@ -0,0 +1,302 @@
+//! Worker pools running the jobs of a queue.
+use super::{claim, complete, fail, heartbeat, Job, JobRow};
+use crate::AppState;
+use chrono::Utc;
+use futures_util::future::BoxFuture;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
+
+type Runner =
+    Arc<dyn Fn(Value, Arc<AppState>) -> BoxFuture<'static, anyhow::Result<()>> + Send + Sync>;
+
+/// Job types the workers know how to run.
+#[derive(Clone, Default)]
+pub struct JobRegistry {
+    runners: HashMap<&'static str, Runner>,
+}
+
+impl JobRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register<J: Job>(mut self) -> Self {
+        let runner: Runner = Arc::new(|payload, state| {
+            Box::pin(async move {
+                let job: J = serde_json::from_value(payload)?;
+                job.run(state).await
+            })
+        });
+        self.runners.insert(J::JOB_TYPE, runner);
+        self
+    }
+}
+
+/// Tuning of [`WorkerPool`].
+#[derive(Clone, Copy, Debug)]
+pub struct WorkerSettings {
+    /// Jobs of the queue running at the same time
+    pub concurrency: usize,
+    /// Wait before looking again once the queue is empty
+    pub poll_interval: Duration,
+    /// How long a claimed job is reserved, renewed while it runs
+    pub lease: Duration,
+    /// Wait before the first retry, doubling on each further one
+    pub retry_base: Duration,
+    pub retry_max: Duration,
+}
+
+impl Default for WorkerSettings {
+    fn default() -> Self {
+        Self {
+            concurrency: 4,
+            poll_interval: Duration::from_secs(1),
+            lease: Duration::from_secs(60),
+            retry_base: Duration::from_secs(5),
+            retry_max: Duration::from_secs(3600),
+        }
+    }
+}
+
+impl WorkerSettings {
+    /// Wait after the failed run number `attempts`.
+    pub fn retry_delay(
+        &self,
+        attempts: i32,
+    ) -> Duration {
+        let exponent = attempts.saturating_sub(1).clamp(0, 20) as u32;
+        self.retry_base
+            .saturating_mul(1 << exponent)
+            .min(self.retry_max)
+    }
+}
+
+/// Workers claiming the due jobs of one queue.
+#[derive(Clone)]
+pub struct WorkerPool {
+    state: Arc<AppState>,
+    registry: Arc<JobRegistry>,
+    queue: String,
+    settings: WorkerSettings,
+}
+
+impl WorkerPool {
+    pub fn new(
+        state: Arc<AppState>,
+        registry: JobRegistry,
+        queue: &str,
+        settings: WorkerSettings,
+    ) -> Self {
+        Self {
+            state,
+            registry: Arc::new(registry),
+            queue: queue.to_string(),
+            settings,
+        }
+    }
+
+    /// Start the workers, they stop claiming jobs once `shutdown` is cancelled.
+    pub fn spawn(
+        self,
+        shutdown: CancellationToken,
+    ) -> Workers {
+        let mut workers = JoinSet::new();
+        for _ in 0..self.settings.concurrency.max(1) {
+            workers.spawn(self.clone().work(shutdown.clone()));
+        }
+        tracing::info!(queue = %self.queue, concurrency = workers.len(), "JOB_WORKERS_STARTED");
+        Workers {
+            queue: self.queue,
+            workers,
+        }
+    }
+
+    async fn work(
+        self,
+        shutdown: CancellationToken,
+    ) {
+        let lease = chrono::Duration::from_std(self.settings.lease).unwrap_or_default();
+        while !shutdown.is_cancelled() {
+            let queue = self.queue.clone();
+            let claimed = self
+                .state
+                .db
+                .transaction(move |conn| Ok(claim(conn, &queue, lease)?))
+                .await;
+            match claimed {
+                Ok(Some(job)) => {
+                    self.run(job).await;
+                    continue;
+                }
+                Ok(None) => {}
+                Err(err) => tracing::error!(queue = %self.queue, error = %err, "JOB_CLAIM_FAILED"),
+            }
+            tokio::select! {
+                _ = tokio::time::sleep(self.settings.poll_interval) => {}
+                _ = shutdown.cancelled() => {}
+            }
+        }
+    }
+
+    // Run a claimed job to its end, renewing the lease meanwhile, then record the outcome
+    async fn run(
+        &self,
+        job: JobRow,
+    ) {
+        let (job_id, attempts) = (job.id, job.attempts);
+        let Some(runner) = self.registry.runners.get(job.job_type.as_str()) else {
+            return self
+                .finish(&job, Err(format!("unknown job type {}", job.job_type)))
+                .await;
+        };
+
+        // Spawned so a panicking job fails like an erroring one
+        let mut task = tokio::spawn(runner(job.payload.clone(), self.state.clone()));
+        let lease = chrono::Duration::from_std(self.settings.lease).unwrap_or_default();
+        let mut renew = tokio::time::interval(self.settings.lease / 3);
+        renew.tick().await;
+        let result = loop {
+            tokio::select! {
+                joined = &mut task => break match joined {
+                    Ok(result) => result.map_err(|err| format!("{err:#}")),
+                    Err(err) => Err(format!("job panicked: {err}")),
+                },
+                _ = renew.tick() => {
+                    let renewed = self
+                        .state
+                        .db
+                        .transaction(move |conn| Ok(heartbeat(conn, job_id, attempts, lease)?))
+                        .await;
+                    match renewed {
+                        Ok(true) => {}
+                        Ok(false) => tracing::warn!(job_id, "JOB_LEASE_LOST"),
+                        Err(err) => tracing::warn!(job_id, error = %err, "JOB_HEARTBEAT_FAILED"),
+                    }
+                }
+            }
+        };
+        self.finish(&job, result).await;
+    }
+
+    async fn finish(
+        &self,
+        job: &JobRow,
+        result: Result<(), String>,
+    ) {
+        let (job_id, attempts) = (job.id, job.attempts);
+        let recorded = match result {
+            Ok(()) => {
+                tracing::debug!(job_id, job_type = %job.job_type, "JOB_COMPLETED");
+                self.state
+                    .db
+                    .transaction(move |conn| Ok(complete(conn, job_id, attempts)?))
+                    .await
+            }
+            Err(error) => {
+                let retry_at = (attempts < job.max_attempts).then(|| {
+                    Utc::now()
+                        + chrono::Duration::from_std(self.settings.retry_delay(attempts))
+                            .unwrap_or_default()
+                });
+                match retry_at {
+                    Some(retry_at) => tracing::warn!(
+                        job_id,
+                        job_type = %job.job_type,
+                        attempts,
+                        %retry_at,
+                        error = %error,
+                        "JOB_FAILED"
+                    ),
+                    None => tracing::error!(
+                        job_id,
+                        job_type = %job.job_type,
+                        attempts,
+                        error = %error,
+                        "JOB_GAVE_UP"
+                    ),
+                }
+                self.state
+                    .db
+                    .transaction(move |conn| Ok(fail(conn, job_id, attempts, &error, retry_at)?))
+                    .await
+            }
+        };
+        if let Err(err) = recorded {
+            tracing::error!(job_id, error = %err, "JOB_UPDATE_FAILED");
+        }
+    }
+}
+
+/// Running workers of a [`WorkerPool`].
+pub struct Workers {
+    queue: String,
+    workers: JoinSet<()>,
+}
+
+impl Workers {
+    /// Wait for the running jobs once shutdown was requested, at most `timeout`.
+    ///
+    /// Jobs still running afterwards are dropped and run again when their lease ends.
+    pub async fn drain(
+        mut self,
+        timeout: Duration,
+    ) {
+        let drained = tokio::time::timeout(timeout, async {
+            while self.workers.join_next().await.is_some() {}
+        })
+        .await;
+        match drained {
+            Ok(()) => tracing::info!(queue = %self.queue, "JOB_WORKERS_DRAINED"),
+            Err(_) => {
+                tracing::warn!(queue = %self.queue, running = self.workers.len(), "JOB_DRAIN_TIMEOUT");
+                self.workers.abort_all();
+            }
+        }
+    }
+}
+
+/// Parse `name:concurrency` pairs separated by comma, a missing concurrency means 1.
+pub fn parse_queues(queues: &str) -> Vec<(String, usize)> {
+    queues
+        .split(',')
+        .map(str::trim)
+        .filter(|queue| !queue.is_empty())
+        .map(|queue| match queue.split_once(':') {
+            Some((name, concurrency)) => (
+                name.trim().to_string(),
+                concurrency.trim().parse().unwrap_or(1),
+            ),
+            None => (queue.to_string(), 1),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_queues_and_concurrency() {
+        assert_eq!(
+            parse_queues("default:4, mail , exports:x,"),
+            vec![
+                ("default".to_string(), 4),
+                ("mail".to_string(), 1),
+                ("exports".to_string(), 1)
+            ]
+        );
+        assert!(parse_queues("").is_empty());
+    }
+
+    #[test]
+    fn retry_delay_doubles_up_to_the_cap() {
+        let settings = WorkerSettings::default();
+        assert_eq!(settings.retry_delay(1), Duration::from_secs(5));
+        assert_eq!(settings.retry_delay(3), Duration::from_secs(20));
+        assert_eq!(settings.retry_delay(40), Duration::from_secs(3600));
+    }
+}
@@ -3,14 +3,19 @@ pub mod constant;
 pub mod database;
 pub mod docs;
 pub mod dto;
+pub mod jobs;
 pub mod middlewares;
 pub mod modules;
+pub mod outbox;
+pub mod repository;
 pub mod schema;
 pub mod server;
+pub mod tasks;
 pub mod utils;
 
 #[derive(Debug, Clone)]
 pub struct AppState {
     pub env: config::Config,
     pub cache: utils::cache::Cache,
+    pub db: database::Database,
 }
@@ -1,12 +1,22 @@
-use axum_boilerplate::config::Config;
+use axum_boilerplate::config::{Command, Config};
 use axum_boilerplate::constant;
+use axum_boilerplate::database::listener::Listener;
+use axum_boilerplate::database::{instrumentation, Database, PoolSettings};
+use axum_boilerplate::jobs::worker::{parse_queues, JobRegistry, WorkerPool, WorkerSettings};
+use axum_boilerplate::modules::auth::auth_model::MagicLinkEmailJob;
+use axum_boilerplate::modules::privacy::privacy_model::DataExportJob;
+use axum_boilerplate::modules::user::user_model::USER_REGISTERED;
+use axum_boilerplate::outbox::relay::{Relay, RelaySettings};
+use axum_boilerplate::outbox::sink::{HandlerSink, WebhookSink};
 use axum_boilerplate::server::ApplicationServer;
+use axum_boilerplate::tasks;
 use axum_boilerplate::utils::cache::Cache;
 use axum_boilerplate::utils::logger::Logger;
 use axum_boilerplate::AppState;
 use dotenv::dotenv;
 use std::sync::Arc;
 use std::time::Duration;
+use tokio_util::sync::CancellationToken;
 
 #[tokio::main]
 async fn main() {
@@ -17,10 +27,133 @@ async fn main() {
     let _log = Logger::init(&config.app_env);
     // Create in memory cache
     let cache = Cache::new(Duration::from_secs(constant::CACHE_TIMEOUT));
+    // Trace every query and log the slow ones, before any connection opens
+    instrumentation::install(Duration::from_millis(config.database_slow_query_ms))
+        .expect("DATABASE_INSTRUMENTATION_FAILED");
+    // Create database connection pool
+    let replica_urls: Vec<&str> = config
+        .database_replica_urls
+        .split(',')
+        .map(str::trim)
+        .filter(|url| !url.is_empty())
+        .collect();
+    let db = Database::connect(&config.database_url, PoolSettings::from(&config))
+        .expect("DATABASE_CONNECTION_FAILED")
+        .with_replicas(
+            &replica_urls,
+            Duration::from_secs(config.database_replica_max_lag),
+        );
+    // `migrate up|down|status` runs instead of the server
+    if let Some(Command::Migrate(command)) = config.command {
+        if let Err(err) = db.run_migrate_command(command).await {
+            tracing::error!(error = %err, "DATABASE_MIGRATION_FAILED");
+            std::process::exit(1);
+        }
+        return;
+    }
+    if config.run_migrations {
+        let applied = db.migrate_up().await.expect("DATABASE_MIGRATION_FAILED");
+        tracing::info!(?applied, "DATABASE_MIGRATED");
+    }
+    // Keep lagging or unreachable replicas out of rotation
+    if db.has_replicas() {
+        tasks::replicas::spawn(
+            db.clone(),
+            Duration::from_secs(config.database_replica_check_interval),
+        );
+    }
+    // Drop cache entries changed by other instances or triggers
+    let listener = config
+        .cache_invalidation_channels
+        .split(',')
+        .map(str::trim)
+        .filter(|channel| !channel.is_empty())
+        .fold(Listener::new(&config.database_url), |listener, channel| {
+            listener.invalidate(channel, cache.clone(), &constant::CACHE_INVALIDATED_KEYS)
+        });
+    if !listener.channels().is_empty() {
+        listener.spawn().expect("DATABASE_LISTENER_FAILED");
+    }
+    // Purge soft deleted rows past the retention period
+    tasks::purge::spawn(
+        db.clone(),
+        chrono::Duration::days(config.soft_delete_retention_days),
+        Duration::from_secs(config.soft_delete_purge_interval),
+    );
+    // Run due data erasures and drop expired export archives
+    tasks::privacy::spawn(
+        db.clone(),
+        Duration::from_secs(config.privacy_task_interval),
+    );
+    // Deliver outbox events once their transaction committed
+    let handlers = HandlerSink::new().on(USER_REGISTERED, |event| async move {
+        tracing::info!(
+            user_id = event.aggregate_id,
+            source = event.payload["source"].as_str(),
+            "USER_REGISTERED"
+        );
+        Ok(())
+    });
+    let relay = config
+        .outbox_webhook_urls
+        .split(',')
+        .map(str::trim)
+        .filter(|url| !url.is_empty())
+        .fold(
+            Relay::new(
+                db.clone(),
+                RelaySettings {
+                    batch_size: config.outbox_batch_size,
+                    max_attempts: config.outbox_max_attempts,
+                    ..RelaySettings::default()
+                },
+            )
+            .sink(handlers),
+            |relay, url| {
+                relay.sink(WebhookSink::new(
+                    url,
+                    Some(config.outbox_webhook_secret.clone()),
+                ))
+            },
+        );
+    tasks::outbox::spawn(
+        db.clone(),
+        relay,
+        Duration::from_millis(config.outbox_poll_interval),
+        chrono::Duration::days(config.outbox_retention_days),
+    );
+    // Drop completed jobs past the retention period
+    tasks::jobs::spawn(
+        db.clone(),
+        chrono::Duration::days(config.job_retention_days),
+    );
     // Application state
-    let app_state = Arc::new(AppState { env: config, cache });
+    let app_state = Arc::new(AppState {
+        env: config,
+        cache,
+        db,
+    });
+    // Work the job queues until shutdown, then let running jobs finish
+    let shutdown = CancellationToken::new();
+    let registry = JobRegistry::new()
+        .register::<DataExportJob>()
+        .register::<MagicLinkEmailJob>();
+    let workers: Vec<_> = parse_queues(&app_state.env.job_queues)
+        .into_iter()
+        .map(|(queue, concurrency)| {
+            let settings = WorkerSettings {
+                concurrency,
+                poll_interval: Duration::from_millis(app_state.env.job_poll_interval),
+                ..WorkerSettings::default()
+            };
+            WorkerPool::new(app_state.clone(), registry.clone(), &queue, settings)
+                .spawn(shutdown.clone())
+        })
+        .collect();
+    let drain_timeout = Duration::from_secs(app_state.env.job_drain_timeout);
     // Serve Application
-    ApplicationServer::serve(app_state)
-        .await
-        .expect("Error starting server");
+    let served = ApplicationServer::serve(app_state, shutdown.clone()).await;
+    shutdown.cancel();
+    futures_util::future::join_all(workers.into_iter().map(|pool| pool.drain(drain_timeout))).await;
+    served.expect("Error starting server");
 }
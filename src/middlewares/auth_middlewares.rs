@@ -1,10 +1,17 @@
+use crate::schema::table::users;
 use crate::utils::errors::HttpError;
+use crate::utils::token::decode_token;
+use crate::{constant, AppState};
 use axum::{
+    async_trait,
     body::Body,
-    http::{header, Request},
+    extract::FromRequestParts,
+    http::{header, request::Parts, Request},
     middleware::Next,
     response::Response,
 };
+use diesel::prelude::*;
+use std::sync::Arc;
 
 #[derive(Clone)]
 pub struct BasicAuth {
@@ -31,3 +38,96 @@ pub async fn auth_header(
     // Run the next middleware/handler
     Ok(next.run(req).await)
 }
+
+/// Caller authenticated by the bearer access token.
+#[derive(Debug, Clone)]
+pub struct AuthUser {
+    pub user_id: i64,
+    pub email: String,
+}
+
+#[async_trait]
+impl FromRequestParts<Arc<AppState>> for AuthUser {
+    type Rejection = HttpError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, Self::Rejection> {
+        let token = parts
+            .headers
+            .get(header::AUTHORIZATION)
+            .ok_or_else(|| HttpError::unauthorized("AUTHORIZATION_REQUIRED"))?
+            .to_str()
+            .map_err(|_| HttpError::forbidden("INVALID_AUTHORIZATION"))?;
+        let token = token.strip_prefix("Bearer ").unwrap_or(token);
+
+        let (user_id, email) = decode_token(token, state.env.secret.as_bytes())?;
+        let user_id = user_id
+            .parse()
+            .map_err(|_| HttpError::unauthorized("INVALID_TOKEN"))?;
+        Ok(AuthUser { user_id, email })
+    }
+}
+
+impl AuthUser {
+    /// Fail with 403 unless the live account of the caller has the admin role.
+    pub async fn ensure_admin(
+        &self,
+        state: &AppState,
+    ) -> Result<(), HttpError> {
+        // The role is read on every request so a demotion applies immediately
+        let user_id = self.user_id;
+        let role = state
+            .db
+            .execute(move |conn| {
+                Ok(users::table
+                    .find(user_id)
+                    .filter(users::deleted_at.is_null())
+                    .select(users::role)
+                    .first::<String>(conn)
+                    .optional()?)
+            })
+            .await
+            .map_err(|err| {
+                tracing::error!(error = %err, "ADMIN_ROLE_LOOKUP_FAILED");
+                HttpError::server_error("ADMIN_ROLE_LOOKUP_FAILED")
+            })?
+            .ok_or_else(|| HttpError::unauthorized("INVALID_TOKEN"))?;
+
+        if role != constant::ROLE_ADMIN {
+            return Err(HttpError::forbidden("ADMIN_REQUIRED"));
+        }
+        Ok(())
+    }
+
+    /// Fail with 403 unless the caller is `user_id` or an admin.
+    pub async fn ensure_self_or_admin(
+        &self,
+        state: &AppState,
+        user_id: i64,
+    ) -> Result<(), HttpError> {
+        if self.user_id == user_id {
+            return Ok(());
+        }
+        self.ensure_admin(state).await
+    }
+}
+
+/// Authenticated caller whose live account has the admin role.
+#[derive(Debug, Clone)]
+pub struct AdminUser(pub AuthUser);
+
+#[async_trait]
+impl FromRequestParts<Arc<AppState>> for AdminUser {
+    type Rejection = HttpError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, Self::Rejection> {
+        let user = AuthUser::from_request_parts(parts, state).await?;
+        user.ensure_admin(state).await?;
+        Ok(AdminUser(user))
+    }
+}
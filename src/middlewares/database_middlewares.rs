@@ -0,0 +1,28 @@
+use crate::constant;
+use crate::database::read_primary;
+use axum::{
+    body::Body,
+    http::{Method, Request},
+    middleware::Next,
+    response::Response,
+};
+
+/// Serve the request from the primary database when it writes, or when the client asks to
+/// read its own writes with `X-Read-Your-Writes: true`, e.g. right after a `POST`.
+///
+/// Other requests let [`crate::database::Database::execute`] read from the replicas.
+pub async fn read_your_writes(
+    req: Request<Body>,
+    next: Next,
+) -> Response {
+    let writes = !matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS);
+    let requested = req
+        .headers()
+        .get(constant::HEADER_READ_YOUR_WRITES)
+        .is_some_and(|value| value.as_bytes().eq_ignore_ascii_case(b"true"));
+    if writes || requested {
+        read_primary(next.run(req)).await
+    } else {
+        next.run(req).await
+    }
+}
@@ -1 +1,3 @@
 pub mod auth_middlewares;
+pub mod database_middlewares;
+pub mod tenant_middlewares;
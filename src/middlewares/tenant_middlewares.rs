@@ -0,0 +1,286 @@
+use crate::config::Config;
+use crate::middlewares::auth_middlewares::AuthUser;
+use crate::schema::table::{organization_members, organizations, users};
+use crate::utils::errors::HttpError;
+use crate::utils::token::decode_claims;
+use crate::{constant, tenant_scoped, AppState};
+use axum::{
+    async_trait,
+    body::Body,
+    extract::{FromRequestParts, State},
+    http::{header, request::Parts, HeaderMap, Request},
+    middleware::Next,
+    response::Response,
+};
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// Organization the request acts on, resolved by [`resolve_tenant`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Tenant {
+    pub id: i64,
+    pub slug: String,
+}
+
+// Outcome of the lookup stored by `resolve_tenant`, turned into an error by the extractors
+#[derive(Debug, Clone)]
+struct TenantLookup(Result<Tenant, HttpError>);
+
+/// Resolve the tenant of the request and store it in the request extensions.
+///
+/// Sources by precedence: the `tenant` claim of a valid access token, the `X-Tenant-Id`
+/// header (organization id or slug), then the subdomain of `TENANT_BASE_DOMAIN`. Every
+/// request passes through, including those naming an unknown tenant (`www.` or `api.`
+/// hosts), only handlers extracting [`Tenant`] or [`TenantMember`] fail without one.
+pub async fn resolve_tenant(
+    State(state): State<Arc<AppState>>,
+    mut req: Request<Body>,
+    next: Next,
+) -> Response {
+    let lookup = match tenant_key(&state.env, req.headers()) {
+        Ok(Some(key)) => Some(find_tenant(&state, key).await),
+        Ok(None) => None,
+        Err(err) => Some(Err(err)),
+    };
+    if let Some(lookup) = lookup {
+        req.extensions_mut().insert(TenantLookup(lookup));
+    }
+    next.run(req).await
+}
+
+fn tenant_key(
+    config: &Config,
+    headers: &HeaderMap,
+) -> Result<Option<String>, HttpError> {
+    // An invalid token carries no claim here, AuthUser rejects it later if the route needs one
+    let claim = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.strip_prefix("Bearer ").unwrap_or(value))
+        .and_then(|token| decode_claims(token, config.secret.as_bytes()).ok())
+        .and_then(|claims| claims.tenant);
+    if claim.is_some() {
+        return Ok(claim);
+    }
+
+    if let Some(value) = headers.get(constant::HEADER_TENANT) {
+        let value = value
+            .to_str()
+            .map_err(|_| HttpError::bad_request("INVALID_TENANT_HEADER"))?
+            .trim();
+        return Ok((!value.is_empty()).then(|| value.to_string()));
+    }
+
+    Ok(headers
+        .get(header::HOST)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|host| subdomain(host, &config.tenant_base_domain)))
+}
+
+/// Leftmost label of `host` when it is a direct subdomain of `base_domain`.
+pub fn subdomain(
+    host: &str,
+    base_domain: &str,
+) -> Option<String> {
+    let base_domain = base_domain.trim_start_matches('.');
+    if base_domain.is_empty() {
+        return None;
+    }
+    let host = host.split(':').next()?.to_ascii_lowercase();
+    let label = host
+        .strip_suffix(&base_domain.to_ascii_lowercase())?
+        .strip_suffix('.')?;
+    // a.b.example.com is not a tenant of example.com
+    (!label.is_empty() && !label.contains('.')).then(|| label.to_string())
+}
+
+async fn find_tenant(
+    state: &AppState,
+    key: String,
+) -> Result<Tenant, HttpError> {
+    // Slugs are case insensitive, all digit keys are ids. The organizations trigger drops
+    // both keys of a renamed or deleted organization.
+    let key = key.to_ascii_lowercase();
+    let cache_key = format!("tenant:{key}");
+    if let Some(tenant) = state
+        .cache
+        .get(&cache_key)
+        .await
+        .and_then(|value| serde_json::from_value(value).ok())
+    {
+        return Ok(tenant);
+    }
+
+    let tenant = state
+        .db
+        .execute(move |conn| {
+            let query = organizations::table
+                .select((organizations::id, organizations::slug))
+                .into_boxed();
+            let query = match key.parse::<i64>() {
+                Ok(id) => query.filter(organizations::id.eq(id)),
+                Err(_) => query.filter(organizations::slug.eq(key)),
+            };
+            Ok(query.first::<(i64, String)>(conn).optional()?)
+        })
+        .await
+        .map_err(|err| {
+            tracing::error!(error = %err, "TENANT_LOOKUP_FAILED");
+            HttpError::server_error("TENANT_LOOKUP_FAILED")
+        })?
+        .map(|(id, slug)| Tenant { id, slug })
+        .ok_or_else(|| HttpError::not_found("TENANT_NOT_FOUND"))?;
+
+    if let Ok(value) = serde_json::to_value(&tenant) {
+        state.cache.set(cache_key, value).await;
+    }
+    Ok(tenant)
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for Tenant
+where
+    S: Send + Sync,
+{
+    type Rejection = HttpError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        _state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        match parts.extensions.get::<TenantLookup>() {
+            Some(TenantLookup(lookup)) => lookup.clone(),
+            None => Err(HttpError::bad_request("TENANT_REQUIRED")),
+        }
+    }
+}
+
+/// Tenant of the request when it names one, fails like [`Tenant`] when it is unknown.
+#[derive(Debug, Clone)]
+pub struct OptionalTenant(pub Option<Tenant>);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for OptionalTenant
+where
+    S: Send + Sync,
+{
+    type Rejection = HttpError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        _state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        match parts.extensions.get::<TenantLookup>() {
+            Some(TenantLookup(lookup)) => lookup.clone().map(|tenant| OptionalTenant(Some(tenant))),
+            None => Ok(OptionalTenant(None)),
+        }
+    }
+}
+
+/// Authenticated caller who is a member of the request tenant.
+#[derive(Debug, Clone)]
+pub struct TenantMember {
+    pub tenant: Tenant,
+    pub user: AuthUser,
+    pub role: String,
+}
+
+#[async_trait]
+impl FromRequestParts<Arc<AppState>> for TenantMember {
+    type Rejection = HttpError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, Self::Rejection> {
+        let tenant = Tenant::from_request_parts(parts, state).await?;
+        let user = AuthUser::from_request_parts(parts, state).await?;
+
+        // The membership is read on every request so a removal applies immediately
+        let user_id = user.user_id;
+        let role = state
+            .db
+            .tenant_transaction(tenant.id, move |conn| {
+                Ok(tenant_scoped!(
+                    organization_members::table,
+                    organization_members::organization_id,
+                    tenant.id
+                )
+                .inner_join(users::table)
+                .filter(organization_members::user_id.eq(user_id))
+                .filter(users::deleted_at.is_null())
+                .select(organization_members::role)
+                .first::<String>(conn)
+                .optional()?)
+            })
+            .await
+            .map_err(|err| {
+                tracing::error!(error = %err, "TENANT_MEMBERSHIP_LOOKUP_FAILED");
+                HttpError::server_error("TENANT_MEMBERSHIP_LOOKUP_FAILED")
+            })?
+            .ok_or_else(|| HttpError::forbidden("TENANT_ACCESS_DENIED"))?;
+
+        Ok(TenantMember { tenant, user, role })
+    }
+}
+
+impl TenantMember {
+    /// Whether the member holds `role` or a more privileged one.
+    pub fn has_role(
+        &self,
+        role: &str,
+    ) -> bool {
+        member_rank(&self.role) >= member_rank(role)
+    }
+
+    /// Fail with 403 unless the member holds `role` or a more privileged one.
+    pub fn ensure_role(
+        &self,
+        role: &str,
+    ) -> Result<(), HttpError> {
+        if !self.has_role(role) {
+            return Err(HttpError::forbidden("TENANT_ROLE_REQUIRED"));
+        }
+        Ok(())
+    }
+}
+
+/// Privilege of a membership role, unknown roles rank below every known one.
+pub fn member_rank(role: &str) -> Option<usize> {
+    constant::MEMBER_ROLES
+        .iter()
+        .position(|known| *known == role)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_direct_subdomains_only() {
+        assert_eq!(
+            subdomain("acme.example.com", "example.com"),
+            Some("acme".into())
+        );
+        assert_eq!(
+            subdomain("ACME.Example.com:8080", ".example.com"),
+            Some("acme".into())
+        );
+        assert_eq!(subdomain("example.com", "example.com"), None);
+        assert_eq!(subdomain("a.b.example.com", "example.com"), None);
+        assert_eq!(subdomain("acmeexample.com", "example.com"), None);
+        assert_eq!(subdomain("acme.example.com", ""), None);
+    }
+
+    #[test]
+    fn ranks_member_roles() {
+        assert!(
+            member_rank(constant::MEMBER_ROLE_OWNER) > member_rank(constant::MEMBER_ROLE_ADMIN)
+        );
+        assert!(
+            member_rank(constant::MEMBER_ROLE_ADMIN) > member_rank(constant::MEMBER_ROLE_MEMBER)
+        );
+        assert!(member_rank("unknown") < member_rank(constant::MEMBER_ROLE_MEMBER));
+    }
+}
@@ -0,0 +1,129 @@
+use crate::{
+    utils::{errors::HttpError, extractor::BodyJson, responses::HttpResponse},
+    AppState,
+};
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::Redirect,
+};
+use std::sync::Arc;
+
+use super::{
+    auth_model::{
+        AuthToken, MagicLinkCallbackQuery, MagicLinkRequest, OidcCallbackQuery, RefreshTokenRequest,
+    },
+    auth_service,
+};
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/auth/oidc/{provider}/authorize",
+    params(
+        ("provider" = String, Path, description = "Configured OIDC provider name e.g. google")
+    ),
+    responses(
+        (status = 303, description = "Redirect to the provider login page"),
+        (status = 404, description = "Unknown provider")
+    ),
+    tag = "auth"
+)]
+pub async fn oidc_authorize(
+    State(state): State<Arc<AppState>>,
+    Path(provider): Path<String>,
+) -> Result<Redirect, HttpError> {
+    let url = auth_service::start_oidc_login(&state, &provider).await?;
+    Ok(Redirect::to(&url))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/auth/oidc/{provider}/callback",
+    params(
+        ("provider" = String, Path, description = "Configured OIDC provider name e.g. google"),
+        OidcCallbackQuery
+    ),
+    responses(
+        (status = 200, description = "Login succeeded", body = AuthToken),
+        (status = 400, description = "Invalid or expired login state"),
+        (status = 401, description = "Provider rejected the login")
+    ),
+    tag = "auth"
+)]
+pub async fn oidc_callback(
+    State(state): State<Arc<AppState>>,
+    Path(provider): Path<String>,
+    Query(query): Query<OidcCallbackQuery>,
+) -> Result<HttpResponse<AuthToken>, HttpError> {
+    // Provider reported an error (e.g. user cancelled the consent screen)
+    if let Some(error) = query.error {
+        tracing::info!(%provider, %error, description = ?query.error_description, "OIDC_LOGIN_DENIED");
+        return Err(HttpError::unauthorized(format!(
+            "OIDC_LOGIN_DENIED:{error}"
+        )));
+    }
+    let code = query
+        .code
+        .ok_or_else(|| HttpError::bad_request("OIDC_CODE_MISSING"))?;
+
+    let tokens = auth_service::complete_oidc_login(&state, &provider, &code, &query.state).await?;
+    Ok(HttpResponse::ok(tokens, "OIDC_LOGIN_SUCCESS"))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/auth/refresh",
+    request_body = RefreshTokenRequest,
+    responses(
+        (status = 200, description = "New token pair", body = AuthToken),
+        (status = 401, description = "Invalid or expired refresh token")
+    ),
+    tag = "auth"
+)]
+pub async fn refresh_token(
+    State(state): State<Arc<AppState>>,
+    BodyJson(body): BodyJson<RefreshTokenRequest>,
+) -> Result<HttpResponse<AuthToken>, HttpError> {
+    let tokens = auth_service::refresh_tokens(&state, &body.refresh_token).await?;
+    Ok(HttpResponse::ok(tokens, "TOKEN_REFRESHED"))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/auth/magic-link",
+    request_body = MagicLinkRequest,
+    responses(
+        (status = 202, description = "Sign-in link queued, it is only mailed when the email belongs to an account"),
+        (status = 429, description = "Too many links requested for this email")
+    ),
+    tag = "auth"
+)]
+pub async fn request_magic_link(
+    State(state): State<Arc<AppState>>,
+    BodyJson(body): BodyJson<MagicLinkRequest>,
+) -> Result<HttpResponse<()>, HttpError> {
+    auth_service::request_magic_link(&state, &body.email).await?;
+    Ok(HttpResponse::new(
+        "MAGIC_LINK_SENT",
+        StatusCode::ACCEPTED,
+        None,
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/auth/magic-link/callback",
+    params(MagicLinkCallbackQuery),
+    responses(
+        (status = 200, description = "Login succeeded", body = AuthToken),
+        (status = 401, description = "Link is invalid, expired or already used")
+    ),
+    tag = "auth"
+)]
+pub async fn magic_link_callback(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<MagicLinkCallbackQuery>,
+) -> Result<HttpResponse<AuthToken>, HttpError> {
+    let tokens = auth_service::consume_magic_link(&state, &query.token).await?;
+    Ok(HttpResponse::ok(tokens, "MAGIC_LINK_LOGIN_SUCCESS"))
+}
@@ -0,0 +1,89 @@
+use crate::schema::table::{magic_links, user_identities};
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use validator::Validate;
+
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthToken {
+    pub access_token: String,
+    pub refresh_token: String,
+    pub token_type: String,
+    /// Access token lifetime in seconds
+    pub expires_in: i64,
+}
+
+#[derive(Deserialize, Validate, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RefreshTokenRequest {
+    #[validate(length(min = 1, message = "Refresh token is required"))]
+    pub refresh_token: String,
+}
+
+#[derive(Deserialize, Validate, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MagicLinkRequest {
+    #[validate(email(message = "Invalid email address"))]
+    pub email: String,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct MagicLinkCallbackQuery {
+    pub token: String,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct OidcCallbackQuery {
+    pub code: Option<String>,
+    pub state: String,
+    pub error: Option<String>,
+    pub error_description: Option<String>,
+}
+
+// Login attempt kept in cache between authorize and callback
+#[derive(Serialize, Deserialize)]
+pub struct OidcPendingLogin {
+    pub provider: String,
+    pub nonce: String,
+    pub code_verifier: String,
+}
+
+#[derive(Debug, Clone, Queryable, Selectable)]
+#[diesel(table_name = user_identities)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct UserIdentity {
+    pub id: i64,
+    pub user_id: i64,
+    pub provider: String,
+    pub subject: String,
+    pub email: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = user_identities)]
+pub struct NewUserIdentity {
+    pub id: i64,
+    pub user_id: i64,
+    pub provider: String,
+    pub subject: String,
+    pub email: Option<String>,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = magic_links)]
+pub struct NewMagicLink {
+    pub id: i64,
+    pub email: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Background job emailing the sign-in link of a magic link
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MagicLinkEmailJob {
+    pub link_id: i64,
+}
@@ -0,0 +1,437 @@
+use super::auth_model::{
+    AuthToken, MagicLinkEmailJob, NewMagicLink, NewUserIdentity, OidcPendingLogin,
+};
+use crate::config::Config;
+use crate::constant;
+use crate::database::instrumentation::CountedRunQueryDsl;
+use crate::database::Database;
+use crate::jobs::{self, Job};
+use crate::modules::user::user_model::{NewUser, User};
+use crate::modules::user::user_service::record_registered;
+use crate::schema::table::{magic_links, user_identities, users};
+use crate::utils::errors::HttpError;
+use crate::utils::mailer::Mailer;
+use crate::utils::oidc::{
+    self, IdTokenClaims, OidcClient, OidcError, OidcProvider, ProviderMetadata,
+};
+use crate::utils::{generator, token};
+use crate::AppState;
+use axum::async_trait;
+use axum::http::StatusCode;
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use diesel::sql_query;
+use diesel::sql_types::{Integer, Text};
+use std::sync::Arc;
+
+const OIDC_STATE_PREFIX: &str = "OIDC_STATE:";
+const OIDC_METADATA_PREFIX: &str = "OIDC_METADATA:";
+
+fn find_provider(
+    config: &Config,
+    name: &str,
+) -> Result<OidcProvider, HttpError> {
+    OidcProvider::parse_list(&config.oidc_providers)
+        .map_err(|err| {
+            tracing::error!(error = %err, "OIDC_PROVIDERS_CONFIG_INVALID");
+            HttpError::server_error("OIDC_PROVIDERS_CONFIG_INVALID")
+        })?
+        .into_iter()
+        .find(|provider| provider.name == name.to_lowercase())
+        .ok_or_else(|| HttpError::not_found("OIDC_PROVIDER_NOT_FOUND"))
+}
+
+fn redirect_uri(
+    config: &Config,
+    provider: &OidcProvider,
+) -> String {
+    format!(
+        "{}/api/v1/auth/oidc/{}/callback",
+        config.app_url.trim_end_matches('/'),
+        provider.name
+    )
+}
+
+fn map_oidc_error(err: OidcError) -> HttpError {
+    tracing::error!(error = %err, "OIDC_REQUEST_FAILED");
+    match err {
+        OidcError::InvalidIdToken(_) => HttpError::unauthorized("OIDC_INVALID_ID_TOKEN"),
+        OidcError::TokenExchange(_) => HttpError::unauthorized("OIDC_TOKEN_EXCHANGE_FAILED"),
+        _ => HttpError::new("OIDC_PROVIDER_UNAVAILABLE", StatusCode::BAD_GATEWAY),
+    }
+}
+
+// Discovery documents rarely change, keep them in cache
+async fn provider_metadata(
+    state: &AppState,
+    client: &OidcClient,
+    provider: &OidcProvider,
+) -> Result<ProviderMetadata, HttpError> {
+    let cache_key = format!("{OIDC_METADATA_PREFIX}{}", provider.name);
+    if let Some(cached) = state.cache.get(&cache_key).await {
+        if let Ok(metadata) = serde_json::from_value(cached) {
+            return Ok(metadata);
+        }
+    }
+    let metadata = client
+        .discover(&provider.issuer)
+        .await
+        .map_err(map_oidc_error)?;
+    if let Ok(value) = serde_json::to_value(&metadata) {
+        state.cache.set(cache_key, value).await;
+    }
+    Ok(metadata)
+}
+
+/// Start the authorization code flow and return the provider login url.
+pub async fn start_oidc_login(
+    state: &AppState,
+    provider_name: &str,
+) -> Result<String, HttpError> {
+    let provider = find_provider(&state.env, provider_name)?;
+    let client = OidcClient::new();
+    let metadata = provider_metadata(state, &client, &provider).await?;
+    let request =
+        oidc::authorization_request(&provider, &metadata, &redirect_uri(&state.env, &provider))
+            .map_err(map_oidc_error)?;
+
+    let pending = OidcPendingLogin {
+        provider: provider.name.clone(),
+        nonce: request.nonce,
+        code_verifier: request.code_verifier,
+    };
+    let value = serde_json::to_value(&pending)
+        .map_err(|_| HttpError::server_error("OIDC_STATE_SERIALIZE_FAILED"))?;
+    state
+        .cache
+        .set(format!("{OIDC_STATE_PREFIX}{}", request.state), value)
+        .await;
+
+    Ok(request.url)
+}
+
+/// Finish the authorization code flow, link the identity and issue our own tokens.
+pub async fn complete_oidc_login(
+    state: &AppState,
+    provider_name: &str,
+    code: &str,
+    login_state: &str,
+) -> Result<AuthToken, HttpError> {
+    let provider = find_provider(&state.env, provider_name)?;
+
+    // State is single use, remove it before doing anything else
+    let cache_key = format!("{OIDC_STATE_PREFIX}{login_state}");
+    let pending = state.cache.get(&cache_key).await;
+    state.cache.delete(&cache_key).await;
+    let pending: OidcPendingLogin = pending
+        .and_then(|value| serde_json::from_value(value).ok())
+        .filter(|pending: &OidcPendingLogin| pending.provider == provider.name)
+        .ok_or_else(|| HttpError::bad_request("OIDC_INVALID_STATE"))?;
+
+    let client = OidcClient::new();
+    let metadata = provider_metadata(state, &client, &provider).await?;
+    let tokens = client
+        .exchange_code(
+            &provider,
+            &metadata,
+            code,
+            &pending.code_verifier,
+            &redirect_uri(&state.env, &provider),
+        )
+        .await
+        .map_err(map_oidc_error)?;
+    let id_token = tokens
+        .id_token
+        .ok_or_else(|| HttpError::unauthorized("OIDC_ID_TOKEN_MISSING"))?;
+    let jwks = client.fetch_jwks(&metadata).await.map_err(map_oidc_error)?;
+    let claims = oidc::validate_id_token(&provider, &metadata, &jwks, &id_token, &pending.nonce)
+        .map_err(map_oidc_error)?;
+
+    let user = link_identity(&state.db, provider.name, claims).await?;
+    issue_tokens(&state.env, &user)
+}
+
+/// Find the local user for an external identity, linking or creating it on first login.
+///
+/// An existing account is only linked by email when the provider verified that email,
+/// otherwise anybody could take over an account by registering the same address upstream.
+async fn link_identity(
+    db: &Database,
+    provider: String,
+    claims: IdTokenClaims,
+) -> Result<User, HttpError> {
+    db.transaction(move |conn| {
+        let linked = user_identities::table
+            .inner_join(users::table)
+            .filter(user_identities::provider.eq(&provider))
+            .filter(user_identities::subject.eq(&claims.sub))
+            .select(User::as_select())
+            .first::<User>(conn)
+            .optional()?;
+        if let Some(user) = linked {
+            if user.deleted_at.is_some() {
+                return Err(HttpError::unauthorized("USER_DELETED").into());
+            }
+            return Ok(user);
+        }
+
+        let email = claims
+            .email
+            .clone()
+            .ok_or_else(|| HttpError::unauthorized("OIDC_EMAIL_MISSING"))?
+            .to_lowercase();
+        let existing = users::table
+            .filter(users::email.eq(&email))
+            .filter(users::deleted_at.is_null())
+            .select(User::as_select())
+            .first::<User>(conn)
+            .optional()?;
+
+        let user = match existing {
+            Some(user) if claims.email_verified == Some(true) => user,
+            Some(_) => {
+                return Err(
+                    HttpError::unique_constraint_violation("OIDC_EMAIL_NOT_VERIFIED").into(),
+                )
+            }
+            None => {
+                let full_name = claims
+                    .name
+                    .clone()
+                    .unwrap_or_else(|| email.split('@').next().unwrap_or_default().to_string());
+                let user = diesel::insert_into(users::table)
+                    .values(NewUser {
+                        id: generator::id() as i64,
+                        full_name,
+                        email: email.clone(),
+                        phone_number: None,
+                    })
+                    .returning(User::as_returning())
+                    .get_result(conn)?;
+                record_registered(conn, user.id, "oidc")?;
+                user
+            }
+        };
+
+        diesel::insert_into(user_identities::table)
+            .values(NewUserIdentity {
+                id: generator::id() as i64,
+                user_id: user.id,
+                provider: provider.clone(),
+                subject: claims.sub.clone(),
+                email: claims.email.clone(),
+            })
+            .execute_counted(conn)?;
+        Ok(user)
+    })
+    .await
+    .map_err(HttpError::from)
+}
+
+// Magic link tokens get their own key so they can never pass as access tokens
+fn magic_link_secret(config: &Config) -> Vec<u8> {
+    format!("{}:magic_link", config.secret).into_bytes()
+}
+
+/// Queue a single use login link for the given address.
+///
+/// The response never reveals whether the address belongs to an account: the link is only
+/// mailed to existing users, by a background job so no SMTP exchange or failure shows up
+/// in the request, but every request counts towards the per email rate limit.
+pub async fn request_magic_link(
+    state: &AppState,
+    email: &str,
+) -> Result<(), HttpError> {
+    let email = email.trim().to_lowercase();
+    let link_id = generator::id() as i64;
+    let ttl = chrono::Duration::seconds(state.env.magic_link_ttl as i64);
+    let rate_limit = state.env.magic_link_rate_limit;
+
+    state
+        .db
+        .transaction(move |conn| {
+            // Concurrent requests for one address queue here, so they cannot all pass the count
+            sql_query("SELECT pg_advisory_xact_lock($1, hashtext($2))")
+                .bind::<Integer, _>(constant::MAGIC_LINK_LOCK_CLASS)
+                .bind::<Text, _>(&email)
+                .execute_counted(conn)?;
+            let recent: i64 = magic_links::table
+                .filter(magic_links::email.eq(&email))
+                .filter(magic_links::created_at.gt(Utc::now() - chrono::Duration::hours(1)))
+                .count()
+                .get_result(conn)?;
+            if recent >= rate_limit {
+                return Err(HttpError::too_many_requests("MAGIC_LINK_RATE_LIMITED").into());
+            }
+
+            diesel::insert_into(magic_links::table)
+                .values(NewMagicLink {
+                    id: link_id,
+                    email: email.clone(),
+                    expires_at: Utc::now() + ttl,
+                })
+                .execute_counted(conn)?;
+
+            let known: bool = diesel::select(diesel::dsl::exists(
+                users::table
+                    .filter(users::email.eq(&email))
+                    .filter(users::deleted_at.is_null()),
+            ))
+            .get_result(conn)?;
+            if known {
+                jobs::enqueue(conn, &MagicLinkEmailJob { link_id })?;
+            } else {
+                tracing::info!("MAGIC_LINK_UNKNOWN_EMAIL");
+            }
+            Ok(())
+        })
+        .await
+        .map_err(HttpError::from)
+}
+
+#[async_trait]
+impl Job for MagicLinkEmailJob {
+    const JOB_TYPE: &'static str = "auth.magic_link_email";
+    // The link expires within minutes, later retries would mail a dead link
+    const MAX_ATTEMPTS: i32 = 3;
+
+    async fn run(
+        self,
+        state: Arc<AppState>,
+    ) -> anyhow::Result<()> {
+        let link_id = self.link_id;
+        let recipient = state
+            .db
+            .transaction(move |conn| {
+                Ok(magic_links::table
+                    .find(link_id)
+                    .inner_join(users::table.on(users::email.eq(magic_links::email)))
+                    .filter(magic_links::consumed_at.is_null())
+                    .filter(magic_links::expires_at.gt(Utc::now()))
+                    .filter(users::deleted_at.is_null())
+                    .select((magic_links::expires_at, users::email, users::full_name))
+                    .first::<(DateTime<Utc>, String, String)>(conn)
+                    .optional()?)
+            })
+            .await?;
+        let Some((expires_at, email, full_name)) = recipient else {
+            tracing::info!(link_id, "MAGIC_LINK_EXPIRED_BEFORE_SEND");
+            return Ok(());
+        };
+
+        // The token must not outlive the link it stands for
+        let ttl = expires_at - Utc::now();
+        let token = token::create_token_with_ttl(
+            format!("{}|{}", link_id, email),
+            &magic_link_secret(&state.env),
+            ttl,
+        )?;
+        let link = format!(
+            "{}/api/v1/auth/magic-link/callback?token={}",
+            state.env.app_url.trim_end_matches('/'),
+            token
+        );
+        let body = format!(
+            "Hi {},\n\n\
+             Use the link below to sign in. It expires in {} minutes and can only be used once.\n\n\
+             {}\n\n\
+             If you did not request this email you can ignore it.\n",
+            full_name,
+            ttl.num_minutes().max(1),
+            link
+        );
+
+        Mailer::from_config(&state.env.mail_smtp, &state.env.mail_from)?
+            .send(&email, "Your sign-in link", body)
+            .await?;
+        Ok(())
+    }
+}
+
+/// Consume a magic link and issue a normal token pair.
+pub async fn consume_magic_link(
+    state: &AppState,
+    magic_token: &str,
+) -> Result<AuthToken, HttpError> {
+    let (link_id, email) = token::decode_token(magic_token, &magic_link_secret(&state.env))
+        .map_err(|_| HttpError::unauthorized("MAGIC_LINK_INVALID"))?;
+    let link_id: i64 = link_id
+        .parse()
+        .map_err(|_| HttpError::unauthorized("MAGIC_LINK_INVALID"))?;
+
+    let user = state
+        .db
+        .transaction(move |conn| {
+            // Mark as consumed only if still unused, a replayed link updates nothing
+            let consumed = diesel::update(magic_links::table.find(link_id))
+                .filter(magic_links::email.eq(&email))
+                .filter(magic_links::consumed_at.is_null())
+                .filter(magic_links::expires_at.gt(Utc::now()))
+                .set(magic_links::consumed_at.eq(Utc::now()))
+                .execute_counted(conn)?;
+            if consumed == 0 {
+                return Err(HttpError::unauthorized("MAGIC_LINK_INVALID").into());
+            }
+
+            Ok(users::table
+                .filter(users::email.eq(&email))
+                .filter(users::deleted_at.is_null())
+                .select(User::as_select())
+                .first::<User>(conn)
+                .optional()?)
+        })
+        .await?
+        .ok_or_else(|| HttpError::unauthorized("MAGIC_LINK_INVALID"))?;
+
+    issue_tokens(&state.env, &user)
+}
+
+/// Exchange a refresh token for a new token pair.
+pub async fn refresh_tokens(
+    state: &AppState,
+    refresh_token: &str,
+) -> Result<AuthToken, HttpError> {
+    let (user_id, _email) =
+        token::decode_token(refresh_token, state.env.refresh_secret.as_bytes())?;
+    let user_id: i64 = user_id
+        .parse()
+        .map_err(|_| HttpError::unauthorized("INVALID_TOKEN"))?;
+
+    // The account may have been removed since the token was issued
+    let user = state
+        .db
+        .execute(move |conn| {
+            Ok(users::table
+                .find(user_id)
+                .filter(users::deleted_at.is_null())
+                .select(User::as_select())
+                .first::<User>(conn)
+                .optional()?)
+        })
+        .await
+        .map_err(|err| {
+            tracing::error!(error = %err, "REFRESH_TOKEN_USER_LOOKUP_FAILED");
+            HttpError::server_error("REFRESH_TOKEN_FAILED")
+        })?
+        .ok_or_else(|| HttpError::unauthorized("INVALID_TOKEN"))?;
+
+    issue_tokens(&state.env, &user)
+}
+
+/// Issue an access/refresh token pair for the given user.
+pub fn issue_tokens(
+    config: &Config,
+    user: &User,
+) -> Result<AuthToken, HttpError> {
+    let subject = format!("{}|{}", user.id, user.email);
+    let access_token = token::create_token(subject.clone(), config.secret.as_bytes())
+        .map_err(|_| HttpError::server_error("TOKEN_CREATION_FAILED"))?;
+    let refresh_token = token::create_refresh_token(subject, config.refresh_secret.as_bytes())
+        .map_err(|_| HttpError::server_error("TOKEN_CREATION_FAILED"))?;
+    Ok(AuthToken {
+        access_token,
+        refresh_token,
+        token_type: "Bearer".to_string(),
+        expires_in: token::ACCESS_TOKEN_TTL_SECS,
+    })
+}
@@ -0,0 +1,31 @@
+pub mod auth_controller;
+pub mod auth_model;
+pub mod auth_service;
+use crate::AppState;
+use axum::{
+    routing::{get, post},
+    Router,
+};
+use std::sync::Arc;
+
+// Define Routes
+pub struct Routes;
+impl Routes {
+    pub fn index() -> Router<Arc<AppState>> {
+        Router::new()
+            .route(
+                "/oidc/:provider/authorize",
+                get(auth_controller::oidc_authorize),
+            )
+            .route(
+                "/oidc/:provider/callback",
+                get(auth_controller::oidc_callback),
+            )
+            .route("/refresh", post(auth_controller::refresh_token))
+            .route("/magic-link", post(auth_controller::request_magic_link))
+            .route(
+                "/magic-link/callback",
+                get(auth_controller::magic_link_callback),
+            )
+    }
+}
@@ -0,0 +1,135 @@
+use crate::{
+    constant,
+    middlewares::tenant_middlewares::TenantMember,
+    modules::auth::auth_model::AuthToken,
+    utils::{errors::HttpError, extractor::BodyJson, responses::HttpResponse},
+    AppState,
+};
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use std::sync::Arc;
+
+use super::{
+    invitation_model::{AcceptInvitationRequest, CreateInvitationRequest, InvitationData},
+    invitation_service,
+};
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/organizations/current/invitations",
+    params(
+        ("X-Tenant-Id" = Option<String>, Header, description = "Organization id or slug, unless resolved from the token or subdomain")
+    ),
+    responses(
+        (status = 200, description = "Open invitations, expired ones included", body = [InvitationData]),
+        (status = 403, description = "Requires admin")
+    ),
+    security(("bearer_auth" = [])),
+    tag = "invitations"
+)]
+pub async fn list_invitations(
+    State(state): State<Arc<AppState>>,
+    member: TenantMember,
+) -> Result<HttpResponse<Vec<InvitationData>>, HttpError> {
+    member.ensure_role(constant::MEMBER_ROLE_ADMIN)?;
+    let invitations = invitation_service::list_invitations(&state.db, member.tenant.id).await?;
+    let data = invitations.into_iter().map(InvitationData::from).collect();
+    Ok(HttpResponse::ok(data, "INVITATIONS_FETCHED"))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/organizations/current/invitations",
+    params(
+        ("X-Tenant-Id" = Option<String>, Header, description = "Organization id or slug, unless resolved from the token or subdomain")
+    ),
+    request_body = CreateInvitationRequest,
+    responses(
+        (status = 201, description = "Invitation created and emailed", body = InvitationData),
+        (status = 400, description = "Invalid request body"),
+        (status = 403, description = "Requires admin, or owner to invite an owner"),
+        (status = 409, description = "Already a member, or an invitation is already open")
+    ),
+    security(("bearer_auth" = [])),
+    tag = "invitations"
+)]
+pub async fn create_invitation(
+    State(state): State<Arc<AppState>>,
+    member: TenantMember,
+    BodyJson(body): BodyJson<CreateInvitationRequest>,
+) -> Result<HttpResponse<InvitationData>, HttpError> {
+    let invitation = invitation_service::create_invitation(&state, &member, body).await?;
+    Ok(HttpResponse::created(invitation.into(), "INVITATION_SENT"))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/organizations/current/invitations/{id}/resend",
+    params(
+        ("id" = i64, Path, description = "Invitation id"),
+        ("X-Tenant-Id" = Option<String>, Header, description = "Organization id or slug, unless resolved from the token or subdomain")
+    ),
+    responses(
+        (status = 200, description = "Invitation extended and emailed again", body = InvitationData),
+        (status = 403, description = "Requires admin, or owner for an owner invitation"),
+        (status = 404, description = "No open invitation with this id")
+    ),
+    security(("bearer_auth" = [])),
+    tag = "invitations"
+)]
+pub async fn resend_invitation(
+    State(state): State<Arc<AppState>>,
+    member: TenantMember,
+    Path(invitation_id): Path<i64>,
+) -> Result<HttpResponse<InvitationData>, HttpError> {
+    let invitation = invitation_service::resend_invitation(&state, &member, invitation_id).await?;
+    Ok(HttpResponse::ok(invitation.into(), "INVITATION_SENT"))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/organizations/current/invitations/{id}",
+    params(
+        ("id" = i64, Path, description = "Invitation id"),
+        ("X-Tenant-Id" = Option<String>, Header, description = "Organization id or slug, unless resolved from the token or subdomain")
+    ),
+    responses(
+        (status = 200, description = "Invitation revoked"),
+        (status = 403, description = "Requires admin, or owner for an owner invitation"),
+        (status = 404, description = "No open invitation with this id")
+    ),
+    security(("bearer_auth" = [])),
+    tag = "invitations"
+)]
+pub async fn revoke_invitation(
+    State(state): State<Arc<AppState>>,
+    member: TenantMember,
+    Path(invitation_id): Path<i64>,
+) -> Result<HttpResponse<()>, HttpError> {
+    invitation_service::revoke_invitation(&state.db, &member, invitation_id).await?;
+    Ok(HttpResponse::new(
+        "INVITATION_REVOKED",
+        StatusCode::OK,
+        None,
+    ))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/invitations/accept",
+    request_body = AcceptInvitationRequest,
+    responses(
+        (status = 200, description = "Joined the organization, the access token is scoped to it", body = AuthToken),
+        (status = 400, description = "Full name required to create the account"),
+        (status = 401, description = "Invalid, revoked or already accepted invitation"),
+        (status = 410, description = "Invitation expired, ask for it to be resent")
+    ),
+    tag = "invitations"
+)]
+pub async fn accept_invitation(
+    State(state): State<Arc<AppState>>,
+    BodyJson(body): BodyJson<AcceptInvitationRequest>,
+) -> Result<HttpResponse<AuthToken>, HttpError> {
+    let tokens = invitation_service::accept_invitation(&state, body).await?;
+    Ok(HttpResponse::ok(tokens, "INVITATION_ACCEPTED"))
+}
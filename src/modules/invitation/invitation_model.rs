@@ -0,0 +1,84 @@
+use crate::modules::organization::organization_model::validate_member_role;
+use crate::schema::table::invitations;
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use validator::Validate;
+
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct InvitationData {
+    pub invitation_id: String,
+    pub email: String,
+    pub role: String,
+    /// Missing once the inviting account is gone
+    pub invited_by: Option<String>,
+    pub expires_at: DateTime<Utc>,
+    /// Expired invitations can be resent, which extends them
+    pub expired: bool,
+    pub sent_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<Invitation> for InvitationData {
+    fn from(invitation: Invitation) -> Self {
+        InvitationData {
+            invitation_id: invitation.id.to_string(),
+            email: invitation.email,
+            role: invitation.role,
+            invited_by: invitation.invited_by.map(|id| id.to_string()),
+            expires_at: invitation.expires_at,
+            expired: invitation.expires_at <= Utc::now(),
+            sent_at: invitation.sent_at,
+            created_at: invitation.created_at,
+        }
+    }
+}
+
+#[derive(Deserialize, Validate, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateInvitationRequest {
+    #[validate(email(message = "Invalid email address"), length(max = 255))]
+    pub email: String,
+    /// owner, admin or member
+    #[validate(custom(function = "validate_member_role"))]
+    pub role: String,
+}
+
+#[derive(Deserialize, Validate, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AcceptInvitationRequest {
+    #[validate(length(min = 1, message = "Token is required"))]
+    pub token: String,
+    /// Required when no account exists for the invited email yet
+    #[validate(length(min = 1, max = 255, message = "Full name is required"))]
+    pub full_name: Option<String>,
+}
+
+#[derive(Debug, Clone, Queryable, Selectable)]
+#[diesel(table_name = invitations)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Invitation {
+    pub id: i64,
+    pub organization_id: i64,
+    pub email: String,
+    pub role: String,
+    pub invited_by: Option<i64>,
+    pub expires_at: DateTime<Utc>,
+    pub accepted_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub sent_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = invitations)]
+pub struct NewInvitation {
+    pub id: i64,
+    pub organization_id: i64,
+    pub email: String,
+    pub role: String,
+    pub invited_by: Option<i64>,
+    pub expires_at: DateTime<Utc>,
+}
@@ -0,0 +1,648 @@
+use super::invitation_model::{
+    AcceptInvitationRequest, CreateInvitationRequest, Invitation, NewInvitation,
+};
+use crate::config::Config;
+use crate::constant;
+use crate::database::instrumentation::CountedRunQueryDsl;
+use crate::database::Database;
+use crate::middlewares::tenant_middlewares::TenantMember;
+use crate::modules::auth::auth_model::AuthToken;
+use crate::modules::organization::organization_model::NewOrganizationMember;
+use crate::modules::organization::organization_service::tenant_tokens;
+use crate::modules::user::user_model::{NewUser, User};
+use crate::modules::user::user_service::record_registered;
+use crate::schema::table::{invitations, organization_members, organizations, users};
+use crate::tenant_scoped;
+use crate::utils::errors::HttpError;
+use crate::utils::mailer::Mailer;
+use crate::utils::{generator, token};
+use crate::AppState;
+use axum::http::StatusCode;
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+
+// Translate database failures, an open invitation per email is the only unique constraint
+fn database_error(err: anyhow::Error) -> HttpError {
+    match err.downcast_ref::<DieselError>() {
+        Some(DieselError::NotFound) => HttpError::not_found("INVITATION_NOT_FOUND"),
+        Some(DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => {
+            HttpError::unique_constraint_violation("INVITATION_ALREADY_PENDING")
+        }
+        _ => err.into(),
+    }
+}
+
+// Invitation tokens get their own key so they can never pass as access tokens
+fn invitation_secret(config: &Config) -> Vec<u8> {
+    format!("{}:invitation", config.secret).into_bytes()
+}
+
+fn expires_at(config: &Config) -> DateTime<Utc> {
+    Utc::now() + chrono::Duration::seconds(config.invitation_ttl as i64)
+}
+
+// Admins invite, and only owners hand out ownership like when changing a member's role
+fn ensure_can_grant(
+    actor: &TenantMember,
+    role: &str,
+) -> Result<(), HttpError> {
+    actor.ensure_role(constant::MEMBER_ROLE_ADMIN)?;
+    if role == constant::MEMBER_ROLE_OWNER {
+        actor.ensure_role(constant::MEMBER_ROLE_OWNER)?;
+    }
+    Ok(())
+}
+
+// Lock an open invitation of the tenant, expired ones included so they can be resent
+fn open_invitation(
+    conn: &mut PgConnection,
+    tenant_id: i64,
+    invitation_id: i64,
+) -> QueryResult<Invitation> {
+    invitations::table
+        .find(invitation_id)
+        .filter(invitations::organization_id.eq(tenant_id))
+        .filter(invitations::accepted_at.is_null())
+        .filter(invitations::revoked_at.is_null())
+        .select(Invitation::as_select())
+        .for_update()
+        .first(conn)
+}
+
+/// Invite an email address to the tenant and send the invitation email.
+pub async fn create_invitation(
+    state: &AppState,
+    actor: &TenantMember,
+    payload: CreateInvitationRequest,
+) -> Result<Invitation, HttpError> {
+    ensure_can_grant(actor, &payload.role)?;
+    let tenant_id = actor.tenant.id;
+    let new_invitation = NewInvitation {
+        id: generator::id() as i64,
+        organization_id: tenant_id,
+        email: payload.email.trim().to_lowercase(),
+        role: payload.role,
+        invited_by: Some(actor.user.user_id),
+        expires_at: expires_at(&state.env),
+    };
+
+    let invitation = state
+        .db
+        .tenant_transaction(tenant_id, move |conn| {
+            let is_member = diesel::select(diesel::dsl::exists(
+                tenant_scoped!(
+                    organization_members::table,
+                    organization_members::organization_id,
+                    tenant_id
+                )
+                .inner_join(users::table)
+                .filter(users::email.eq(&new_invitation.email))
+                .filter(users::deleted_at.is_null()),
+            ))
+            .get_result::<bool>(conn)?;
+            if is_member {
+                return Err(HttpError::unique_constraint_violation("MEMBER_ALREADY_EXISTS").into());
+            }
+            Ok(diesel::insert_into(invitations::table)
+                .values(&new_invitation)
+                .returning(Invitation::as_returning())
+                .get_result(conn)?)
+        })
+        .await
+        .map_err(database_error)?;
+
+    send_invitation(state, &invitation).await?;
+    Ok(invitation)
+}
+
+/// Open invitations of the tenant, newest first.
+pub async fn list_invitations(
+    db: &Database,
+    tenant_id: i64,
+) -> Result<Vec<Invitation>, HttpError> {
+    db.tenant_transaction(tenant_id, move |conn| {
+        Ok(
+            tenant_scoped!(invitations::table, invitations::organization_id, tenant_id)
+                .filter(invitations::accepted_at.is_null())
+                .filter(invitations::revoked_at.is_null())
+                .select(Invitation::as_select())
+                .order((invitations::created_at.desc(), invitations::id.desc()))
+                .load_counted(conn)?,
+        )
+    })
+    .await
+    .map_err(database_error)
+}
+
+/// Extend an open invitation by another full lifetime and email it again.
+pub async fn resend_invitation(
+    state: &AppState,
+    actor: &TenantMember,
+    invitation_id: i64,
+) -> Result<Invitation, HttpError> {
+    actor.ensure_role(constant::MEMBER_ROLE_ADMIN)?;
+    let tenant_id = actor.tenant.id;
+    let actor = actor.clone();
+    let expires_at = expires_at(&state.env);
+    let invitation = state
+        .db
+        .tenant_transaction(tenant_id, move |conn| {
+            extend_invitation(conn, &actor, invitation_id, expires_at)
+        })
+        .await
+        .map_err(database_error)?;
+
+    send_invitation(state, &invitation).await?;
+    Ok(invitation)
+}
+
+fn extend_invitation(
+    conn: &mut PgConnection,
+    actor: &TenantMember,
+    invitation_id: i64,
+    expires_at: DateTime<Utc>,
+) -> anyhow::Result<Invitation> {
+    let invitation = open_invitation(conn, actor.tenant.id, invitation_id)?;
+    ensure_can_grant(actor, &invitation.role)?;
+    Ok(diesel::update(invitations::table.find(invitation.id))
+        .set((
+            invitations::expires_at.eq(expires_at),
+            invitations::sent_at.eq(Utc::now()),
+        ))
+        .returning(Invitation::as_returning())
+        .get_result(conn)?)
+}
+
+/// Revoke an open invitation, its link stops working immediately.
+pub async fn revoke_invitation(
+    db: &Database,
+    actor: &TenantMember,
+    invitation_id: i64,
+) -> Result<(), HttpError> {
+    actor.ensure_role(constant::MEMBER_ROLE_ADMIN)?;
+    let tenant_id = actor.tenant.id;
+    let actor = actor.clone();
+    db.tenant_transaction(tenant_id, move |conn| {
+        revoke_open_invitation(conn, &actor, invitation_id)
+    })
+    .await
+    .map_err(database_error)
+}
+
+fn revoke_open_invitation(
+    conn: &mut PgConnection,
+    actor: &TenantMember,
+    invitation_id: i64,
+) -> anyhow::Result<()> {
+    let invitation = open_invitation(conn, actor.tenant.id, invitation_id)?;
+    ensure_can_grant(actor, &invitation.role)?;
+    diesel::update(invitations::table.find(invitation.id))
+        .set(invitations::revoked_at.eq(Utc::now()))
+        .execute_counted(conn)?;
+    Ok(())
+}
+
+async fn send_invitation(
+    state: &AppState,
+    invitation: &Invitation,
+) -> Result<(), HttpError> {
+    let organization_id = invitation.organization_id;
+    let invited_by = invitation.invited_by;
+    let (organization_name, inviter_name) = state
+        .db
+        .execute(move |conn| {
+            let organization_name = organizations::table
+                .find(organization_id)
+                .select(organizations::name)
+                .first::<String>(conn)?;
+            let inviter_name = match invited_by {
+                Some(user_id) => users::table
+                    .find(user_id)
+                    .select(users::full_name)
+                    .first::<String>(conn)
+                    .optional()?,
+                None => None,
+            };
+            Ok((organization_name, inviter_name))
+        })
+        .await
+        .map_err(database_error)?;
+
+    let ttl = invitation.expires_at - Utc::now();
+    let token = token::create_token_with_ttl(
+        format!("{}|{}", invitation.id, invitation.email),
+        &invitation_secret(&state.env),
+        ttl,
+    )
+    .map_err(|_| HttpError::server_error("TOKEN_CREATION_FAILED"))?;
+    let url = &state.env.invitation_url;
+    let separator = if url.contains('?') { '&' } else { '?' };
+    let link = format!("{url}{separator}token={token}");
+    let body = format!(
+        "Hi,\n\n\
+         {} invited you to join {} as {}.\n\n\
+         Use the link below to accept, it expires in {} days.\n\n\
+         {}\n\n\
+         If you were not expecting this invitation you can ignore it.\n",
+        inviter_name.as_deref().unwrap_or("A team member"),
+        organization_name,
+        invitation.role,
+        // Rounded up, a fresh 7 day invitation is a few seconds short of 7 days
+        ((ttl.num_hours() + 23) / 24).max(1),
+        link
+    );
+
+    Mailer::from_config(&state.env.mail_smtp, &state.env.mail_from)
+        .map_err(|err| {
+            tracing::error!(error = %err, "MAILER_NOT_CONFIGURED");
+            HttpError::server_error("MAILER_NOT_CONFIGURED")
+        })?
+        .send(
+            &invitation.email,
+            &format!("You are invited to join {organization_name}"),
+            body,
+        )
+        .await
+        .map_err(|err| {
+            // The invitation stays open, an admin can resend it
+            tracing::error!(error = %err, invitation_id = invitation.id, "INVITATION_SEND_FAILED");
+            HttpError::server_error("INVITATION_SEND_FAILED")
+        })
+}
+
+/// Accept an invitation, creating the account when the email has none yet, and issue a
+/// token pair scoped to the organization.
+pub async fn accept_invitation(
+    state: &AppState,
+    payload: AcceptInvitationRequest,
+) -> Result<AuthToken, HttpError> {
+    let (invitation_id, email) =
+        token::decode_token(&payload.token, &invitation_secret(&state.env))
+            .map_err(|_| HttpError::unauthorized("INVITATION_INVALID"))?;
+    let invitation_id: i64 = invitation_id
+        .parse()
+        .map_err(|_| HttpError::unauthorized("INVITATION_INVALID"))?;
+    let full_name = payload.full_name.map(|name| name.trim().to_string());
+
+    // The organization is not known yet, so the lookup crosses tenants
+    let organization_id = state
+        .db
+        .unscoped_transaction(move |conn| {
+            Ok(invitations::table
+                .find(invitation_id)
+                .select(invitations::organization_id)
+                .first::<i64>(conn)
+                .optional()?)
+        })
+        .await
+        .map_err(database_error)?
+        .ok_or_else(|| HttpError::unauthorized("INVITATION_INVALID"))?;
+
+    let user = state
+        .db
+        .tenant_transaction(organization_id, move |conn| {
+            accept_open_invitation(conn, invitation_id, &email, full_name)
+        })
+        .await?;
+
+    tenant_tokens(&state.env, user.id, &user.email, organization_id)
+}
+
+// Join the organization of an open invitation, creating the account when needed
+fn accept_open_invitation(
+    conn: &mut PgConnection,
+    invitation_id: i64,
+    email: &str,
+    full_name: Option<String>,
+) -> anyhow::Result<User> {
+    // Locked so a link clicked twice cannot accept twice
+    let invitation = invitations::table
+        .find(invitation_id)
+        .filter(invitations::email.eq(email))
+        .filter(invitations::accepted_at.is_null())
+        .filter(invitations::revoked_at.is_null())
+        .select(Invitation::as_select())
+        .for_update()
+        .first(conn)
+        .optional()?
+        .ok_or_else(|| HttpError::unauthorized("INVITATION_INVALID"))?;
+    if invitation.expires_at <= Utc::now() {
+        return Err(HttpError::new("INVITATION_EXPIRED", StatusCode::GONE).into());
+    }
+
+    let existing = users::table
+        .filter(users::email.eq(&invitation.email))
+        .filter(users::deleted_at.is_null())
+        .select(User::as_select())
+        .first::<User>(conn)
+        .optional()?;
+    let user = match existing {
+        Some(user) => user,
+        None => {
+            let full_name = full_name
+                .filter(|name| !name.is_empty())
+                .ok_or_else(|| HttpError::bad_request("FULL_NAME_REQUIRED"))?;
+            let user = diesel::insert_into(users::table)
+                .values(NewUser {
+                    id: generator::id() as i64,
+                    full_name,
+                    email: invitation.email.clone(),
+                    phone_number: None,
+                })
+                .returning(User::as_returning())
+                .get_result(conn)?;
+            record_registered(conn, user.id, "invitation")?;
+            user
+        }
+    };
+
+    // An existing member keeps their current role
+    diesel::insert_into(organization_members::table)
+        .values(NewOrganizationMember {
+            organization_id: invitation.organization_id,
+            user_id: user.id,
+            role: invitation.role.clone(),
+        })
+        .on_conflict_do_nothing()
+        .execute_counted(conn)?;
+    diesel::update(invitations::table.find(invitation.id))
+        .set(invitations::accepted_at.eq(Utc::now()))
+        .execute_counted(conn)?;
+    Ok(user)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{set_tenant, test_connection};
+    use crate::middlewares::auth_middlewares::AuthUser;
+    use crate::middlewares::tenant_middlewares::Tenant;
+    use crate::modules::organization::organization_model::NewOrganization;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    fn member(
+        tenant_id: i64,
+        user_id: i64,
+        role: &str,
+    ) -> TenantMember {
+        TenantMember {
+            tenant: Tenant {
+                id: tenant_id,
+                slug: format!("t{tenant_id}"),
+            },
+            user: AuthUser {
+                user_id,
+                email: format!("{user_id}@example.com"),
+            },
+            role: role.to_string(),
+        }
+    }
+
+    fn status(err: anyhow::Error) -> StatusCode {
+        database_error(err).status
+    }
+
+    // An organization owned by a new user, returns the tenant and the owner's id
+    fn organization(conn: &mut PgConnection) -> (i64, i64) {
+        let tenant_id = generator::id() as i64;
+        let owner_id = generator::id() as i64;
+        diesel::insert_into(organizations::table)
+            .values(NewOrganization {
+                id: tenant_id,
+                name: "Test".to_string(),
+                slug: format!("t{tenant_id}"),
+            })
+            .execute_counted(conn)
+            .unwrap();
+        diesel::insert_into(users::table)
+            .values(NewUser {
+                id: owner_id,
+                full_name: "Owner".to_string(),
+                email: format!("{owner_id}@example.com"),
+                phone_number: None,
+            })
+            .execute_counted(conn)
+            .unwrap();
+        set_tenant(conn, tenant_id).unwrap();
+        diesel::insert_into(organization_members::table)
+            .values(NewOrganizationMember {
+                organization_id: tenant_id,
+                user_id: owner_id,
+                role: constant::MEMBER_ROLE_OWNER.to_string(),
+            })
+            .execute_counted(conn)
+            .unwrap();
+        (tenant_id, owner_id)
+    }
+
+    fn invite(
+        conn: &mut PgConnection,
+        tenant_id: i64,
+        role: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Invitation {
+        let id = generator::id() as i64;
+        diesel::insert_into(invitations::table)
+            .values(NewInvitation {
+                id,
+                organization_id: tenant_id,
+                email: format!("invitee-{id}@example.com"),
+                role: role.to_string(),
+                invited_by: None,
+                expires_at,
+            })
+            .returning(Invitation::as_returning())
+            .get_result(conn)
+            .unwrap()
+    }
+
+    fn in_a_day() -> DateTime<Utc> {
+        Utc::now() + chrono::Duration::days(1)
+    }
+
+    #[test]
+    fn only_owners_grant_ownership() {
+        let owner = member(1, 1, constant::MEMBER_ROLE_OWNER);
+        let admin = member(1, 2, constant::MEMBER_ROLE_ADMIN);
+        let plain = member(1, 3, constant::MEMBER_ROLE_MEMBER);
+
+        assert!(ensure_can_grant(&owner, constant::MEMBER_ROLE_OWNER).is_ok());
+        assert!(ensure_can_grant(&admin, constant::MEMBER_ROLE_ADMIN).is_ok());
+        assert!(ensure_can_grant(&admin, constant::MEMBER_ROLE_MEMBER).is_ok());
+        assert_eq!(
+            ensure_can_grant(&admin, constant::MEMBER_ROLE_OWNER)
+                .unwrap_err()
+                .status,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            ensure_can_grant(&plain, constant::MEMBER_ROLE_MEMBER)
+                .unwrap_err()
+                .status,
+            StatusCode::FORBIDDEN
+        );
+    }
+
+    #[test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    fn accepts_once_and_not_after_expiry() {
+        let mut conn = test_connection();
+        conn.begin_test_transaction().unwrap();
+        let (tenant_id, _) = organization(&mut conn);
+
+        let invitation = invite(
+            &mut conn,
+            tenant_id,
+            constant::MEMBER_ROLE_ADMIN,
+            in_a_day(),
+        );
+        let name = Some("Invitee".to_string());
+        let user =
+            accept_open_invitation(&mut conn, invitation.id, &invitation.email, name.clone())
+                .unwrap();
+        let role: String = organization_members::table
+            .find((tenant_id, user.id))
+            .select(organization_members::role)
+            .first(&mut conn)
+            .unwrap();
+        assert_eq!(role, constant::MEMBER_ROLE_ADMIN);
+        let again = accept_open_invitation(&mut conn, invitation.id, &invitation.email, name);
+        assert_eq!(status(again.unwrap_err()), StatusCode::UNAUTHORIZED);
+
+        let expired = invite(
+            &mut conn,
+            tenant_id,
+            constant::MEMBER_ROLE_MEMBER,
+            Utc::now() - chrono::Duration::seconds(1),
+        );
+        let err = accept_open_invitation(&mut conn, expired.id, &expired.email, None).unwrap_err();
+        assert_eq!(status(err), StatusCode::GONE);
+    }
+
+    #[test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    fn concurrent_accepts_join_once() {
+        // Committed, so the second connection sees the rows, and removed at the end
+        let mut conn = test_connection();
+        let (tenant_id, owner_id) = conn
+            .transaction::<_, DieselError, _>(|conn| Ok(organization(conn)))
+            .unwrap();
+        let invitation = conn
+            .transaction::<_, DieselError, _>(|conn| {
+                set_tenant(conn, tenant_id)?;
+                Ok(invite(
+                    conn,
+                    tenant_id,
+                    constant::MEMBER_ROLE_MEMBER,
+                    in_a_day(),
+                ))
+            })
+            .unwrap();
+
+        let (locked, wait_for_lock) = mpsc::channel();
+        let (id, email) = (invitation.id, invitation.email.clone());
+        let first = std::thread::spawn(move || {
+            let mut conn = test_connection();
+            conn.transaction::<_, anyhow::Error, _>(|conn| {
+                set_tenant(conn, tenant_id)?;
+                let user = accept_open_invitation(conn, id, &email, Some("First".into()))?;
+                locked.send(()).unwrap();
+                // Hold the row lock while the second accept waits on it
+                std::thread::sleep(Duration::from_millis(300));
+                Ok(user)
+            })
+        });
+        wait_for_lock.recv().unwrap();
+        let second = conn.transaction(|conn| {
+            set_tenant(conn, tenant_id)?;
+            accept_open_invitation(
+                conn,
+                invitation.id,
+                &invitation.email,
+                Some("Second".into()),
+            )
+        });
+
+        let user = first.join().unwrap().unwrap();
+        assert_eq!(status(second.unwrap_err()), StatusCode::UNAUTHORIZED);
+        let members = conn
+            .transaction::<i64, DieselError, _>(|conn| {
+                set_tenant(conn, tenant_id)?;
+                organization_members::table
+                    .filter(organization_members::organization_id.eq(tenant_id))
+                    .count()
+                    .get_result(conn)
+            })
+            .unwrap();
+        assert_eq!(members, 2);
+
+        diesel::delete(organizations::table.find(tenant_id))
+            .execute_counted(&mut conn)
+            .unwrap();
+        diesel::delete(users::table.filter(users::id.eq_any([owner_id, user.id])))
+            .execute_counted(&mut conn)
+            .unwrap();
+    }
+
+    #[test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    fn resends_and_revokes_open_invitations() {
+        let mut conn = test_connection();
+        conn.begin_test_transaction().unwrap();
+        let (tenant_id, owner_id) = organization(&mut conn);
+        let owner = member(tenant_id, owner_id, constant::MEMBER_ROLE_OWNER);
+        let admin = member(tenant_id, owner_id, constant::MEMBER_ROLE_ADMIN);
+
+        // Expired invitations can be resent, for another full lifetime
+        let expired = invite(
+            &mut conn,
+            tenant_id,
+            constant::MEMBER_ROLE_MEMBER,
+            Utc::now() - chrono::Duration::seconds(1),
+        );
+        let resent = extend_invitation(&mut conn, &admin, expired.id, in_a_day()).unwrap();
+        assert!(resent.expires_at > Utc::now());
+        assert!(
+            accept_open_invitation(&mut conn, resent.id, &resent.email, Some("A".into())).is_ok()
+        );
+        // Accepted invitations are no longer open
+        let err = extend_invitation(&mut conn, &admin, resent.id, in_a_day()).unwrap_err();
+        assert_eq!(status(err), StatusCode::NOT_FOUND);
+
+        // Ownership invitations are left to owners
+        let ownership = invite(
+            &mut conn,
+            tenant_id,
+            constant::MEMBER_ROLE_OWNER,
+            in_a_day(),
+        );
+        let err = revoke_open_invitation(&mut conn, &admin, ownership.id).unwrap_err();
+        assert_eq!(status(err), StatusCode::FORBIDDEN);
+        let err = extend_invitation(&mut conn, &admin, ownership.id, in_a_day()).unwrap_err();
+        assert_eq!(status(err), StatusCode::FORBIDDEN);
+        revoke_open_invitation(&mut conn, &owner, ownership.id).unwrap();
+        let err =
+            accept_open_invitation(&mut conn, ownership.id, &ownership.email, Some("B".into()))
+                .unwrap_err();
+        assert_eq!(status(err), StatusCode::UNAUTHORIZED);
+        let err = revoke_open_invitation(&mut conn, &owner, ownership.id).unwrap_err();
+        assert_eq!(status(err), StatusCode::NOT_FOUND);
+
+        // Another tenant's invitation is not found
+        let other = member(
+            generator::id() as i64,
+            owner_id,
+            constant::MEMBER_ROLE_OWNER,
+        );
+        let invitation = invite(
+            &mut conn,
+            tenant_id,
+            constant::MEMBER_ROLE_MEMBER,
+            in_a_day(),
+        );
+        let err = revoke_open_invitation(&mut conn, &other, invitation.id).unwrap_err();
+        assert_eq!(status(err), StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,32 @@
+pub mod invitation_controller;
+pub mod invitation_model;
+pub mod invitation_service;
+use crate::AppState;
+use axum::{
+    routing::{delete, get, post},
+    Router,
+};
+use std::sync::Arc;
+
+// Define Routes
+pub struct Routes;
+impl Routes {
+    pub fn index() -> Router<Arc<AppState>> {
+        Router::new().route("/accept", post(invitation_controller::accept_invitation))
+    }
+
+    // Managed by admins of the request tenant, nested under /organizations/current
+    pub fn tenant() -> Router<Arc<AppState>> {
+        Router::new()
+            .route(
+                "/",
+                get(invitation_controller::list_invitations)
+                    .post(invitation_controller::create_invitation),
+            )
+            .route("/:id", delete(invitation_controller::revoke_invitation))
+            .route(
+                "/:id/resend",
+                post(invitation_controller::resend_invitation),
+            )
+    }
+}
@@ -1,3 +1,7 @@
+pub mod auth;
+pub mod invitation;
+pub mod organization;
+pub mod privacy;
 pub mod user;
 use crate::docs::api_doc::ApiDoc;
 use crate::AppState;
@@ -11,7 +15,16 @@ pub struct AppRoute;
 impl AppRoute {
     pub fn register() -> Router<Arc<AppState>> {
         // Route Index
-        let route_index = Router::new().nest("/users", user::Routes::index());
+        let route_index = Router::new()
+            .nest("/auth", auth::Routes::index())
+            .nest("/users/me", privacy::Routes::index())
+            .nest("/users", user::Routes::index())
+            .nest("/organizations", organization::Routes::index())
+            .nest(
+                "/organizations/current/invitations",
+                invitation::Routes::tenant(),
+            )
+            .nest("/invitations", invitation::Routes::index());
         // Docs Route
         let openapi = ApiDoc::openapi();
         Router::new()
@@ -0,0 +1,39 @@
+pub mod organization_controller;
+pub mod organization_model;
+pub mod organization_service;
+use crate::AppState;
+use axum::{
+    routing::{get, post, put},
+    Router,
+};
+use std::sync::Arc;
+
+// Define Routes
+pub struct Routes;
+impl Routes {
+    pub fn index() -> Router<Arc<AppState>> {
+        Router::new()
+            .route(
+                "/",
+                get(organization_controller::list_organizations)
+                    .post(organization_controller::create_organization),
+            )
+            .route(
+                "/current",
+                get(organization_controller::get_current_organization),
+            )
+            .route(
+                "/current/members",
+                get(organization_controller::list_members),
+            )
+            .route(
+                "/current/members/:user_id",
+                put(organization_controller::update_member)
+                    .delete(organization_controller::remove_member),
+            )
+            .route(
+                "/:id/token",
+                post(organization_controller::issue_tenant_token),
+            )
+    }
+}
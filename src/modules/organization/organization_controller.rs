@@ -0,0 +1,189 @@
+use crate::{
+    constant,
+    middlewares::{auth_middlewares::AuthUser, tenant_middlewares::TenantMember},
+    modules::auth::auth_model::AuthToken,
+    utils::{errors::HttpError, extractor::BodyJson, responses::HttpResponse},
+    AppState,
+};
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use std::sync::Arc;
+
+use super::{
+    organization_model::{
+        CreateOrganizationRequest, MemberData, OrganizationData, UpdateMemberRequest,
+    },
+    organization_service,
+};
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/organizations",
+    responses(
+        (status = 200, description = "Organizations of the caller", body = [OrganizationData]),
+        (status = 401, description = "Missing or invalid token")
+    ),
+    security(("bearer_auth" = [])),
+    tag = "organizations"
+)]
+pub async fn list_organizations(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+) -> Result<HttpResponse<Vec<OrganizationData>>, HttpError> {
+    let organizations = organization_service::list_organizations(&state.db, auth.user_id).await?;
+    let data = organizations
+        .into_iter()
+        .map(|(organization, role)| OrganizationData::new(organization, role))
+        .collect();
+    Ok(HttpResponse::ok(data, "ORGANIZATIONS_FETCHED"))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/organizations",
+    request_body = CreateOrganizationRequest,
+    responses(
+        (status = 201, description = "Organization created, the caller is its owner", body = OrganizationData),
+        (status = 400, description = "Invalid request body"),
+        (status = 409, description = "Slug already taken")
+    ),
+    security(("bearer_auth" = [])),
+    tag = "organizations"
+)]
+pub async fn create_organization(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    BodyJson(body): BodyJson<CreateOrganizationRequest>,
+) -> Result<HttpResponse<OrganizationData>, HttpError> {
+    let organization =
+        organization_service::create_organization(&state.db, auth.user_id, body).await?;
+    Ok(HttpResponse::created(
+        OrganizationData::new(organization, constant::MEMBER_ROLE_OWNER.to_string()),
+        "ORGANIZATION_CREATED",
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/organizations/current",
+    params(
+        ("X-Tenant-Id" = Option<String>, Header, description = "Organization id or slug, unless resolved from the token or subdomain")
+    ),
+    responses(
+        (status = 200, description = "Organization the request acts on", body = OrganizationData),
+        (status = 400, description = "No tenant in the request"),
+        (status = 403, description = "Caller is not a member"),
+        (status = 404, description = "Unknown organization")
+    ),
+    security(("bearer_auth" = [])),
+    tag = "organizations"
+)]
+pub async fn get_current_organization(
+    State(state): State<Arc<AppState>>,
+    member: TenantMember,
+) -> Result<HttpResponse<OrganizationData>, HttpError> {
+    let organization = organization_service::get_organization(&state.db, member.tenant.id).await?;
+    Ok(HttpResponse::ok(
+        OrganizationData::new(organization, member.role),
+        "ORGANIZATION_FETCHED",
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/organizations/current/members",
+    params(
+        ("X-Tenant-Id" = Option<String>, Header, description = "Organization id or slug, unless resolved from the token or subdomain")
+    ),
+    responses(
+        (status = 200, description = "Members of the organization", body = [MemberData]),
+        (status = 400, description = "No tenant in the request"),
+        (status = 403, description = "Caller is not a member")
+    ),
+    security(("bearer_auth" = [])),
+    tag = "organizations"
+)]
+pub async fn list_members(
+    State(state): State<Arc<AppState>>,
+    member: TenantMember,
+) -> Result<HttpResponse<Vec<MemberData>>, HttpError> {
+    let members = organization_service::list_members(&state.db, member.tenant.id).await?;
+    let data = members.into_iter().map(MemberData::from).collect();
+    Ok(HttpResponse::ok(data, "MEMBERS_FETCHED"))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/organizations/current/members/{user_id}",
+    params(
+        ("user_id" = i64, Path, description = "User id"),
+        ("X-Tenant-Id" = Option<String>, Header, description = "Organization id or slug, unless resolved from the token or subdomain")
+    ),
+    request_body = UpdateMemberRequest,
+    responses(
+        (status = 200, description = "Role changed", body = MemberData),
+        (status = 400, description = "Invalid role"),
+        (status = 403, description = "Requires admin, or owner to grant or revoke ownership"),
+        (status = 404, description = "Not a member, users join through invitations"),
+        (status = 409, description = "The organization needs another owner first")
+    ),
+    security(("bearer_auth" = [])),
+    tag = "organizations"
+)]
+pub async fn update_member(
+    State(state): State<Arc<AppState>>,
+    member: TenantMember,
+    Path(user_id): Path<i64>,
+    BodyJson(body): BodyJson<UpdateMemberRequest>,
+) -> Result<HttpResponse<MemberData>, HttpError> {
+    let updated =
+        organization_service::set_member_role(&state.db, &member, user_id, body.role).await?;
+    Ok(HttpResponse::ok(updated.into(), "MEMBER_UPDATED"))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/organizations/current/members/{user_id}",
+    params(
+        ("user_id" = i64, Path, description = "User id, the caller's own id to leave"),
+        ("X-Tenant-Id" = Option<String>, Header, description = "Organization id or slug, unless resolved from the token or subdomain")
+    ),
+    responses(
+        (status = 200, description = "Member removed"),
+        (status = 403, description = "Requires admin, or owner to remove an owner"),
+        (status = 404, description = "Not a member"),
+        (status = 409, description = "The organization needs another owner first")
+    ),
+    security(("bearer_auth" = [])),
+    tag = "organizations"
+)]
+pub async fn remove_member(
+    State(state): State<Arc<AppState>>,
+    member: TenantMember,
+    Path(user_id): Path<i64>,
+) -> Result<HttpResponse<()>, HttpError> {
+    organization_service::remove_member(&state.db, &member, user_id).await?;
+    Ok(HttpResponse::new("MEMBER_REMOVED", StatusCode::OK, None))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/organizations/{id}/token",
+    params(
+        ("id" = i64, Path, description = "Organization id")
+    ),
+    responses(
+        (status = 200, description = "Token pair, the access token carries the tenant claim", body = AuthToken),
+        (status = 403, description = "Caller is not a member")
+    ),
+    security(("bearer_auth" = [])),
+    tag = "organizations"
+)]
+pub async fn issue_tenant_token(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Path(organization_id): Path<i64>,
+) -> Result<HttpResponse<AuthToken>, HttpError> {
+    let tokens = organization_service::issue_tenant_tokens(&state, &auth, organization_id).await?;
+    Ok(HttpResponse::ok(tokens, "TENANT_TOKEN_ISSUED"))
+}
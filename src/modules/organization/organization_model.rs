@@ -0,0 +1,148 @@
+use crate::constant;
+use crate::schema::table::{organization_members, organizations};
+use crate::utils::generator;
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use validator::{Validate, ValidationError};
+
+/// Organization as seen by one of its members.
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct OrganizationData {
+    pub organization_id: String,
+    pub name: String,
+    pub slug: String,
+    /// Role of the caller in the organization
+    pub role: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl OrganizationData {
+    pub fn new(
+        organization: Organization,
+        role: String,
+    ) -> Self {
+        OrganizationData {
+            organization_id: organization.id.to_string(),
+            name: organization.name,
+            slug: organization.slug,
+            role,
+            created_at: organization.created_at,
+            updated_at: organization.updated_at,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MemberData {
+    pub user_id: String,
+    pub full_name: String,
+    pub email: String,
+    pub role: String,
+    pub joined_at: DateTime<Utc>,
+}
+
+impl From<(OrganizationMember, String, String)> for MemberData {
+    fn from((member, full_name, email): (OrganizationMember, String, String)) -> Self {
+        MemberData {
+            user_id: member.user_id.to_string(),
+            full_name,
+            email,
+            role: member.role,
+            joined_at: member.created_at,
+        }
+    }
+}
+
+#[derive(Deserialize, Validate, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateOrganizationRequest {
+    #[validate(length(min = 1, max = 255, message = "Name is required"))]
+    pub name: String,
+    /// Lowercase letters, digits and hyphens, used as subdomain and in `X-Tenant-Id`
+    #[validate(custom(function = "validate_slug"))]
+    pub slug: String,
+}
+
+#[derive(Deserialize, Validate, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateMemberRequest {
+    /// owner, admin or member
+    #[validate(custom(function = "validate_member_role"))]
+    pub role: String,
+}
+
+// Slugs double as DNS labels, all digit slugs would be mistaken for organization ids
+fn validate_slug(slug: &str) -> Result<(), ValidationError> {
+    let valid = (3..=63).contains(&slug.len())
+        && slug
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-')
+        && !slug.starts_with('-')
+        && !slug.ends_with('-')
+        && !slug.bytes().all(|b| b.is_ascii_digit());
+    if !valid {
+        return Err(ValidationError::new("slug")
+            .with_message("Slug must be 3 to 63 lowercase letters, digits or hyphens".into()));
+    }
+    Ok(())
+}
+
+pub fn validate_member_role(role: &str) -> Result<(), ValidationError> {
+    if !constant::MEMBER_ROLES.contains(&role) {
+        return Err(ValidationError::new("role")
+            .with_message("Role must be one of owner, admin or member".into()));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Queryable, Selectable)]
+#[diesel(table_name = organizations)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Organization {
+    pub id: i64,
+    pub name: String,
+    pub slug: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = organizations)]
+pub struct NewOrganization {
+    pub id: i64,
+    pub name: String,
+    pub slug: String,
+}
+
+impl From<CreateOrganizationRequest> for NewOrganization {
+    fn from(payload: CreateOrganizationRequest) -> Self {
+        NewOrganization {
+            id: generator::id() as i64,
+            name: payload.name.trim().to_string(),
+            slug: payload.slug,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Queryable, Selectable)]
+#[diesel(table_name = organization_members)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct OrganizationMember {
+    pub organization_id: i64,
+    pub user_id: i64,
+    pub role: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = organization_members)]
+pub struct NewOrganizationMember {
+    pub organization_id: i64,
+    pub user_id: i64,
+    pub role: String,
+}
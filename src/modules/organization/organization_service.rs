@@ -0,0 +1,289 @@
+use super::organization_model::{
+    CreateOrganizationRequest, NewOrganization, NewOrganizationMember, Organization,
+    OrganizationMember,
+};
+use crate::config::Config;
+use crate::constant;
+use crate::database::instrumentation::CountedRunQueryDsl;
+use crate::database::Database;
+use crate::middlewares::auth_middlewares::AuthUser;
+use crate::middlewares::tenant_middlewares::TenantMember;
+use crate::modules::auth::auth_model::AuthToken;
+use crate::schema::table::{organization_members, organizations, users};
+use crate::tenant_scoped;
+use crate::utils::errors::HttpError;
+use crate::utils::token;
+use crate::AppState;
+use axum::http::StatusCode;
+use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+
+// Translate database failures, the slug is the only unique column organizations collide on
+fn database_error(err: anyhow::Error) -> HttpError {
+    match err.downcast_ref::<DieselError>() {
+        Some(DieselError::NotFound) => HttpError::not_found("ORGANIZATION_NOT_FOUND"),
+        Some(DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => {
+            HttpError::unique_constraint_violation("ORGANIZATION_SLUG_TAKEN")
+        }
+        _ => err.into(),
+    }
+}
+
+/// Create an organization owned by `user_id`.
+pub async fn create_organization(
+    db: &Database,
+    user_id: i64,
+    payload: CreateOrganizationRequest,
+) -> Result<Organization, HttpError> {
+    let new_organization = NewOrganization::from(payload);
+    db.tenant_transaction(new_organization.id, move |conn| {
+        let organization = diesel::insert_into(organizations::table)
+            .values(&new_organization)
+            .returning(Organization::as_returning())
+            .get_result(conn)?;
+        diesel::insert_into(organization_members::table)
+            .values(NewOrganizationMember {
+                organization_id: organization.id,
+                user_id,
+                role: constant::MEMBER_ROLE_OWNER.to_string(),
+            })
+            .execute_counted(conn)?;
+        Ok(organization)
+    })
+    .await
+    .map_err(database_error)
+}
+
+/// Organizations `user_id` belongs to, with their role in each.
+pub async fn list_organizations(
+    db: &Database,
+    user_id: i64,
+) -> Result<Vec<(Organization, String)>, HttpError> {
+    // Memberships of every tenant are hidden outside of the bypass role
+    db.unscoped_transaction(move |conn| {
+        Ok(organizations::table
+            .inner_join(organization_members::table)
+            .filter(organization_members::user_id.eq(user_id))
+            .select((Organization::as_select(), organization_members::role))
+            .order((organizations::name.asc(), organizations::id.asc()))
+            .load_counted(conn)?)
+    })
+    .await
+    .map_err(database_error)
+}
+
+pub async fn get_organization(
+    db: &Database,
+    organization_id: i64,
+) -> Result<Organization, HttpError> {
+    db.execute(move |conn| {
+        Ok(organizations::table
+            .find(organization_id)
+            .select(Organization::as_select())
+            .first(conn)?)
+    })
+    .await
+    .map_err(database_error)
+}
+
+/// Members of the tenant with their name and email, oldest first.
+pub async fn list_members(
+    db: &Database,
+    tenant_id: i64,
+) -> Result<Vec<(OrganizationMember, String, String)>, HttpError> {
+    db.tenant_transaction(tenant_id, move |conn| {
+        Ok(tenant_scoped!(
+            organization_members::table,
+            organization_members::organization_id,
+            tenant_id
+        )
+        .inner_join(users::table)
+        .filter(users::deleted_at.is_null())
+        .select((
+            OrganizationMember::as_select(),
+            users::full_name,
+            users::email,
+        ))
+        .order((
+            organization_members::created_at.asc(),
+            organization_members::user_id.asc(),
+        ))
+        .load_counted(conn)?)
+    })
+    .await
+    .map_err(database_error)
+}
+
+// Serialize membership changes of one organization, so two owners demoting each other
+// concurrently cannot leave it without any
+fn lock_organization(
+    conn: &mut PgConnection,
+    tenant_id: i64,
+) -> anyhow::Result<()> {
+    organizations::table
+        .find(tenant_id)
+        .select(organizations::id)
+        .for_update()
+        .first::<i64>(conn)?;
+    Ok(())
+}
+
+fn member_role(
+    conn: &mut PgConnection,
+    tenant_id: i64,
+    user_id: i64,
+) -> QueryResult<Option<String>> {
+    tenant_scoped!(
+        organization_members::table,
+        organization_members::organization_id,
+        tenant_id
+    )
+    .filter(organization_members::user_id.eq(user_id))
+    .select(organization_members::role)
+    .first(conn)
+    .optional()
+}
+
+// Fail unless someone other than `user_id` owns the organization
+fn ensure_other_owner(
+    conn: &mut PgConnection,
+    tenant_id: i64,
+    user_id: i64,
+) -> anyhow::Result<()> {
+    let owners: i64 = tenant_scoped!(
+        organization_members::table,
+        organization_members::organization_id,
+        tenant_id
+    )
+    .filter(organization_members::role.eq(constant::MEMBER_ROLE_OWNER))
+    .filter(organization_members::user_id.ne(user_id))
+    .count()
+    .get_result(conn)?;
+    if owners == 0 {
+        return Err(HttpError::new("ORGANIZATION_OWNER_REQUIRED", StatusCode::CONFLICT).into());
+    }
+    Ok(())
+}
+
+/// Change the role of a member of the tenant, admin only. Users join through invitations.
+///
+/// Only owners grant or revoke ownership, and the last owner cannot be demoted.
+pub async fn set_member_role(
+    db: &Database,
+    actor: &TenantMember,
+    user_id: i64,
+    role: String,
+) -> Result<(OrganizationMember, String, String), HttpError> {
+    actor.ensure_role(constant::MEMBER_ROLE_ADMIN)?;
+    let tenant_id = actor.tenant.id;
+    let actor_is_owner = actor.has_role(constant::MEMBER_ROLE_OWNER);
+    db.tenant_transaction(tenant_id, move |conn| {
+        lock_organization(conn, tenant_id)?;
+        let current = member_role(conn, tenant_id, user_id)?
+            .ok_or_else(|| HttpError::not_found("MEMBER_NOT_FOUND"))?;
+        let was_owner = current == constant::MEMBER_ROLE_OWNER;
+        if (was_owner || role == constant::MEMBER_ROLE_OWNER) && !actor_is_owner {
+            return Err(HttpError::forbidden("TENANT_ROLE_REQUIRED").into());
+        }
+        if was_owner && role != constant::MEMBER_ROLE_OWNER {
+            ensure_other_owner(conn, tenant_id, user_id)?;
+        }
+
+        let (full_name, email) = users::table
+            .find(user_id)
+            .filter(users::deleted_at.is_null())
+            .select((users::full_name, users::email))
+            .first::<(String, String)>(conn)
+            .optional()?
+            .ok_or_else(|| HttpError::not_found("USER_NOT_FOUND"))?;
+        let member = diesel::update(organization_members::table.find((tenant_id, user_id)))
+            .set(organization_members::role.eq(role))
+            .returning(OrganizationMember::as_returning())
+            .get_result(conn)?;
+        Ok((member, full_name, email))
+    })
+    .await
+    .map_err(database_error)
+}
+
+/// Remove `user_id` from the tenant. Members may leave, admins remove others, and only
+/// owners remove owners. The last owner cannot leave.
+pub async fn remove_member(
+    db: &Database,
+    actor: &TenantMember,
+    user_id: i64,
+) -> Result<(), HttpError> {
+    let leaving = actor.user.user_id == user_id;
+    if !leaving {
+        actor.ensure_role(constant::MEMBER_ROLE_ADMIN)?;
+    }
+    let tenant_id = actor.tenant.id;
+    let actor_is_owner = actor.has_role(constant::MEMBER_ROLE_OWNER);
+    db.tenant_transaction(tenant_id, move |conn| {
+        lock_organization(conn, tenant_id)?;
+        let current = member_role(conn, tenant_id, user_id)?
+            .ok_or_else(|| HttpError::not_found("MEMBER_NOT_FOUND"))?;
+        if current == constant::MEMBER_ROLE_OWNER {
+            if !leaving && !actor_is_owner {
+                return Err(HttpError::forbidden("TENANT_ROLE_REQUIRED").into());
+            }
+            ensure_other_owner(conn, tenant_id, user_id)?;
+        }
+        diesel::delete(organization_members::table.find((tenant_id, user_id)))
+            .execute_counted(conn)?;
+        Ok(())
+    })
+    .await
+    .map_err(database_error)
+}
+
+/// Issue a token pair whose access token resolves `organization_id` as the request tenant.
+pub async fn issue_tenant_tokens(
+    state: &AppState,
+    user: &AuthUser,
+    organization_id: i64,
+) -> Result<AuthToken, HttpError> {
+    let user_id = user.user_id;
+    let is_member = state
+        .db
+        .tenant_transaction(organization_id, move |conn| {
+            Ok(diesel::select(diesel::dsl::exists(
+                organization_members::table
+                    .find((organization_id, user_id))
+                    .inner_join(users::table)
+                    .filter(users::deleted_at.is_null()),
+            ))
+            .get_result::<bool>(conn)?)
+        })
+        .await
+        .map_err(database_error)?;
+    if !is_member {
+        return Err(HttpError::forbidden("TENANT_ACCESS_DENIED"));
+    }
+
+    tenant_tokens(&state.env, user.user_id, &user.email, organization_id)
+}
+
+/// Token pair whose access token carries the `tenant` claim of `organization_id`.
+pub fn tenant_tokens(
+    config: &Config,
+    user_id: i64,
+    email: &str,
+    organization_id: i64,
+) -> Result<AuthToken, HttpError> {
+    let subject = format!("{user_id}|{email}");
+    let access_token = token::create_tenant_token(
+        subject.clone(),
+        organization_id.to_string(),
+        config.secret.as_bytes(),
+    )
+    .map_err(|_| HttpError::server_error("TOKEN_CREATION_FAILED"))?;
+    let refresh_token = token::create_refresh_token(subject, config.refresh_secret.as_bytes())
+        .map_err(|_| HttpError::server_error("TOKEN_CREATION_FAILED"))?;
+    Ok(AuthToken {
+        access_token,
+        refresh_token,
+        token_type: "Bearer".to_string(),
+        expires_in: token::ACCESS_TOKEN_TTL_SECS,
+    })
+}
@@ -0,0 +1,29 @@
+pub mod privacy_controller;
+pub mod privacy_model;
+pub mod privacy_service;
+use crate::AppState;
+use axum::{
+    routing::{get, post},
+    Router,
+};
+use std::sync::Arc;
+
+// Define Routes, nested under /users/me
+pub struct Routes;
+impl Routes {
+    pub fn index() -> Router<Arc<AppState>> {
+        Router::new()
+            .route("/export", post(privacy_controller::request_export))
+            .route("/export/:id", get(privacy_controller::get_export))
+            .route(
+                "/export/:id/download",
+                get(privacy_controller::download_export),
+            )
+            .route(
+                "/erasure",
+                post(privacy_controller::request_erasure)
+                    .get(privacy_controller::get_erasure)
+                    .delete(privacy_controller::cancel_erasure),
+            )
+    }
+}
@@ -0,0 +1,166 @@
+use crate::{
+    middlewares::auth_middlewares::AuthUser,
+    utils::{errors::HttpError, responses::HttpResponse},
+    AppState,
+};
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use std::sync::Arc;
+
+use super::{
+    privacy_model::{ArchiveQuery, DataExportData, ErasureData},
+    privacy_service,
+};
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/users/me/export",
+    params(ArchiveQuery),
+    responses(
+        (status = 202, description = "Export job started, poll it until ready", body = DataExportData),
+        (status = 409, description = "An export is already running")
+    ),
+    security(("bearer_auth" = [])),
+    tag = "privacy"
+)]
+pub async fn request_export(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Query(query): Query<ArchiveQuery>,
+) -> Result<HttpResponse<DataExportData>, HttpError> {
+    let export = privacy_service::request_export(&state.db, auth.user_id, query.format).await?;
+    Ok(HttpResponse::new(
+        "DATA_EXPORT_STARTED",
+        StatusCode::ACCEPTED,
+        Some(export.into()),
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/users/me/export/{id}",
+    params(
+        ("id" = i64, Path, description = "Export id")
+    ),
+    responses(
+        (status = 200, description = "Export job status", body = DataExportData),
+        (status = 404, description = "Export not found")
+    ),
+    security(("bearer_auth" = [])),
+    tag = "privacy"
+)]
+pub async fn get_export(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Path(export_id): Path<i64>,
+) -> Result<HttpResponse<DataExportData>, HttpError> {
+    let export = privacy_service::get_export(&state.db, auth.user_id, export_id).await?;
+    Ok(HttpResponse::ok(export.into(), "DATA_EXPORT_FETCHED"))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/users/me/export/{id}/download",
+    params(
+        ("id" = i64, Path, description = "Export id")
+    ),
+    responses(
+        (status = 200, description = "JSON document or ZIP archive", content_type = "application/zip"),
+        (status = 404, description = "Export not found"),
+        (status = 409, description = "Export is not ready yet"),
+        (status = 410, description = "Export expired")
+    ),
+    security(("bearer_auth" = [])),
+    tag = "privacy"
+)]
+pub async fn download_export(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Path(export_id): Path<i64>,
+) -> Result<Response, HttpError> {
+    let (export, contents) =
+        privacy_service::read_export(&state.db, auth.user_id, export_id).await?;
+    let format = export.archive_format();
+    let disposition = format!(
+        "attachment; filename=\"personal-data-{}.{}\"",
+        export.id,
+        format.as_str()
+    );
+    Ok((
+        [
+            (
+                header::CONTENT_TYPE,
+                HeaderValue::from_static(format.content_type()),
+            ),
+            (
+                header::CONTENT_DISPOSITION,
+                HeaderValue::from_str(&disposition)
+                    .unwrap_or_else(|_| HeaderValue::from_static("attachment")),
+            ),
+        ],
+        contents,
+    )
+        .into_response())
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/users/me/erasure",
+    responses(
+        (status = 202, description = "Erasure scheduled after the grace period", body = ErasureData),
+        (status = 409, description = "An erasure is already pending")
+    ),
+    security(("bearer_auth" = [])),
+    tag = "privacy"
+)]
+pub async fn request_erasure(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+) -> Result<HttpResponse<ErasureData>, HttpError> {
+    let grace_period = chrono::Duration::days(state.env.erasure_grace_days);
+    let request = privacy_service::request_erasure(&state.db, auth.user_id, grace_period).await?;
+    Ok(HttpResponse::new(
+        "ERASURE_SCHEDULED",
+        StatusCode::ACCEPTED,
+        Some(request.into()),
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/users/me/erasure",
+    responses(
+        (status = 200, description = "Latest erasure request", body = ErasureData),
+        (status = 404, description = "No erasure was requested")
+    ),
+    security(("bearer_auth" = [])),
+    tag = "privacy"
+)]
+pub async fn get_erasure(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+) -> Result<HttpResponse<ErasureData>, HttpError> {
+    let request = privacy_service::get_erasure(&state.db, auth.user_id).await?;
+    Ok(HttpResponse::ok(request.into(), "ERASURE_FETCHED"))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/users/me/erasure",
+    responses(
+        (status = 200, description = "Erasure cancelled", body = ErasureData),
+        (status = 404, description = "No erasure is pending")
+    ),
+    security(("bearer_auth" = [])),
+    tag = "privacy"
+)]
+pub async fn cancel_erasure(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+) -> Result<HttpResponse<ErasureData>, HttpError> {
+    let request = privacy_service::cancel_erasure(&state.db, auth.user_id).await?;
+    Ok(HttpResponse::ok(request.into(), "ERASURE_CANCELLED"))
+}
@@ -0,0 +1,209 @@
+use crate::modules::user::user_model::UserExport;
+use crate::schema::table::{data_exports, erasure_requests};
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+// Values of data_exports.status
+pub const EXPORT_PENDING: &str = "pending";
+pub const EXPORT_READY: &str = "ready";
+pub const EXPORT_FAILED: &str = "failed";
+
+// Values of erasure_requests.status
+pub const ERASURE_PENDING: &str = "pending";
+pub const ERASURE_CANCELLED: &str = "cancelled";
+pub const ERASURE_COMPLETED: &str = "completed";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ArchiveFormat {
+    /// Single JSON document
+    #[default]
+    Json,
+    /// `data.json` plus uploaded files such as the avatar
+    Zip,
+}
+
+impl ArchiveFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ArchiveFormat::Json => "json",
+            ArchiveFormat::Zip => "zip",
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ArchiveFormat::Json => "application/json",
+            ArchiveFormat::Zip => "application/zip",
+        }
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct ArchiveQuery {
+    /// `json` (default) or `zip`
+    #[serde(default)]
+    pub format: ArchiveFormat,
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DataExportData {
+    pub export_id: String,
+    pub format: String,
+    /// pending, ready or failed
+    pub status: String,
+    /// Set once the archive is ready
+    pub download_url: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl From<DataExport> for DataExportData {
+    fn from(export: DataExport) -> Self {
+        DataExportData {
+            export_id: export.id.to_string(),
+            download_url: (export.status == EXPORT_READY)
+                .then(|| format!("/api/v1/users/me/export/{}/download", export.id)),
+            format: export.format,
+            status: export.status,
+            created_at: export.created_at,
+            completed_at: export.completed_at,
+            expires_at: export.expires_at,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ErasureData {
+    pub erasure_id: String,
+    /// pending, cancelled or completed
+    pub status: String,
+    /// Personal data is erased after this moment unless cancelled
+    pub execute_after: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    pub cancelled_at: Option<DateTime<Utc>>,
+}
+
+impl From<ErasureRequest> for ErasureData {
+    fn from(request: ErasureRequest) -> Self {
+        ErasureData {
+            erasure_id: request.id.to_string(),
+            status: request.status,
+            execute_after: request.execute_after,
+            created_at: request.created_at,
+            cancelled_at: request.cancelled_at,
+        }
+    }
+}
+
+/// Everything stored about one user, the `data.json` of an export.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PersonalData {
+    pub generated_at: DateTime<Utc>,
+    pub profile: UserExport,
+    pub identities: Vec<IdentityExport>,
+    pub organizations: Vec<MembershipExport>,
+    pub invitations: Vec<InvitationExport>,
+    pub sign_in_links: Vec<SignInLinkExport>,
+}
+
+#[derive(Serialize, Queryable)]
+#[serde(rename_all = "camelCase")]
+pub struct IdentityExport {
+    pub provider: String,
+    pub subject: String,
+    pub email: Option<String>,
+    pub linked_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Queryable)]
+#[serde(rename_all = "camelCase")]
+pub struct MembershipExport {
+    pub organization: String,
+    pub slug: String,
+    pub role: String,
+    pub joined_at: DateTime<Utc>,
+}
+
+/// Invitation received by the email of the user
+#[derive(Serialize, Queryable)]
+#[serde(rename_all = "camelCase")]
+pub struct InvitationExport {
+    pub organization: String,
+    pub role: String,
+    pub created_at: DateTime<Utc>,
+    pub accepted_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, Queryable)]
+#[serde(rename_all = "camelCase")]
+pub struct SignInLinkExport {
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub consumed_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Queryable, Selectable)]
+#[diesel(table_name = data_exports)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct DataExport {
+    pub id: i64,
+    pub user_id: i64,
+    pub format: String,
+    pub status: String,
+    pub file_key: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = data_exports)]
+pub struct NewDataExport {
+    pub id: i64,
+    pub user_id: i64,
+    pub format: String,
+}
+
+/// Background job building the archive of a pending export
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DataExportJob {
+    pub export_id: i64,
+}
+
+#[derive(Debug, Clone, Queryable, Selectable)]
+#[diesel(table_name = erasure_requests)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct ErasureRequest {
+    pub id: i64,
+    pub user_id: i64,
+    pub status: String,
+    pub execute_after: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    pub cancelled_at: Option<DateTime<Utc>>,
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = erasure_requests)]
+pub struct NewErasureRequest {
+    pub id: i64,
+    pub user_id: i64,
+    pub execute_after: DateTime<Utc>,
+}
+
+impl DataExport {
+    pub fn archive_format(&self) -> ArchiveFormat {
+        match self.format.as_str() {
+            "zip" => ArchiveFormat::Zip,
+            _ => ArchiveFormat::Json,
+        }
+    }
+}
@@ -0,0 +1,948 @@
+use super::privacy_model::{
+    ArchiveFormat, DataExport, DataExportJob, ErasureRequest, IdentityExport, InvitationExport,
+    MembershipExport, NewDataExport, NewErasureRequest, PersonalData, SignInLinkExport,
+    ERASURE_CANCELLED, ERASURE_COMPLETED, ERASURE_PENDING, EXPORT_FAILED, EXPORT_PENDING,
+    EXPORT_READY,
+};
+use crate::constant;
+use crate::database::instrumentation::CountedRunQueryDsl;
+use crate::database::Database;
+use crate::jobs::{self, Job};
+use crate::modules::user::user_model::{User, UserExport};
+use crate::modules::user::user_service::{avatar_path, delete_avatar_files};
+use crate::repository::audit;
+use crate::schema::table::{
+    data_exports, erasure_requests, invitations, magic_links, organization_members, organizations,
+    user_identities, users,
+};
+use crate::utils::errors::HttpError;
+use crate::utils::{files, generator};
+use crate::AppState;
+use axum::async_trait;
+use axum::body::Bytes;
+use axum::http::StatusCode;
+use axum_typed_multipart::{FieldData, FieldMetadata};
+use chrono::{Duration, Utc};
+use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+use serde_json::json;
+use std::io::{Cursor, Write};
+use std::sync::Arc;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+// Translate database failures, one pending erasure per user is the only unique constraint
+fn database_error(err: anyhow::Error) -> HttpError {
+    match err.downcast_ref::<DieselError>() {
+        Some(DieselError::NotFound) => HttpError::not_found("USER_NOT_FOUND"),
+        Some(DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => {
+            HttpError::unique_constraint_violation("ERASURE_ALREADY_REQUESTED")
+        }
+        _ => err.into(),
+    }
+}
+
+fn export_path(
+    user_id: i64,
+    export_id: i64,
+    format: ArchiveFormat,
+) -> String {
+    format!("exports/{user_id}/{export_id}.{}", format.as_str())
+}
+
+/// Start gathering the personal data of `user_id` into an archive in the background.
+///
+/// Only one export runs per user, a pending job is considered dead after
+/// `DATA_EXPORT_STALE_MINUTES` so a crash does not block the user forever.
+pub async fn request_export(
+    db: &Database,
+    user_id: i64,
+    format: ArchiveFormat,
+) -> Result<DataExport, HttpError> {
+    let stale_before = Utc::now() - Duration::minutes(constant::DATA_EXPORT_STALE_MINUTES);
+    let export = db
+        .transaction(move |conn| {
+            let running = diesel::select(diesel::dsl::exists(
+                data_exports::table
+                    .filter(data_exports::user_id.eq(user_id))
+                    .filter(data_exports::status.eq(EXPORT_PENDING))
+                    .filter(data_exports::created_at.gt(stale_before)),
+            ))
+            .get_result::<bool>(conn)?;
+            if running {
+                return Err(HttpError::new("DATA_EXPORT_IN_PROGRESS", StatusCode::CONFLICT).into());
+            }
+            let export = diesel::insert_into(data_exports::table)
+                .values(NewDataExport {
+                    id: generator::id() as i64,
+                    user_id,
+                    format: format.as_str().to_string(),
+                })
+                .returning(DataExport::as_returning())
+                .get_result(conn)?;
+            jobs::enqueue(
+                conn,
+                &DataExportJob {
+                    export_id: export.id,
+                },
+            )?;
+            Ok(export)
+        })
+        .await
+        .map_err(database_error)?;
+    Ok(export)
+}
+
+#[async_trait]
+impl Job for DataExportJob {
+    const JOB_TYPE: &'static str = "privacy.data_export";
+    // The export records its own failure, retries only cover not reaching it
+    const MAX_ATTEMPTS: i32 = 3;
+
+    async fn run(
+        self,
+        state: Arc<AppState>,
+    ) -> anyhow::Result<()> {
+        let export_id = self.export_id;
+        let export = state
+            .db
+            .transaction(move |conn| {
+                Ok(data_exports::table
+                    .find(export_id)
+                    .filter(data_exports::status.eq(EXPORT_PENDING))
+                    .select(DataExport::as_select())
+                    .first(conn)
+                    .optional()?)
+            })
+            .await?;
+        // Already built by an earlier run of the job
+        if let Some(export) = export {
+            run_export(state.db.clone(), export).await;
+        }
+        Ok(())
+    }
+}
+
+async fn run_export(
+    db: Database,
+    export: DataExport,
+) {
+    let export_id = export.id;
+    let format = export.archive_format();
+    let file_key = export_path(export.user_id, export_id, format);
+    let saved = match build_archive(&db, export.user_id, format).await {
+        Ok(contents) => {
+            let file = FieldData {
+                metadata: FieldMetadata {
+                    content_type: Some(format.content_type().to_string()),
+                    ..Default::default()
+                },
+                contents: Bytes::from(contents),
+            };
+            files::save_file(&file_key, file, true)
+                .await
+                .map(|_| ())
+                .map_err(|err| anyhow::anyhow!(err.to_string()))
+        }
+        Err(err) => Err(err),
+    };
+
+    let finished = match saved {
+        Ok(()) => {
+            db.transaction(move |conn| {
+                let now = Utc::now();
+                diesel::update(data_exports::table.find(export_id))
+                    .set((
+                        data_exports::status.eq(EXPORT_READY),
+                        data_exports::file_key.eq(&file_key),
+                        data_exports::completed_at.eq(now),
+                        data_exports::expires_at
+                            .eq(now + Duration::hours(constant::DATA_EXPORT_TTL_HOURS)),
+                    ))
+                    .execute_counted(conn)?;
+                Ok(())
+            })
+            .await
+        }
+        Err(err) => {
+            tracing::error!(export_id, error = %err, "DATA_EXPORT_FAILED");
+            db.transaction(move |conn| {
+                diesel::update(data_exports::table.find(export_id))
+                    .set((
+                        data_exports::status.eq(EXPORT_FAILED),
+                        data_exports::completed_at.eq(Utc::now()),
+                    ))
+                    .execute_counted(conn)?;
+                Ok(())
+            })
+            .await
+        }
+    };
+    if let Err(err) = finished {
+        tracing::error!(export_id, error = %err, "DATA_EXPORT_UPDATE_FAILED");
+    }
+}
+
+async fn build_archive(
+    db: &Database,
+    user_id: i64,
+    format: ArchiveFormat,
+) -> anyhow::Result<Vec<u8>> {
+    let (data, avatar_key) = db
+        .unscoped_transaction(move |conn| collect_personal_data(conn, user_id))
+        .await?;
+    let json = serde_json::to_vec_pretty(&data)?;
+    if format == ArchiveFormat::Json {
+        return Ok(json);
+    }
+
+    let largest = constant::AVATAR_SIZES[constant::AVATAR_SIZES.len() - 1];
+    // A missing thumbnail leaves the avatar out rather than failing the whole export
+    let avatar = match avatar_key {
+        Some(key) => match files::read_file(avatar_path(user_id, &key, largest)).await {
+            Ok(contents) => Some(contents),
+            Err(err) => {
+                tracing::warn!(user_id, key, error = %err, "DATA_EXPORT_AVATAR_MISSING");
+                None
+            }
+        },
+        None => None,
+    };
+    tokio::task::spawn_blocking(move || zip_archive(&json, avatar.as_deref())).await?
+}
+
+// Zip of the JSON data and the avatar thumbnail when there is one
+fn zip_archive(
+    json: &[u8],
+    avatar: Option<&[u8]>,
+) -> anyhow::Result<Vec<u8>> {
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    writer.start_file("data.json", options)?;
+    writer.write_all(json)?;
+    if let Some(avatar) = avatar {
+        writer.start_file("avatar.png", options)?;
+        writer.write_all(avatar)?;
+    }
+    Ok(writer.finish()?.into_inner())
+}
+
+/// Gather everything stored about `user_id` across modules, with the avatar key.
+fn collect_personal_data(
+    conn: &mut PgConnection,
+    user_id: i64,
+) -> anyhow::Result<(PersonalData, Option<String>)> {
+    let user = users::table
+        .find(user_id)
+        .select(User::as_select())
+        .first::<User>(conn)?;
+
+    let identities = user_identities::table
+        .filter(user_identities::user_id.eq(user_id))
+        .select((
+            user_identities::provider,
+            user_identities::subject,
+            user_identities::email,
+            user_identities::created_at,
+        ))
+        .order(user_identities::created_at.asc())
+        .load_counted::<IdentityExport>(conn)?;
+    let organizations = organization_members::table
+        .inner_join(organizations::table)
+        .filter(organization_members::user_id.eq(user_id))
+        .select((
+            organizations::name,
+            organizations::slug,
+            organization_members::role,
+            organization_members::created_at,
+        ))
+        .order(organization_members::created_at.asc())
+        .load_counted::<MembershipExport>(conn)?;
+    let invitations = invitations::table
+        .inner_join(organizations::table)
+        .filter(invitations::email.eq(&user.email))
+        .select((
+            organizations::name,
+            invitations::role,
+            invitations::created_at,
+            invitations::accepted_at,
+            invitations::revoked_at,
+        ))
+        .order(invitations::created_at.asc())
+        .load_counted::<InvitationExport>(conn)?;
+    let sign_in_links = magic_links::table
+        .filter(magic_links::email.eq(&user.email))
+        .select((
+            magic_links::created_at,
+            magic_links::expires_at,
+            magic_links::consumed_at,
+        ))
+        .order(magic_links::created_at.asc())
+        .load_counted::<SignInLinkExport>(conn)?;
+
+    let avatar_key = user.avatar_key.clone();
+    let data = PersonalData {
+        generated_at: Utc::now(),
+        profile: UserExport::from(user),
+        identities,
+        organizations,
+        invitations,
+        sign_in_links,
+    };
+    Ok((data, avatar_key))
+}
+
+pub async fn get_export(
+    db: &Database,
+    user_id: i64,
+    export_id: i64,
+) -> Result<DataExport, HttpError> {
+    db.execute(move |conn| {
+        Ok(data_exports::table
+            .find(export_id)
+            .filter(data_exports::user_id.eq(user_id))
+            .select(DataExport::as_select())
+            .first(conn)
+            .optional()?)
+    })
+    .await
+    .map_err(database_error)?
+    .ok_or_else(|| HttpError::not_found("DATA_EXPORT_NOT_FOUND"))
+}
+
+/// Contents of a ready export archive.
+pub async fn read_export(
+    db: &Database,
+    user_id: i64,
+    export_id: i64,
+) -> Result<(DataExport, Bytes), HttpError> {
+    let export = get_export(db, user_id, export_id).await?;
+    if export.status != EXPORT_READY {
+        return Err(HttpError::new(
+            "DATA_EXPORT_NOT_READY",
+            StatusCode::CONFLICT,
+        ));
+    }
+    let file_key = match (&export.file_key, export.expires_at) {
+        (Some(file_key), Some(expires_at)) if expires_at > Utc::now() => file_key,
+        _ => return Err(HttpError::new("DATA_EXPORT_EXPIRED", StatusCode::GONE)),
+    };
+    let contents = files::read_file(file_key)
+        .await
+        .map_err(|err| err.to_string())
+        .map_err(|err| {
+            tracing::error!(export_id, error = %err, "DATA_EXPORT_READ_FAILED");
+            HttpError::new("DATA_EXPORT_EXPIRED", StatusCode::GONE)
+        })?;
+    Ok((export, contents))
+}
+
+/// Delete archives past their expiry, returns how many were removed.
+pub async fn remove_expired_exports(db: &Database) -> anyhow::Result<usize> {
+    let expired = db
+        .transaction(|conn| {
+            Ok(
+                diesel::delete(data_exports::table.filter(data_exports::expires_at.lt(Utc::now())))
+                    .returning(data_exports::file_key)
+                    .get_results::<Option<String>>(conn)?,
+            )
+        })
+        .await?;
+    let removed = expired.len();
+    for file_key in expired.into_iter().flatten() {
+        if let Err(err) = files::delete_file(&file_key).await {
+            tracing::warn!(file_key, error = %err, "DATA_EXPORT_DELETE_FAILED");
+        }
+    }
+    Ok(removed)
+}
+
+/// Schedule the erasure of the personal data of `user_id` after the grace period.
+///
+/// Ownership of the organizations they own alone is handed over when it runs.
+pub async fn request_erasure(
+    db: &Database,
+    user_id: i64,
+    grace_period: Duration,
+) -> Result<ErasureRequest, HttpError> {
+    db.transaction(move |conn| {
+        let request = diesel::insert_into(erasure_requests::table)
+            .values(NewErasureRequest {
+                id: generator::id() as i64,
+                user_id,
+                execute_after: Utc::now() + grace_period,
+            })
+            .returning(ErasureRequest::as_returning())
+            .get_result::<ErasureRequest>(conn)?;
+        audit::record(
+            conn,
+            Some(user_id),
+            "erasure.requested",
+            "user",
+            user_id,
+            json!({ "erasureId": request.id.to_string(), "executeAfter": request.execute_after }),
+        )?;
+        Ok(request)
+    })
+    .await
+    .map_err(database_error)
+}
+
+/// Latest erasure request of `user_id`.
+pub async fn get_erasure(
+    db: &Database,
+    user_id: i64,
+) -> Result<ErasureRequest, HttpError> {
+    db.execute(move |conn| {
+        Ok(erasure_requests::table
+            .filter(erasure_requests::user_id.eq(user_id))
+            .select(ErasureRequest::as_select())
+            .order((
+                erasure_requests::created_at.desc(),
+                erasure_requests::id.desc(),
+            ))
+            .first(conn)
+            .optional()?)
+    })
+    .await
+    .map_err(database_error)?
+    .ok_or_else(|| HttpError::not_found("ERASURE_NOT_REQUESTED"))
+}
+
+/// Cancel the pending erasure of `user_id` during the grace period.
+pub async fn cancel_erasure(
+    db: &Database,
+    user_id: i64,
+) -> Result<ErasureRequest, HttpError> {
+    db.transaction(move |conn| {
+        let request = diesel::update(
+            erasure_requests::table
+                .filter(erasure_requests::user_id.eq(user_id))
+                .filter(erasure_requests::status.eq(ERASURE_PENDING)),
+        )
+        .set((
+            erasure_requests::status.eq(ERASURE_CANCELLED),
+            erasure_requests::cancelled_at.eq(Utc::now()),
+        ))
+        .returning(ErasureRequest::as_returning())
+        .get_result::<ErasureRequest>(conn)
+        .optional()?
+        .ok_or_else(|| HttpError::not_found("ERASURE_NOT_PENDING"))?;
+        audit::record(
+            conn,
+            Some(user_id),
+            "erasure.cancelled",
+            "user",
+            user_id,
+            json!({ "erasureId": request.id.to_string() }),
+        )?;
+        Ok(request)
+    })
+    .await
+    .map_err(database_error)
+}
+
+// Files to remove once the erasure is committed
+struct ErasedFiles {
+    user_id: i64,
+    avatar_key: Option<String>,
+    export_keys: Vec<String>,
+}
+
+/// Execute erasure requests whose grace period is over, returns how many ran.
+pub async fn run_due_erasures(db: &Database) -> anyhow::Result<usize> {
+    let mut executed = 0;
+    loop {
+        // One request per transaction, SKIP LOCKED lets several instances share the work
+        let erased = db
+            .unscoped_transaction(|conn| {
+                let Some(request) = erasure_requests::table
+                    .filter(erasure_requests::status.eq(ERASURE_PENDING))
+                    .filter(erasure_requests::execute_after.le(Utc::now()))
+                    .select(ErasureRequest::as_select())
+                    .order(erasure_requests::execute_after.asc())
+                    .for_update()
+                    .skip_locked()
+                    .first::<ErasureRequest>(conn)
+                    .optional()?
+                else {
+                    return Ok(None);
+                };
+                Ok(Some(erase_user(conn, &request)?))
+            })
+            .await?;
+        let Some(erased) = erased else {
+            return Ok(executed);
+        };
+        executed += 1;
+
+        // Files go after the commit, a rolled back erasure must not lose them
+        if let Some(key) = &erased.avatar_key {
+            delete_avatar_files(erased.user_id, key).await;
+        }
+        for file_key in &erased.export_keys {
+            if let Err(err) = files::delete_file(file_key).await {
+                tracing::warn!(user_id = erased.user_id, file_key, error = %err, "DATA_EXPORT_DELETE_FAILED");
+            }
+        }
+    }
+}
+
+// Keep every organization `user_id` owns alone owned by someone: the longest standing admin
+// is promoted, else the longest standing member. Organizations left without anyone are
+// deleted. Returns the ids of both, as strings for the audit log.
+fn hand_over_ownership(
+    conn: &mut PgConnection,
+    user_id: i64,
+) -> QueryResult<(Vec<String>, Vec<String>)> {
+    let owned: Vec<i64> = organization_members::table
+        .filter(organization_members::user_id.eq(user_id))
+        .filter(organization_members::role.eq(constant::MEMBER_ROLE_OWNER))
+        .select(organization_members::organization_id)
+        .load_counted(conn)?;
+    let mut transferred = Vec::new();
+    let mut deleted = Vec::new();
+    for organization_id in owned {
+        // Same lock as membership changes, so no owner leaves meanwhile
+        organizations::table
+            .find(organization_id)
+            .select(organizations::id)
+            .for_update()
+            .first::<i64>(conn)?;
+        let others = organization_members::table
+            .inner_join(users::table)
+            .filter(organization_members::organization_id.eq(organization_id))
+            .filter(organization_members::user_id.ne(user_id))
+            .filter(users::deleted_at.is_null());
+        let other_owner = diesel::select(diesel::dsl::exists(
+            others.filter(organization_members::role.eq(constant::MEMBER_ROLE_OWNER)),
+        ))
+        .get_result::<bool>(conn)?;
+        if other_owner {
+            continue;
+        }
+        let successor = others
+            .select(organization_members::user_id)
+            .order((
+                organization_members::role
+                    .eq(constant::MEMBER_ROLE_ADMIN)
+                    .desc(),
+                organization_members::created_at.asc(),
+                organization_members::user_id.asc(),
+            ))
+            .first::<i64>(conn)
+            .optional()?;
+        match successor {
+            Some(successor) => {
+                diesel::update(organization_members::table.find((organization_id, successor)))
+                    .set(organization_members::role.eq(constant::MEMBER_ROLE_OWNER))
+                    .execute_counted(conn)?;
+                transferred.push(organization_id.to_string());
+            }
+            None => {
+                diesel::delete(organizations::table.find(organization_id)).execute_counted(conn)?;
+                deleted.push(organization_id.to_string());
+            }
+        }
+    }
+    Ok((transferred, deleted))
+}
+
+// Anonymize the user row and delete personal data held by the other modules. The row is
+// kept, soft deleted, so references and the audit trail stay intact until it is purged.
+fn erase_user(
+    conn: &mut PgConnection,
+    request: &ErasureRequest,
+) -> anyhow::Result<ErasedFiles> {
+    let user_id = request.user_id;
+    let (email, avatar_key) = users::table
+        .find(user_id)
+        .select((users::email, users::avatar_key))
+        .first::<(String, Option<String>)>(conn)?;
+
+    let (ownership_transferred, organizations_deleted) = hand_over_ownership(conn, user_id)?;
+    let identities =
+        diesel::delete(user_identities::table.filter(user_identities::user_id.eq(user_id)))
+            .execute_counted(conn)?;
+    let memberships = diesel::delete(
+        organization_members::table.filter(organization_members::user_id.eq(user_id)),
+    )
+    .execute_counted(conn)?;
+    let invitations_received =
+        diesel::delete(invitations::table.filter(invitations::email.eq(&email)))
+            .execute_counted(conn)?;
+    diesel::update(invitations::table.filter(invitations::invited_by.eq(user_id)))
+        .set(invitations::invited_by.eq(None::<i64>))
+        .execute_counted(conn)?;
+    let sign_in_links = diesel::delete(magic_links::table.filter(magic_links::email.eq(&email)))
+        .execute_counted(conn)?;
+    let export_keys: Vec<String> =
+        diesel::delete(data_exports::table.filter(data_exports::user_id.eq(user_id)))
+            .returning(data_exports::file_key)
+            .get_results::<Option<String>>(conn)?
+            .into_iter()
+            .flatten()
+            .collect();
+
+    let now = Utc::now();
+    diesel::update(users::table.find(user_id))
+        .set((
+            users::full_name.eq("Erased user"),
+            users::email.eq(format!("erased-{user_id}@invalid")),
+            users::phone_number.eq(None::<String>),
+            users::avatar_key.eq(None::<String>),
+            users::role.eq(constant::ROLE_USER),
+            users::updated_at.eq(now),
+            users::version.eq(users::version + 1),
+        ))
+        .execute_counted(conn)?;
+    diesel::update(
+        users::table
+            .find(user_id)
+            .filter(users::deleted_at.is_null()),
+    )
+    .set(users::deleted_at.eq(now))
+    .execute_counted(conn)?;
+    diesel::update(erasure_requests::table.find(request.id))
+        .set((
+            erasure_requests::status.eq(ERASURE_COMPLETED),
+            erasure_requests::completed_at.eq(now),
+        ))
+        .execute_counted(conn)?;
+
+    audit::record(
+        conn,
+        None,
+        "user.erased",
+        "user",
+        user_id,
+        json!({
+            "erasureId": request.id.to_string(),
+            "identities": identities,
+            "memberships": memberships,
+            "ownershipTransferred": ownership_transferred,
+            "organizationsDeleted": organizations_deleted,
+            "invitations": invitations_received,
+            "signInLinks": sign_in_links,
+            "exports": export_keys.len(),
+            "avatar": avatar_key.is_some(),
+        }),
+    )?;
+    Ok(ErasedFiles {
+        user_id,
+        avatar_key,
+        export_keys,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::test_connection;
+    use crate::modules::user::user_model::NewUser;
+    use crate::schema::table::audit_logs;
+    use diesel::sql_query;
+    use std::collections::HashSet;
+    use std::io::Read;
+    use zip::ZipArchive;
+
+    // A test transaction of the role bypassing row level security, like the privacy tasks
+    fn connection() -> PgConnection {
+        let mut conn = test_connection();
+        conn.begin_test_transaction().unwrap();
+        sql_query(format!("SET LOCAL ROLE {}", constant::RLS_BYPASS_ROLE))
+            .execute_counted(&mut conn)
+            .unwrap();
+        conn
+    }
+
+    fn user(
+        conn: &mut PgConnection,
+        name: &str,
+    ) -> (i64, String) {
+        let id = generator::id() as i64;
+        let email = format!("{name}-{id}@example.com");
+        diesel::insert_into(users::table)
+            .values(NewUser {
+                id,
+                full_name: name.to_string(),
+                email: email.clone(),
+                phone_number: None,
+            })
+            .execute_counted(conn)
+            .unwrap();
+        (id, email)
+    }
+
+    // Organization with its members, in the order they joined
+    fn organization(
+        conn: &mut PgConnection,
+        members: &[(i64, &str)],
+    ) -> i64 {
+        let id = generator::id() as i64;
+        diesel::insert_into(organizations::table)
+            .values((
+                organizations::id.eq(id),
+                organizations::name.eq(format!("Org {id}")),
+                organizations::slug.eq(format!("o{id}")),
+            ))
+            .execute_counted(conn)
+            .unwrap();
+        for (joined, (user_id, role)) in members.iter().enumerate() {
+            diesel::insert_into(organization_members::table)
+                .values((
+                    organization_members::organization_id.eq(id),
+                    organization_members::user_id.eq(user_id),
+                    organization_members::role.eq(role),
+                    organization_members::created_at
+                        .eq(Utc::now() - Duration::minutes(60 - joined as i64)),
+                ))
+                .execute_counted(conn)
+                .unwrap();
+        }
+        id
+    }
+
+    fn role_of(
+        conn: &mut PgConnection,
+        organization_id: i64,
+        user_id: i64,
+    ) -> Option<String> {
+        organization_members::table
+            .find((organization_id, user_id))
+            .select(organization_members::role)
+            .first(conn)
+            .optional()
+            .unwrap()
+    }
+
+    fn personal_rows(
+        conn: &mut PgConnection,
+        user_id: i64,
+        email: &str,
+    ) {
+        diesel::insert_into(user_identities::table)
+            .values((
+                user_identities::id.eq(generator::id() as i64),
+                user_identities::user_id.eq(user_id),
+                user_identities::provider.eq("google"),
+                user_identities::subject.eq(format!("sub-{user_id}")),
+                user_identities::email.eq(email),
+            ))
+            .execute_counted(conn)
+            .unwrap();
+        diesel::insert_into(magic_links::table)
+            .values((
+                magic_links::id.eq(generator::id() as i64),
+                magic_links::email.eq(email),
+                magic_links::expires_at.eq(Utc::now()),
+            ))
+            .execute_counted(conn)
+            .unwrap();
+    }
+
+    fn invite(
+        conn: &mut PgConnection,
+        organization_id: i64,
+        email: &str,
+        invited_by: i64,
+    ) {
+        diesel::insert_into(invitations::table)
+            .values((
+                invitations::id.eq(generator::id() as i64),
+                invitations::organization_id.eq(organization_id),
+                invitations::email.eq(email),
+                invitations::role.eq(constant::MEMBER_ROLE_MEMBER),
+                invitations::invited_by.eq(invited_by),
+                invitations::expires_at.eq(Utc::now() + Duration::days(1)),
+            ))
+            .execute_counted(conn)
+            .unwrap();
+    }
+
+    #[test]
+    fn zips_the_data_and_the_avatar() {
+        let archive = zip_archive(br#"{"profile":{}}"#, Some(b"png")).unwrap();
+        let mut archive = ZipArchive::new(Cursor::new(archive)).unwrap();
+        assert_eq!(
+            archive.file_names().collect::<HashSet<_>>(),
+            HashSet::from(["data.json", "avatar.png"])
+        );
+        let mut json = String::new();
+        archive
+            .by_name("data.json")
+            .unwrap()
+            .read_to_string(&mut json)
+            .unwrap();
+        assert_eq!(json, r#"{"profile":{}}"#);
+
+        let archive = zip_archive(b"{}", None).unwrap();
+        let archive = ZipArchive::new(Cursor::new(archive)).unwrap();
+        assert_eq!(archive.file_names().collect::<Vec<_>>(), vec!["data.json"]);
+    }
+
+    #[test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    fn collects_the_personal_data_of_one_user() {
+        let mut conn = connection();
+        let (user_id, email) = user(&mut conn, "ann");
+        let (other_id, other_email) = user(&mut conn, "bob");
+        personal_rows(&mut conn, user_id, &email);
+        personal_rows(&mut conn, other_id, &other_email);
+        let organization_id = organization(
+            &mut conn,
+            &[
+                (other_id, constant::MEMBER_ROLE_OWNER),
+                (user_id, constant::MEMBER_ROLE_ADMIN),
+            ],
+        );
+        invite(&mut conn, organization_id, &email, other_id);
+        invite(&mut conn, organization_id, &other_email, user_id);
+
+        let (data, avatar_key) = collect_personal_data(&mut conn, user_id).unwrap();
+        assert_eq!(avatar_key, None);
+        let json = serde_json::to_value(&data).unwrap();
+        assert_eq!(json["profile"]["email"], email.as_str());
+        assert_eq!(json["identities"].as_array().unwrap().len(), 1);
+        assert_eq!(json["identities"][0]["subject"], format!("sub-{user_id}"));
+        assert_eq!(json["organizations"].as_array().unwrap().len(), 1);
+        assert_eq!(
+            json["organizations"][0]["slug"],
+            format!("o{organization_id}")
+        );
+        assert_eq!(json["organizations"][0]["role"], "admin");
+        // Only the invitations the user received
+        assert_eq!(json["invitations"].as_array().unwrap().len(), 1);
+        assert_eq!(json["invitations"][0]["role"], "member");
+        assert_eq!(json["signInLinks"].as_array().unwrap().len(), 1);
+    }
+
+    #[test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    fn erases_the_user_and_hands_over_ownership() {
+        let mut conn = connection();
+        let (user_id, email) = user(&mut conn, "ann");
+        let (admin_id, _) = user(&mut conn, "admin");
+        let (member_id, _) = user(&mut conn, "member");
+        let (owner_id, _) = user(&mut conn, "owner");
+        personal_rows(&mut conn, user_id, &email);
+
+        // The member joined first, the admin is still preferred
+        let shared = organization(
+            &mut conn,
+            &[
+                (user_id, constant::MEMBER_ROLE_OWNER),
+                (member_id, constant::MEMBER_ROLE_MEMBER),
+                (admin_id, constant::MEMBER_ROLE_ADMIN),
+            ],
+        );
+        let members_only = organization(
+            &mut conn,
+            &[
+                (user_id, constant::MEMBER_ROLE_OWNER),
+                (member_id, constant::MEMBER_ROLE_MEMBER),
+            ],
+        );
+        let co_owned = organization(
+            &mut conn,
+            &[
+                (user_id, constant::MEMBER_ROLE_OWNER),
+                (owner_id, constant::MEMBER_ROLE_OWNER),
+                (admin_id, constant::MEMBER_ROLE_ADMIN),
+            ],
+        );
+        let alone = organization(&mut conn, &[(user_id, constant::MEMBER_ROLE_OWNER)]);
+        invite(&mut conn, shared, &email, admin_id);
+        invite(&mut conn, shared, "someone@example.com", user_id);
+
+        let request = diesel::insert_into(erasure_requests::table)
+            .values(NewErasureRequest {
+                id: generator::id() as i64,
+                user_id,
+                execute_after: Utc::now(),
+            })
+            .returning(ErasureRequest::as_returning())
+            .get_result(&mut conn)
+            .unwrap();
+        let erased = erase_user(&mut conn, &request).unwrap();
+        assert_eq!(erased.user_id, user_id);
+
+        assert_eq!(
+            role_of(&mut conn, shared, admin_id).as_deref(),
+            Some("owner")
+        );
+        assert_eq!(
+            role_of(&mut conn, shared, member_id).as_deref(),
+            Some("member")
+        );
+        assert_eq!(
+            role_of(&mut conn, members_only, member_id).as_deref(),
+            Some("owner")
+        );
+        assert_eq!(
+            role_of(&mut conn, co_owned, admin_id).as_deref(),
+            Some("admin")
+        );
+        let remaining: Vec<i64> = organizations::table
+            .filter(organizations::id.eq_any([shared, members_only, co_owned, alone]))
+            .select(organizations::id)
+            .load_counted(&mut conn)
+            .unwrap();
+        assert_eq!(remaining.len(), 3);
+        assert!(!remaining.contains(&alone));
+
+        let memberships: i64 = organization_members::table
+            .filter(organization_members::user_id.eq(user_id))
+            .count()
+            .get_result(&mut conn)
+            .unwrap();
+        assert_eq!(memberships, 0);
+        let (name, erased_email, deleted_at) = users::table
+            .find(user_id)
+            .select((users::full_name, users::email, users::deleted_at))
+            .first::<(String, String, Option<chrono::DateTime<Utc>>)>(&mut conn)
+            .unwrap();
+        assert_eq!(name, "Erased user");
+        assert_eq!(erased_email, format!("erased-{user_id}@invalid"));
+        assert!(deleted_at.is_some());
+        let left: i64 = invitations::table
+            .filter(invitations::email.eq(&email))
+            .count()
+            .get_result(&mut conn)
+            .unwrap();
+        assert_eq!(left, 0);
+        let invited_by: Option<i64> = invitations::table
+            .filter(invitations::email.eq("someone@example.com"))
+            .select(invitations::invited_by)
+            .first(&mut conn)
+            .unwrap();
+        assert_eq!(invited_by, None);
+        let links: i64 = magic_links::table
+            .filter(magic_links::email.eq(&email))
+            .count()
+            .get_result(&mut conn)
+            .unwrap();
+        assert_eq!(links, 0);
+        let status: String = erasure_requests::table
+            .find(request.id)
+            .select(erasure_requests::status)
+            .first(&mut conn)
+            .unwrap();
+        assert_eq!(status, ERASURE_COMPLETED);
+
+        let details: serde_json::Value = audit_logs::table
+            .filter(audit_logs::action.eq("user.erased"))
+            .filter(audit_logs::subject_id.eq(user_id))
+            .select(audit_logs::details)
+            .first(&mut conn)
+            .unwrap();
+        assert_eq!(details["identities"], 1);
+        // The membership of the deleted organization went with it
+        assert_eq!(details["memberships"], 3);
+        assert_eq!(
+            details["ownershipTransferred"],
+            json!([shared.to_string(), members_only.to_string()])
+        );
+        assert_eq!(details["organizationsDeleted"], json!([alone.to_string()]));
+    }
+}
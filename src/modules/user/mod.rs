@@ -1,8 +1,12 @@
 pub mod user_controller;
 pub mod user_model;
 pub mod user_service;
-use crate::AppState;
-use axum::{routing::get, Router};
+use crate::{constant, AppState};
+use axum::{
+    extract::DefaultBodyLimit,
+    routing::{get, post},
+    Router,
+};
 use std::sync::Arc;
 
 // Define Routes
@@ -10,7 +14,29 @@ pub struct Routes;
 impl Routes {
     pub fn index() -> Router<Arc<AppState>> {
         Router::new()
-            .route("/", get(user_controller::get_all_users_use_struct))
-            .route("/json", get(user_controller::get_all_users_use_json))
+            .route(
+                "/",
+                get(user_controller::list_users).post(user_controller::create_user),
+            )
+            .route("/import", post(user_controller::import_users))
+            .route("/export", get(user_controller::export_users))
+            .route("/me/overview", get(user_controller::get_overview))
+            .route(
+                "/:id",
+                get(user_controller::get_user)
+                    .put(user_controller::update_user)
+                    .patch(user_controller::patch_user)
+                    .delete(user_controller::delete_user),
+            )
+            .route("/:id/restore", post(user_controller::restore_user))
+            .route(
+                "/:id/avatar",
+                get(user_controller::get_avatar)
+                    .put(user_controller::update_avatar)
+                    // Leave room for the multipart envelope, the image itself is checked later
+                    .layer(DefaultBodyLimit::max(
+                        constant::AVATAR_MAX_BYTES + 64 * 1024,
+                    )),
+            )
     }
 }
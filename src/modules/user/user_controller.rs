@@ -1,66 +1,406 @@
 use crate::{
-    utils::{errors::HttpError, responses::HttpResponse},
+    constant,
+    middlewares::auth_middlewares::{AdminUser, AuthUser},
+    middlewares::tenant_middlewares::OptionalTenant,
+    repository::soft_delete::DeletedScope,
+    utils::{
+        errors::HttpError,
+        etag::{conditional_response, with_etag, IfMatch, IfNoneMatch, Versioned},
+        export::{self, ExportFormat},
+        extractor::BodyJson,
+        list_query::ListQuery,
+        patch::BodyPatch,
+        responses::HttpResponse,
+    },
     AppState,
 };
-use axum::{
-    extract::State,
-    // Extension,
-};
-use serde_json::json;
+use axum::body::Body;
+use axum::extract::{Path, Query, State};
+use axum::http::{header, HeaderMap, HeaderValue};
+use axum::response::Response;
+use axum_typed_multipart::TypedMultipart;
 use std::sync::Arc;
 
-use super::{user_model::UserData, user_service};
+use super::{
+    user_model::{
+        AvatarQuery, AvatarUpload, CreateUserRequest, ExportQuery, ImportQuery, ImportReport,
+        UpdateUserRequest, UserData, UserListConfig, UserOverviewData,
+    },
+    user_service,
+};
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/users",
+    params(
+        ("page" = Option<i64>, Query, description = "Page number, starts at 1"),
+        ("limit" = Option<i64>, Query, description = "Items per page, max 100"),
+        ("cursor" = Option<String>, Query, description = "Opaque cursor, empty for the first page"),
+        ("sort" = Option<String>, Query, description = "e.g. -created_at,full_name"),
+        ("include_deleted" = Option<bool>, Query, description = "Include soft deleted users, admin only"),
+        ("email[like]" = Option<String>, Query, description = "Filters: email, full_name, phone_number with eq/in/like, created_at and updated_at with gte")
+    ),
+    responses(
+        (status = 200, description = "Paginated users", body = [UserData]),
+        (status = 400, description = "Invalid pagination, sort or filter"),
+        (status = 403, description = "Admin only")
+    ),
+    security(("bearer_auth" = [])),
+    tag = "users"
+)]
+pub async fn list_users(
+    State(state): State<Arc<AppState>>,
+    _admin: AdminUser,
+    OptionalTenant(tenant): OptionalTenant,
+    query: ListQuery<UserListConfig>,
+) -> Result<HttpResponse<serde_json::Value>, HttpError> {
+    let tenant_id = tenant.map(|tenant| tenant.id);
+    let page = user_service::list_users(&state.db, query.clone(), tenant_id).await?;
+    let data = page.map(UserData::from).into_json(&query);
+    Ok(HttpResponse::ok(data, "USERS_FETCHED"))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/users/{id}",
+    params(
+        ("id" = i64, Path, description = "User id"),
+        DeletedScope
+    ),
+    responses(
+        (status = 200, description = "User detail, the ETag header carries its version", body = UserData),
+        (status = 304, description = "If-None-Match still matches the current version"),
+        (status = 403, description = "Only the user or an admin can read it, include_deleted requires an admin"),
+        (status = 404, description = "User not found")
+    ),
+    security(("bearer_auth" = [])),
+    tag = "users"
+)]
+pub async fn get_user(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    OptionalTenant(tenant): OptionalTenant,
+    if_none_match: IfNoneMatch,
+    Path(user_id): Path<i64>,
+    Query(scope): Query<DeletedScope>,
+) -> Result<Response, HttpError> {
+    // The role is only looked up for other users or when deleted users are asked for
+    if scope.include_deleted {
+        auth.ensure_admin(&state).await?;
+    } else {
+        auth.ensure_self_or_admin(&state, user_id).await?;
+    }
+    let tenant_id = tenant.map(|tenant| tenant.id);
+    let user = user_service::get_user(&state.db, user_id, scope.include_deleted, tenant_id).await?;
+    let etag = user.etag();
+    Ok(conditional_response(
+        &if_none_match,
+        &etag,
+        HttpResponse::<UserData>::ok(user.into(), "USER_FETCHED"),
+    ))
+}
 
 #[utoipa::path(
     get,
+    path = "/api/v1/users/me/overview",
+    responses(
+        (status = 200, description = "Profile summary of the current user", body = UserOverviewData),
+        (status = 404, description = "User not found")
+    ),
+    security(("bearer_auth" = [])),
+    tag = "users"
+)]
+pub async fn get_overview(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+) -> Result<HttpResponse<UserOverviewData>, HttpError> {
+    let overview = user_service::get_overview(&state.db, auth.user_id).await?;
+    Ok(HttpResponse::ok(overview.into(), "USER_OVERVIEW_FETCHED"))
+}
+
+#[utoipa::path(
+    post,
     path = "/api/v1/users",
+    request_body = CreateUserRequest,
     responses(
-        (status = 200, description = "List users", body = [UserData])
-    )
+        (status = 201, description = "User created", body = UserData),
+        (status = 400, description = "Invalid request body"),
+        (status = 403, description = "Admin only"),
+        (status = 409, description = "Email already registered")
+    ),
+    security(("bearer_auth" = [])),
+    tag = "users"
 )]
-pub async fn get_all_users_use_struct(
-    // Extension(_token): Extension<String>,
-    State(_state): State<Arc<AppState>>,
-    // BodyJson(_body): BodyJson<serde_json::Value>,
-    // Query(_params): Query<HashMap<String, String>>,
+pub async fn create_user(
+    State(state): State<Arc<AppState>>,
+    _admin: AdminUser,
+    BodyJson(body): BodyJson<CreateUserRequest>,
 ) -> Result<HttpResponse<UserData>, HttpError> {
-    let user_data = UserData {
-        user_id: "123456789".to_string(),
-        full_name: "Thomi".to_string(),
-        email: "thomi@example.com".to_string(),
-        phone_number: "1234567890".to_string(),
-    };
+    let user = user_service::create_user(&state.db, body).await?;
+    Ok(HttpResponse::created(user.into(), "USER_CREATED"))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/users/{id}",
+    params(
+        ("id" = i64, Path, description = "User id"),
+        ("If-Match" = String, Header, description = "ETag of the version being replaced, or *")
+    ),
+    request_body = UpdateUserRequest,
+    responses(
+        (status = 200, description = "User updated", body = UserData),
+        (status = 400, description = "Invalid request body"),
+        (status = 403, description = "Admin only"),
+        (status = 404, description = "User not found"),
+        (status = 409, description = "Email already registered"),
+        (status = 412, description = "User changed since it was read"),
+        (status = 428, description = "If-Match header missing")
+    ),
+    security(("bearer_auth" = [])),
+    tag = "users"
+)]
+pub async fn update_user(
+    State(state): State<Arc<AppState>>,
+    _admin: AdminUser,
+    Path(user_id): Path<i64>,
+    if_match: IfMatch,
+    BodyJson(body): BodyJson<UpdateUserRequest>,
+) -> Result<Response, HttpError> {
+    let expected_version = if_match.expected_version(user_id)?;
+    let user = user_service::update_user(&state.db, user_id, expected_version, body).await?;
+    let etag = user.etag();
+    Ok(with_etag(
+        &etag,
+        HttpResponse::<UserData>::ok(user.into(), "USER_UPDATED"),
+    ))
+}
 
-    // Call the service function
-    user_service::get_print_user();
+#[utoipa::path(
+    patch,
+    path = "/api/v1/users/{id}",
+    params(
+        ("id" = i64, Path, description = "User id"),
+        ("If-Match" = String, Header, description = "ETag of the version being patched, or *")
+    ),
+    request_body(
+        content = Object,
+        content_type = "application/merge-patch+json",
+        description = "JSON Merge Patch (RFC 7396) over the fields of UpdateUserRequest, a JSON Patch (RFC 6902) is accepted as application/json-patch+json"
+    ),
+    responses(
+        (status = 200, description = "User patched", body = UserData),
+        (status = 400, description = "Malformed patch or patched user is invalid"),
+        (status = 403, description = "Admin only"),
+        (status = 404, description = "User not found"),
+        (status = 409, description = "Email already registered"),
+        (status = 412, description = "User changed since it was read"),
+        (status = 415, description = "Unsupported patch content type"),
+        (status = 422, description = "Patch operation could not be applied"),
+        (status = 428, description = "If-Match header missing")
+    ),
+    security(("bearer_auth" = [])),
+    tag = "users"
+)]
+pub async fn patch_user(
+    State(state): State<Arc<AppState>>,
+    _admin: AdminUser,
+    Path(user_id): Path<i64>,
+    if_match: IfMatch,
+    patch: BodyPatch,
+) -> Result<Response, HttpError> {
+    let expected_version = if_match.expected_version(user_id)?;
+    let user = user_service::patch_user(&state.db, user_id, expected_version, patch).await?;
+    let etag = user.etag();
+    Ok(with_etag(
+        &etag,
+        HttpResponse::<UserData>::ok(user.into(), "USER_UPDATED"),
+    ))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/users/{id}",
+    params(
+        ("id" = i64, Path, description = "User id"),
+        ("If-Match" = String, Header, description = "ETag of the version being deleted, or *")
+    ),
+    responses(
+        (status = 200, description = "User soft deleted, purged after the retention period"),
+        (status = 403, description = "Admin only"),
+        (status = 404, description = "User not found"),
+        (status = 412, description = "User changed since it was read"),
+        (status = 428, description = "If-Match header missing")
+    ),
+    security(("bearer_auth" = [])),
+    tag = "users"
+)]
+pub async fn delete_user(
+    State(state): State<Arc<AppState>>,
+    _admin: AdminUser,
+    Path(user_id): Path<i64>,
+    if_match: IfMatch,
+) -> Result<HttpResponse<()>, HttpError> {
+    let expected_version = if_match.expected_version(user_id)?;
+    user_service::delete_user(&state.db, user_id, expected_version).await?;
+    Ok(HttpResponse::delete(user_id.to_string()))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/users/{id}/restore",
+    params(
+        ("id" = i64, Path, description = "User id")
+    ),
+    responses(
+        (status = 200, description = "User restored", body = UserData),
+        (status = 403, description = "Admin only"),
+        (status = 404, description = "No deleted user with this id"),
+        (status = 409, description = "Email registered again since the deletion")
+    ),
+    security(("bearer_auth" = [])),
+    tag = "users"
+)]
+pub async fn restore_user(
+    State(state): State<Arc<AppState>>,
+    _admin: AdminUser,
+    Path(user_id): Path<i64>,
+) -> Result<Response, HttpError> {
+    let user = user_service::restore_user(&state.db, user_id).await?;
+    let etag = user.etag();
+    Ok(with_etag(
+        &etag,
+        HttpResponse::<UserData>::ok(user.into(), "USER_RESTORED"),
+    ))
+}
 
-    // Return the response
-    Ok(HttpResponse::ok(user_data, "CREDENTIAL_PIN_ADDED"))
+#[utoipa::path(
+    put,
+    path = "/api/v1/users/{id}/avatar",
+    params(
+        ("id" = i64, Path, description = "User id")
+    ),
+    request_body(content = AvatarUpload, content_type = "multipart/form-data"),
+    responses(
+        (status = 200, description = "Avatar replaced", body = UserData),
+        (status = 400, description = "Missing or unreadable image"),
+        (status = 403, description = "Only the user or an admin can change the avatar"),
+        (status = 413, description = "Image larger than 5 MiB or 4096 pixels"),
+        (status = 415, description = "Not a JPEG, PNG or WebP image")
+    ),
+    security(("bearer_auth" = [])),
+    tag = "users"
+)]
+pub async fn update_avatar(
+    State(state): State<Arc<AppState>>,
+    auth: AuthUser,
+    Path(user_id): Path<i64>,
+    TypedMultipart(body): TypedMultipart<AvatarUpload>,
+) -> Result<Response, HttpError> {
+    auth.ensure_self_or_admin(&state, user_id).await?;
+    let user = user_service::update_avatar(&state.db, user_id, body.avatar).await?;
+    let etag = user.etag();
+    Ok(with_etag(
+        &etag,
+        HttpResponse::<UserData>::ok(user.into(), "AVATAR_UPDATED"),
+    ))
 }
 
 #[utoipa::path(
     get,
-    path = "/api/v1/users/json",
+    path = "/api/v1/users/{id}/avatar",
+    params(
+        ("id" = i64, Path, description = "User id"),
+        AvatarQuery
+    ),
     responses(
-        (status = 200, description = "List users as JSON", body = serde_json::Value)
-    )
+        (status = 200, description = "PNG thumbnail", content_type = "image/png"),
+        (status = 304, description = "Cached copy is still current"),
+        (status = 404, description = "User has no avatar")
+    ),
+    tag = "users"
 )]
-pub async fn get_all_users_use_json(
-    // Extension(_token): Extension<String>,
-    State(_state): State<Arc<AppState>>,
-    // Json(_body): Json<serde_json::Value>,
-    // Query(_params): Query<HashMap<String, String>>,
-) -> Result<HttpResponse<serde_json::Value>, HttpError> {
-    let user_data = json!({
-        "user_id": "123456789",
-        "full_name": "Thomi",
-        "email": "thomi@example.com",
-        "phone_number": "1234567890"
-    });
+pub async fn get_avatar(
+    State(state): State<Arc<AppState>>,
+    Path(user_id): Path<i64>,
+    Query(query): Query<AvatarQuery>,
+    if_none_match: IfNoneMatch,
+) -> Result<Response, HttpError> {
+    let (contents, etag) = user_service::read_avatar(&state.db, user_id, query.size).await?;
+    let mut response = conditional_response(
+        &if_none_match,
+        &etag,
+        ([(header::CONTENT_TYPE, "image/png")], contents),
+    );
+    response.headers_mut().insert(
+        header::CACHE_CONTROL,
+        HeaderValue::from_static(constant::AVATAR_CACHE_CONTROL),
+    );
+    Ok(response)
+}
 
-    // Call the service function
-    user_service::get_print_user();
+#[utoipa::path(
+    post,
+    path = "/api/v1/users/import",
+    params(ImportQuery),
+    request_body(
+        content = String,
+        content_type = "text/csv",
+        description = "CSV with a fullName,email,phoneNumber header, at most 50 MiB"
+    ),
+    responses(
+        (status = 200, description = "Import report with the rejected rows", body = ImportReport),
+        (status = 400, description = "Malformed CSV, rows of batches already committed are kept"),
+        (status = 403, description = "Admin only"),
+        (status = 413, description = "File larger than 50 MiB")
+    ),
+    security(("bearer_auth" = [])),
+    tag = "users"
+)]
+pub async fn import_users(
+    State(state): State<Arc<AppState>>,
+    _admin: AdminUser,
+    Query(query): Query<ImportQuery>,
+    body: Body,
+) -> Result<HttpResponse<ImportReport>, HttpError> {
+    let report = user_service::import_users(&state.db, body, query.dry_run).await?;
+    let message = if report.dry_run {
+        "USERS_IMPORT_CHECKED"
+    } else {
+        "USERS_IMPORTED"
+    };
+    Ok(HttpResponse::ok(report, message))
+}
 
-    // Return the response
-    Ok(HttpResponse::ok(user_data, "CREDENTIAL_PIN_ADDED"))
+#[utoipa::path(
+    get,
+    path = "/api/v1/users/export",
+    params(
+        ExportQuery,
+        ("sort" = Option<String>, Query, description = "e.g. -created_at,full_name"),
+        ("include_deleted" = Option<bool>, Query, description = "Include soft deleted users"),
+        ("email[like]" = Option<String>, Query, description = "Same filters as the users list")
+    ),
+    responses(
+        (status = 200, description = "All matching users as a CSV or NDJSON download", content_type = "text/csv"),
+        (status = 400, description = "Invalid format, sort or filter"),
+        (status = 403, description = "Admin only")
+    ),
+    security(("bearer_auth" = [])),
+    tag = "users"
+)]
+pub async fn export_users(
+    State(state): State<Arc<AppState>>,
+    _admin: AdminUser,
+    OptionalTenant(tenant): OptionalTenant,
+    Query(params): Query<ExportQuery>,
+    headers: HeaderMap,
+    query: ListQuery<UserListConfig>,
+) -> Result<Response, HttpError> {
+    let format = ExportFormat::negotiate(params.format.as_deref(), &headers)?;
+    let export = user_service::export_users(&query, tenant.map(|tenant| tenant.id))?;
+    let file_name = format!("users-{}", chrono::Utc::now().format("%Y%m%dT%H%M%SZ"));
+    Ok(export::export_response(
+        &state.db, format, &file_name, export,
+    ))
 }
@@ -1,5 +1,21 @@
+use crate::repository::crud::Entity;
+use crate::repository::memory::MemoryEntity;
+use crate::repository::soft_delete::SoftDelete;
+use crate::schema::table::users;
+use crate::schema::view::user_overviews;
+use crate::utils::etag::Versioned;
+use crate::utils::generator;
+use crate::utils::list_query::{FilterOp, ListConfig};
+use axum::body::Bytes;
+use axum_typed_multipart::{FieldData, TryFromMultipart};
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
 use serde::{Deserialize, Serialize};
-use utoipa::ToSchema;
+use utoipa::{IntoParams, ToSchema};
+use validator::Validate;
+
+// Outbox event recorded with every new account
+pub const USER_REGISTERED: &str = "user.registered";
 
 #[derive(Serialize, Deserialize, ToSchema)]
 #[serde(rename_all = "camelCase")]
@@ -7,5 +23,333 @@ pub struct UserData {
     pub user_id: String,
     pub full_name: String,
     pub email: String,
-    pub phone_number: String,
+    pub phone_number: Option<String>,
+    pub role: String,
+    // Changes with every upload so clients can cache the image for long
+    pub avatar_url: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    // Only set on soft deleted users, which admins list with include_deleted
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<DateTime<Utc>>,
+}
+
+impl From<User> for UserData {
+    fn from(user: User) -> Self {
+        UserData {
+            // Snowflake ids exceed the JS safe integer range, expose them as string
+            user_id: user.id.to_string(),
+            full_name: user.full_name,
+            email: user.email,
+            phone_number: user.phone_number,
+            role: user.role,
+            avatar_url: user
+                .avatar_key
+                .map(|key| format!("/api/v1/users/{}/avatar?v={key}", user.id)),
+            created_at: user.created_at,
+            updated_at: user.updated_at,
+            deleted_at: user.deleted_at,
+        }
+    }
+}
+
+/// Row of the users export, every column is always present so CSV rows line up
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserExport {
+    pub user_id: String,
+    pub full_name: String,
+    pub email: String,
+    pub phone_number: Option<String>,
+    pub role: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub deleted_at: Option<DateTime<Utc>>,
+}
+
+impl From<User> for UserExport {
+    fn from(user: User) -> Self {
+        UserExport {
+            user_id: user.id.to_string(),
+            full_name: user.full_name,
+            email: user.email,
+            phone_number: user.phone_number,
+            role: user.role,
+            created_at: user.created_at,
+            updated_at: user.updated_at,
+            deleted_at: user.deleted_at,
+        }
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct ExportQuery {
+    /// `csv` or `ndjson`, overrides the Accept header
+    pub format: Option<String>,
+}
+
+/// Sort and filter whitelist of `GET /users`
+pub struct UserListConfig;
+impl ListConfig for UserListConfig {
+    const SORT_FIELDS: &'static [&'static str] =
+        &["id", "full_name", "email", "created_at", "updated_at"];
+    const FILTER_FIELDS: &'static [(&'static str, &'static [FilterOp])] = &[
+        ("email", &[FilterOp::Eq, FilterOp::In, FilterOp::Like]),
+        ("full_name", &[FilterOp::Eq, FilterOp::Like]),
+        ("phone_number", &[FilterOp::Eq, FilterOp::Like]),
+        ("created_at", &[FilterOp::Gte]),
+        ("updated_at", &[FilterOp::Gte]),
+    ];
+}
+
+#[derive(TryFromMultipart, ToSchema)]
+pub struct AvatarUpload {
+    /// JPEG, PNG or WebP image
+    #[schema(value_type = String, format = Binary)]
+    pub avatar: FieldData<Bytes>,
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct AvatarQuery {
+    /// Thumbnail edge in pixels, one of 64, 128 or 256 (default)
+    pub size: Option<u32>,
+    /// Avatar version from `avatarUrl`, only used to bust caches
+    pub v: Option<String>,
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct ImportQuery {
+    /// Validate and check duplicates without inserting anything
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Default, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportReport {
+    pub dry_run: bool,
+    pub total_rows: u64,
+    /// Rows inserted, or that would be inserted on a dry run
+    pub imported: u64,
+    pub rejected: u64,
+    /// Rejected rows, capped to the first 1000
+    pub errors: Vec<ImportRowError>,
+    pub errors_truncated: bool,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportRowError {
+    /// Line in the CSV file, the header is line 1
+    pub line: u64,
+    pub email: Option<String>,
+    pub error: String,
+}
+
+impl ImportReport {
+    pub fn reject(
+        &mut self,
+        line: u64,
+        email: Option<String>,
+        error: impl Into<String>,
+    ) {
+        self.rejected += 1;
+        self.errors.push(ImportRowError {
+            line,
+            email,
+            error: error.into(),
+        });
+        // Rows of a batch are rejected after later lines, so errors are only cut once
+        // sorted. Cutting whenever twice the cap piled up keeps the memory bounded.
+        if self.errors.len() >= 2 * crate::constant::IMPORT_MAX_REPORTED_ERRORS {
+            self.truncate_errors();
+        }
+    }
+
+    /// Keep the rejected rows with the lowest lines, up to `IMPORT_MAX_REPORTED_ERRORS`.
+    pub fn truncate_errors(&mut self) {
+        self.errors.sort_by_key(|error| error.line);
+        if self.errors.len() > crate::constant::IMPORT_MAX_REPORTED_ERRORS {
+            self.errors
+                .truncate(crate::constant::IMPORT_MAX_REPORTED_ERRORS);
+            self.errors_truncated = true;
+        }
+    }
+}
+
+#[derive(Deserialize, Validate, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateUserRequest {
+    #[validate(length(min = 1, max = 255, message = "Full name is required"))]
+    pub full_name: String,
+    #[validate(email(message = "Invalid email address"), length(max = 255))]
+    pub email: String,
+    #[validate(length(min = 6, max = 32, message = "Invalid phone number"))]
+    pub phone_number: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Validate, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateUserRequest {
+    #[validate(length(min = 1, max = 255, message = "Full name is required"))]
+    pub full_name: String,
+    #[validate(email(message = "Invalid email address"), length(max = 255))]
+    pub email: String,
+    #[validate(length(min = 6, max = 32, message = "Invalid phone number"))]
+    pub phone_number: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UserOverviewData {
+    pub user_id: String,
+    pub full_name: String,
+    pub email: String,
+    pub role: String,
+    pub created_at: DateTime<Utc>,
+    pub organization_count: i64,
+    pub identity_count: i64,
+}
+
+impl From<UserOverview> for UserOverviewData {
+    fn from(overview: UserOverview) -> Self {
+        UserOverviewData {
+            user_id: overview.id.to_string(),
+            full_name: overview.full_name,
+            email: overview.email,
+            role: overview.role,
+            created_at: overview.created_at,
+            organization_count: overview.organization_count,
+            identity_count: overview.identity_count,
+        }
+    }
+}
+
+/// Row of the `user_overviews` view, also the JSON `get_user_overview` returns
+#[derive(Debug, Clone, Queryable, Selectable, Deserialize)]
+#[diesel(table_name = user_overviews)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct UserOverview {
+    pub id: i64,
+    pub full_name: String,
+    pub email: String,
+    pub role: String,
+    pub created_at: DateTime<Utc>,
+    pub organization_count: i64,
+    pub identity_count: i64,
+}
+
+#[derive(Debug, Clone, Queryable, Selectable)]
+#[diesel(table_name = users)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct User {
+    pub id: i64,
+    pub full_name: String,
+    pub email: String,
+    pub phone_number: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub role: String,
+    pub deleted_at: Option<DateTime<Utc>>,
+    pub avatar_key: Option<String>,
+    pub version: i32,
+}
+
+impl Versioned for User {
+    fn id(&self) -> i64 {
+        self.id
+    }
+
+    fn version(&self) -> i32 {
+        self.version
+    }
+}
+
+impl Entity for User {
+    type Table = users::table;
+    type Id = users::id;
+    type New = NewUser;
+    type Changes = UserChanges;
+
+    const SOFT_DELETE: Option<&'static str> = Some(users::table::TABLE);
+
+    fn id(&self) -> i64 {
+        self.id
+    }
+}
+
+impl MemoryEntity for User {
+    fn create(new: NewUser) -> Self {
+        let now = Utc::now();
+        User {
+            id: new.id,
+            full_name: new.full_name,
+            email: new.email,
+            phone_number: new.phone_number,
+            created_at: now,
+            updated_at: now,
+            role: "user".to_string(),
+            deleted_at: None,
+            avatar_key: None,
+            version: 1,
+        }
+    }
+
+    fn apply(
+        &mut self,
+        changes: UserChanges,
+    ) {
+        self.full_name = changes.full_name;
+        self.email = changes.email;
+        self.phone_number = changes.phone_number;
+        self.updated_at = changes.updated_at;
+    }
+
+    fn set_deleted_at(
+        &mut self,
+        deleted_at: Option<DateTime<Utc>>,
+    ) {
+        self.deleted_at = deleted_at;
+    }
+}
+
+#[derive(Debug, Clone, Insertable)]
+#[diesel(table_name = users)]
+pub struct NewUser {
+    pub id: i64,
+    pub full_name: String,
+    pub email: String,
+    pub phone_number: Option<String>,
+}
+
+#[derive(Debug, Clone, AsChangeset)]
+#[diesel(table_name = users)]
+#[diesel(treat_none_as_null = true)]
+pub struct UserChanges {
+    pub full_name: String,
+    pub email: String,
+    pub phone_number: Option<String>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Editable fields of a user, the document `PATCH /users/{id}` is applied to
+impl From<&User> for UpdateUserRequest {
+    fn from(user: &User) -> Self {
+        UpdateUserRequest {
+            full_name: user.full_name.clone(),
+            email: user.email.clone(),
+            phone_number: user.phone_number.clone(),
+        }
+    }
+}
+
+impl From<CreateUserRequest> for NewUser {
+    fn from(payload: CreateUserRequest) -> Self {
+        NewUser {
+            id: generator::id() as i64,
+            full_name: payload.full_name.trim().to_string(),
+            email: payload.email.trim().to_lowercase(),
+            phone_number: payload.phone_number,
+        }
+    }
 }
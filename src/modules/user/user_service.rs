@@ -1,4 +1,895 @@
-pub fn get_print_user() {
-    // This is a sample function
-    println!("get_all_users");
+use super::user_model::{
+    CreateUserRequest, ImportReport, NewUser, UpdateUserRequest, User, UserChanges, UserExport,
+    UserListConfig, UserOverview, USER_REGISTERED,
+};
+use crate::constant;
+use crate::database::function::FunctionCall;
+use crate::database::instrumentation::CountedRunQueryDsl;
+use crate::database::instrumentation::QueryScope;
+use crate::database::{set_tenant, Database};
+use crate::outbox;
+use crate::repository::soft_delete;
+use crate::schema::table::{organization_members, users};
+use crate::utils::errors::HttpError;
+use crate::utils::etag::{precondition_failed, ETag};
+use crate::utils::export::RowSink;
+use crate::utils::extractor::format_validation_errors;
+use crate::utils::list_query::{Cursor, ListQuery, Page, SortDirection};
+use crate::utils::patch::BodyPatch;
+use crate::utils::thumbnail::{self, ThumbnailError};
+use crate::utils::{files, generator};
+use crate::{exclude_deleted, filter_column, filter_text_column, sort_column, tenant_scoped};
+use axum::body::{Body, Bytes};
+use axum::http::StatusCode;
+use axum_typed_multipart::{FieldData, FieldMetadata};
+use chrono::{DateTime, Utc};
+use diesel::pg::{Pg, PgRowByRowLoadingMode};
+use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+use futures_util::TryStreamExt;
+use serde_json::json;
+use std::collections::HashSet;
+use std::io::{self, Read};
+use tokio_util::io::{StreamReader, SyncIoBridge};
+use validator::Validate;
+
+// Translate database failures, email is the only unique column users can collide on
+fn database_error(err: anyhow::Error) -> HttpError {
+    match err.downcast_ref::<DieselError>() {
+        Some(DieselError::NotFound) => HttpError::not_found("USER_NOT_FOUND"),
+        Some(DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => {
+            HttpError::unique_constraint_violation("USER_EMAIL_ALREADY_EXISTS")
+        }
+        _ => err.into(),
+    }
+}
+
+// Users visible to the request
+fn scoped_users(
+    include_deleted: bool,
+    tenant_id: Option<i64>,
+) -> users::BoxedQuery<'static, Pg> {
+    let statement = exclude_deleted!(
+        users::table.into_boxed(),
+        users::deleted_at,
+        include_deleted
+    );
+    match tenant_id {
+        // Users are global, a tenant sees its members only
+        Some(tenant_id) => statement.filter(
+            users::id.eq_any(
+                tenant_scoped!(
+                    organization_members::table,
+                    organization_members::organization_id,
+                    tenant_id
+                )
+                .select(organization_members::user_id),
+            ),
+        ),
+        None => statement,
+    }
+}
+
+// Users matching the whitelisted filters of the list query
+fn filtered_users(
+    query: &ListQuery<UserListConfig>,
+    tenant_id: Option<i64>,
+) -> Result<users::BoxedQuery<'static, Pg>, HttpError> {
+    let mut statement = scoped_users(query.include_deleted, tenant_id);
+    for filter in &query.filters {
+        statement = match filter.field.as_str() {
+            "email" => filter_text_column!(statement, users::email, filter),
+            "full_name" => filter_text_column!(statement, users::full_name, filter),
+            "phone_number" => filter_text_column!(statement, users::phone_number, filter),
+            "created_at" => filter_column!(statement, users::created_at, filter, DateTime<Utc>),
+            "updated_at" => filter_column!(statement, users::updated_at, filter, DateTime<Utc>),
+            _ => return Err(filter.invalid()),
+        };
+    }
+    Ok(statement)
+}
+
+// Apply the requested sort, ties are broken by id so pages and exports are stable
+fn sorted_users(
+    mut statement: users::BoxedQuery<'static, Pg>,
+    query: &ListQuery<UserListConfig>,
+) -> users::BoxedQuery<'static, Pg> {
+    for sort in &query.sort {
+        statement = match sort.field.as_str() {
+            "id" => sort_column!(statement, users::id, sort.direction),
+            "full_name" => sort_column!(statement, users::full_name, sort.direction),
+            "email" => sort_column!(statement, users::email, sort.direction),
+            "created_at" => sort_column!(statement, users::created_at, sort.direction),
+            "updated_at" => sort_column!(statement, users::updated_at, sort.direction),
+            _ => statement,
+        };
+    }
+    statement.then_order_by(users::id.desc())
+}
+
+/// Page of users, the members of `tenant_id` only when the request has a tenant.
+pub async fn list_users(
+    db: &Database,
+    query: ListQuery<UserListConfig>,
+    tenant_id: Option<i64>,
+) -> Result<Page<User>, HttpError> {
+    let load = move |conn: &mut PgConnection| {
+        let mut statement = filtered_users(&query, tenant_id)?;
+
+        if let Some(cursor) = query.cursor {
+            // Keyset pagination on the time ordered snowflake id
+            let direction = query.cursor_direction();
+            if let Some(Cursor { id }) = cursor {
+                statement = match direction {
+                    SortDirection::Asc => statement.filter(users::id.gt(id)),
+                    SortDirection::Desc => statement.filter(users::id.lt(id)),
+                };
+            }
+            let mut items: Vec<User> = sort_column!(statement, users::id, direction)
+                .select(User::as_select())
+                .limit(query.limit + 1)
+                .load_counted(conn)?;
+            let next_cursor = if items.len() as i64 > query.limit {
+                items.truncate(query.limit as usize);
+                items.last().map(|user| Cursor { id: user.id }.encode())
+            } else {
+                None
+            };
+            return Ok(Page {
+                items,
+                total: None,
+                next_cursor,
+            });
+        }
+
+        let total: i64 = filtered_users(&query, tenant_id)?
+            .count()
+            .get_result(conn)?;
+        let items = sorted_users(statement, &query)
+            .select(User::as_select())
+            .limit(query.limit)
+            .offset(query.offset())
+            .load_counted(conn)?;
+        Ok(Page {
+            items,
+            total: Some(total),
+            next_cursor: None,
+        })
+    };
+    match tenant_id {
+        Some(tenant_id) => db.tenant_transaction(tenant_id, load).await,
+        None => db.execute(load).await,
+    }
+    .map_err(database_error)
+}
+
+/// Build the export of the users matching the list query, run by `export::export_response`.
+///
+/// Filters are checked here so an invalid query fails before the response starts.
+pub fn export_users(
+    query: &ListQuery<UserListConfig>,
+    tenant_id: Option<i64>,
+) -> Result<impl FnOnce(&mut PgConnection, &mut RowSink) -> anyhow::Result<()>, HttpError> {
+    let statement = sorted_users(filtered_users(query, tenant_id)?, query);
+    Ok(move |conn: &mut PgConnection, sink: &mut RowSink| {
+        // The tenant setting only lasts until the end of a transaction
+        conn.build_transaction().read_only().run(|conn| {
+            if let Some(tenant_id) = tenant_id {
+                set_tenant(conn, tenant_id)?;
+            }
+            let rows = statement
+                .select(User::as_select())
+                .load_iter_counted::<User, PgRowByRowLoadingMode>(conn)?;
+            for user in rows {
+                sink.push(&UserExport::from(user?))?;
+            }
+            Ok(())
+        })
+    })
+}
+
+/// Profile summary of a live user, from the `get_user_overview` function.
+///
+/// The memberships of every organization count, so it runs as the role bypassing row level
+/// security.
+pub async fn get_overview(
+    db: &Database,
+    user_id: i64,
+) -> Result<UserOverview, HttpError> {
+    db.unscoped_transaction(move |conn| Ok(load_overview(conn, user_id)?))
+        .await
+        .map_err(|err| match HttpError::from(err) {
+            err if err.status == StatusCode::NOT_FOUND => HttpError::not_found("USER_NOT_FOUND"),
+            err => err,
+        })
+}
+
+fn load_overview(
+    conn: &mut PgConnection,
+    user_id: i64,
+) -> QueryResult<UserOverview> {
+    FunctionCall::new("get_user_overview")
+        .arg::<diesel::sql_types::BigInt, _>(user_id)
+        .get_result(conn)
+}
+
+/// User by id, 404 when `tenant_id` is given and they are not one of its members.
+pub async fn get_user(
+    db: &Database,
+    user_id: i64,
+    include_deleted: bool,
+    tenant_id: Option<i64>,
+) -> Result<User, HttpError> {
+    let load = move |conn: &mut PgConnection| {
+        Ok(scoped_users(include_deleted, tenant_id)
+            .filter(users::id.eq(user_id))
+            .select(User::as_select())
+            .first(conn)?)
+    };
+    match tenant_id {
+        Some(tenant_id) => db.tenant_transaction(tenant_id, load).await,
+        None => db.execute(load).await,
+    }
+    .map_err(database_error)
+}
+
+/// Announce a new account through the outbox, `source` tells how it was created.
+pub fn record_registered(
+    conn: &mut PgConnection,
+    user_id: i64,
+    source: &str,
+) -> QueryResult<()> {
+    outbox::record(
+        conn,
+        USER_REGISTERED,
+        "user",
+        user_id,
+        json!({ "userId": user_id.to_string(), "source": source }),
+    )?;
+    Ok(())
+}
+
+pub async fn create_user(
+    db: &Database,
+    payload: CreateUserRequest,
+) -> Result<User, HttpError> {
+    let new_user = NewUser::from(payload);
+    db.transaction(move |conn| {
+        let user = diesel::insert_into(users::table)
+            .values(&new_user)
+            .returning(User::as_returning())
+            .get_result(conn)?;
+        record_registered(conn, user.id, "admin")?;
+        Ok(user)
+    })
+    .await
+    .map_err(database_error)
+}
+
+// Bump the version of a live user, 412 when `expected` is stale and 404 when it is missing.
+// Claiming first means a concurrent writer holding the same tag fails instead of overwriting.
+fn claim_version(
+    conn: &mut PgConnection,
+    user_id: i64,
+    expected: Option<i32>,
+) -> anyhow::Result<()> {
+    let live_user = users::table
+        .find(user_id)
+        .filter(users::deleted_at.is_null());
+    let bump = users::version.eq(users::version + 1);
+    let claimed = match expected {
+        Some(version) => diesel::update(live_user.filter(users::version.eq(version)))
+            .set(bump)
+            .execute_counted(conn)?,
+        None => diesel::update(live_user).set(bump).execute_counted(conn)?,
+    };
+    if claimed > 0 {
+        return Ok(());
+    }
+    let exists: bool = diesel::select(diesel::dsl::exists(live_user)).get_result(conn)?;
+    if exists {
+        Err(precondition_failed().into())
+    } else {
+        Err(HttpError::not_found("USER_NOT_FOUND").into())
+    }
+}
+
+/// Replace the profile, `expected_version` comes from `If-Match` (`None` for `*`).
+pub async fn update_user(
+    db: &Database,
+    user_id: i64,
+    expected_version: Option<i32>,
+    payload: UpdateUserRequest,
+) -> Result<User, HttpError> {
+    let changes = UserChanges {
+        full_name: payload.full_name.trim().to_string(),
+        email: payload.email.trim().to_lowercase(),
+        phone_number: payload.phone_number,
+        updated_at: Utc::now(),
+    };
+    db.transaction(move |conn| {
+        claim_version(conn, user_id, expected_version)?;
+        Ok(diesel::update(users::table.find(user_id))
+            .set(&changes)
+            .returning(User::as_returning())
+            .get_result(conn)?)
+    })
+    .await
+    .map_err(database_error)
+}
+
+/// Apply a merge patch or JSON patch to the editable fields of the user.
+///
+/// The patch is applied to the version that was read, so without a concrete `If-Match`
+/// version a concurrent change still fails with 412 instead of being overwritten.
+pub async fn patch_user(
+    db: &Database,
+    user_id: i64,
+    expected_version: Option<i32>,
+    patch: BodyPatch,
+) -> Result<User, HttpError> {
+    let current = get_user(db, user_id, false, None).await?;
+    if expected_version.is_some_and(|version| version != current.version) {
+        return Err(precondition_failed());
+    }
+    let payload = patch.apply(&UpdateUserRequest::from(&current))?;
+    update_user(db, user_id, Some(current.version), payload).await
+}
+
+/// Soft delete, the row is purged once the retention period is over.
+pub async fn delete_user(
+    db: &Database,
+    user_id: i64,
+    expected_version: Option<i32>,
+) -> Result<(), HttpError> {
+    db.transaction(move |conn| {
+        claim_version(conn, user_id, expected_version)?;
+        if !soft_delete::soft_delete::<users::table>(conn, user_id)? {
+            return Err(HttpError::not_found("USER_NOT_FOUND").into());
+        }
+        Ok(())
+    })
+    .await
+    .map_err(database_error)
+}
+
+pub async fn restore_user(
+    db: &Database,
+    user_id: i64,
+) -> Result<User, HttpError> {
+    db.transaction(move |conn| {
+        // Fails with a unique violation when the email was taken again meanwhile
+        if !soft_delete::restore::<users::table>(conn, user_id)? {
+            return Err(HttpError::not_found("DELETED_USER_NOT_FOUND").into());
+        }
+        // A tag read before the deletion must not match the restored row
+        Ok(diesel::update(users::table.find(user_id))
+            .set(users::version.eq(users::version + 1))
+            .returning(User::as_returning())
+            .get_result(conn)?)
+    })
+    .await
+    .map_err(database_error)
+}
+
+// Content types accepted for avatars, the content itself is checked again when decoding
+const AVATAR_CONTENT_TYPES: [&str; 3] = ["image/jpeg", "image/png", "image/webp"];
+
+/// Path of one avatar thumbnail, relative to the uploads root.
+pub fn avatar_path(
+    user_id: i64,
+    key: &str,
+    size: u32,
+) -> String {
+    format!("avatars/{user_id}/{key}_{size}.png")
+}
+
+/// Delete every thumbnail of an avatar, failures are only logged.
+pub async fn delete_avatar_files(
+    user_id: i64,
+    key: &str,
+) {
+    for size in constant::AVATAR_SIZES {
+        if let Err(err) = files::delete_file(avatar_path(user_id, key, size)).await {
+            tracing::warn!(user_id, key, size, error = %err, "AVATAR_DELETE_FAILED");
+        }
+    }
+}
+
+/// Store thumbnails of the uploaded image as the new avatar and delete the previous one.
+pub async fn update_avatar(
+    db: &Database,
+    user_id: i64,
+    upload: FieldData<Bytes>,
+) -> Result<User, HttpError> {
+    if upload.contents.len() > constant::AVATAR_MAX_BYTES {
+        return Err(HttpError::new(
+            "AVATAR_TOO_LARGE",
+            StatusCode::PAYLOAD_TOO_LARGE,
+        ));
+    }
+    let content_type = upload.metadata.content_type.as_deref().unwrap_or_default();
+    if !AVATAR_CONTENT_TYPES.contains(&content_type) {
+        return Err(HttpError::new(
+            "AVATAR_UNSUPPORTED_TYPE",
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+        ));
+    }
+    // Fail before the expensive resize when the user does not exist
+    get_user(db, user_id, false, None).await?;
+
+    let contents = upload.contents;
+    let thumbnails = tokio::task::spawn_blocking(move || {
+        thumbnail::square_thumbnails(
+            &contents,
+            &constant::AVATAR_SIZES,
+            constant::AVATAR_MAX_DIMENSION,
+        )
+    })
+    .await
+    .map_err(|err| {
+        tracing::error!(error = %err, "AVATAR_RESIZE_FAILED");
+        HttpError::server_error("AVATAR_RESIZE_FAILED")
+    })?
+    .map_err(|err| match err {
+        ThumbnailError::UnsupportedFormat => HttpError::new(
+            "AVATAR_UNSUPPORTED_TYPE",
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+        ),
+        ThumbnailError::TooLarge => {
+            HttpError::new("AVATAR_DIMENSIONS_TOO_LARGE", StatusCode::PAYLOAD_TOO_LARGE)
+        }
+        ThumbnailError::Decode(_) => HttpError::bad_request("AVATAR_INVALID_IMAGE"),
+        ThumbnailError::Encode(_) => {
+            tracing::error!(error = %err, "AVATAR_RESIZE_FAILED");
+            HttpError::server_error("AVATAR_RESIZE_FAILED")
+        }
+    })?;
+
+    // Every upload gets a new key, so cached copies of the previous avatar are never served
+    let key = generator::id().to_string();
+    for (size, contents) in constant::AVATAR_SIZES.into_iter().zip(thumbnails) {
+        let file = FieldData {
+            metadata: FieldMetadata {
+                content_type: Some("image/png".to_string()),
+                ..Default::default()
+            },
+            contents: Bytes::from(contents),
+        };
+        let saved = files::save_file(avatar_path(user_id, &key, size), file, false)
+            .await
+            .map_err(|err| err.to_string());
+        if let Err(err) = saved {
+            tracing::error!(user_id, error = %err, "AVATAR_SAVE_FAILED");
+            delete_avatar_files(user_id, &key).await;
+            return Err(HttpError::server_error("AVATAR_SAVE_FAILED"));
+        }
+    }
+
+    let new_key = key.clone();
+    let swapped = db
+        .transaction(move |conn| {
+            let previous: Option<String> = users::table
+                .find(user_id)
+                .filter(users::deleted_at.is_null())
+                .select(users::avatar_key)
+                .for_update()
+                .first(conn)?;
+            let user = diesel::update(users::table.find(user_id))
+                .set((
+                    users::avatar_key.eq(&new_key),
+                    users::updated_at.eq(Utc::now()),
+                    users::version.eq(users::version + 1),
+                ))
+                .returning(User::as_returning())
+                .get_result(conn)?;
+            Ok((user, previous))
+        })
+        .await
+        .map_err(database_error);
+
+    match swapped {
+        Ok((user, previous)) => {
+            if let Some(previous) = previous {
+                delete_avatar_files(user_id, &previous).await;
+            }
+            Ok(user)
+        }
+        Err(err) => {
+            delete_avatar_files(user_id, &key).await;
+            Err(err)
+        }
+    }
+}
+
+/// Avatar thumbnail of a live user with its ETag.
+pub async fn read_avatar(
+    db: &Database,
+    user_id: i64,
+    size: Option<u32>,
+) -> Result<(Bytes, ETag), HttpError> {
+    let size = match size {
+        Some(size) if constant::AVATAR_SIZES.contains(&size) => size,
+        Some(_) => return Err(HttpError::bad_request("AVATAR_INVALID_SIZE")),
+        None => constant::AVATAR_SIZES[constant::AVATAR_SIZES.len() - 1],
+    };
+    let key = get_user(db, user_id, false, None)
+        .await?
+        .avatar_key
+        .ok_or_else(|| HttpError::not_found("AVATAR_NOT_FOUND"))?;
+
+    let contents = files::read_file(avatar_path(user_id, &key, size))
+        .await
+        .map_err(|err| err.to_string())
+        .map_err(|err| {
+            tracing::error!(user_id, key, size, error = %err, "AVATAR_READ_FAILED");
+            HttpError::not_found("AVATAR_NOT_FOUND")
+        })?;
+    Ok((contents, ETag::new(format!("{key}-{size}"))))
+}
+
+/// Import users from a CSV body with a `fullName,email,phoneNumber` header.
+///
+/// The body is streamed through the CSV reader, valid rows are inserted in batches of
+/// `IMPORT_BATCH_SIZE`, each in its own transaction. Rows failing validation or using an
+/// email that is already registered (or repeated in the file) are listed in the report.
+/// A dry run goes through the same inserts inside a transaction that is rolled back.
+pub async fn import_users(
+    db: &Database,
+    body: Body,
+    dry_run: bool,
+) -> Result<ImportReport, HttpError> {
+    let mut received = 0;
+    let stream = body
+        .into_data_stream()
+        .map_err(io::Error::other)
+        .and_then(move |chunk| {
+            received += chunk.len();
+            let result = if received > constant::IMPORT_MAX_BYTES {
+                Err(io::Error::new(
+                    io::ErrorKind::FileTooLarge,
+                    "IMPORT_TOO_LARGE",
+                ))
+            } else {
+                Ok(chunk)
+            };
+            std::future::ready(result)
+        });
+    // The bridge blocks on the async body, it must be read from a blocking thread
+    let reader = SyncIoBridge::new(StreamReader::new(stream));
+
+    let db = db.clone();
+    let scope = QueryScope::capture();
+    tokio::task::spawn_blocking(move || {
+        scope.run(|| {
+            let mut conn = db.get_connection()?;
+            import_csv(&mut *conn, reader, dry_run)
+        })
+    })
+    .await
+    .map_err(|err| {
+        tracing::error!(error = %err, "USER_IMPORT_FAILED");
+        HttpError::server_error("USER_IMPORT_FAILED")
+    })?
+    .map_err(|err: anyhow::Error| {
+        // Broken CSV framing or body aborts the import, rows of committed batches stay
+        if let Some(err) = err.downcast_ref::<csv::Error>() {
+            let line = err
+                .position()
+                .map(|position| position.line())
+                .unwrap_or_default();
+            return match err.kind() {
+                csv::ErrorKind::Io(io) if io.kind() == io::ErrorKind::FileTooLarge => {
+                    HttpError::new("IMPORT_TOO_LARGE", StatusCode::PAYLOAD_TOO_LARGE)
+                }
+                _ => HttpError::bad_request(format!("IMPORT_INVALID_CSV:LINE:{line}")),
+            };
+        }
+        database_error(err)
+    })
+}
+
+// Destination of the imported rows, the import runs on an in-memory one in the tests
+trait ImportStore {
+    /// Insert the rows in one transaction, returning the emails that were not registered yet
+    fn insert(
+        &mut self,
+        rows: &[&NewUser],
+    ) -> anyhow::Result<HashSet<String>>;
+
+    /// Run `import` in a transaction that is rolled back afterwards.
+    fn rolled_back(
+        &mut self,
+        import: impl FnOnce(&mut Self) -> anyhow::Result<()>,
+    ) -> anyhow::Result<()>;
+}
+
+impl ImportStore for PgConnection {
+    fn insert(
+        &mut self,
+        rows: &[&NewUser],
+    ) -> anyhow::Result<HashSet<String>> {
+        let inserted = self.transaction(|conn| {
+            let inserted = diesel::insert_into(users::table)
+                .values(rows.to_vec())
+                .on_conflict_do_nothing()
+                .returning((users::id, users::email))
+                .get_results::<(i64, String)>(conn)?;
+            for (user_id, _) in &inserted {
+                record_registered(conn, *user_id, "import")?;
+            }
+            QueryResult::Ok(inserted)
+        })?;
+        Ok(inserted.into_iter().map(|(_, email)| email).collect())
+    }
+
+    fn rolled_back(
+        &mut self,
+        import: impl FnOnce(&mut Self) -> anyhow::Result<()>,
+    ) -> anyhow::Result<()> {
+        let rolled_back = self.transaction::<(), anyhow::Error, _>(|conn| {
+            import(conn)?;
+            Err(DieselError::RollbackTransaction.into())
+        });
+        match rolled_back {
+            Err(err) if !matches!(err.downcast_ref(), Some(DieselError::RollbackTransaction)) => {
+                Err(err)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+// Import the CSV into `store`, a dry run goes through the same inserts and rolls them back
+fn import_csv<S: ImportStore>(
+    store: &mut S,
+    reader: impl Read,
+    dry_run: bool,
+) -> anyhow::Result<ImportReport> {
+    let mut report = ImportReport {
+        dry_run,
+        ..Default::default()
+    };
+    if dry_run {
+        store.rolled_back(|store| import_rows(store, reader, &mut report))?;
+    } else {
+        import_rows(store, reader, &mut report)?;
+    }
+    Ok(report)
+}
+
+fn import_rows<S: ImportStore>(
+    store: &mut S,
+    reader: impl Read,
+    report: &mut ImportReport,
+) -> anyhow::Result<()> {
+    let mut csv = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        // Rows with a wrong field count are reported like any other invalid row
+        .flexible(true)
+        .from_reader(reader);
+    let headers = csv.headers()?.clone();
+    let mut seen_emails = HashSet::new();
+    let mut batch = Vec::with_capacity(constant::IMPORT_BATCH_SIZE);
+
+    let mut record = csv::StringRecord::new();
+    while csv.read_record(&mut record)? {
+        report.total_rows += 1;
+        let line = record
+            .position()
+            .map(|position| position.line())
+            .unwrap_or_default();
+        let payload = match record.deserialize::<CreateUserRequest>(Some(&headers)) {
+            Ok(payload) => payload,
+            Err(err) => {
+                report.reject(line, None, format!("INVALID_ROW | {err}"));
+                continue;
+            }
+        };
+        if let Err(err) = payload.validate() {
+            let error = format!("INVALID_VALIDATION | {}", format_validation_errors(&err));
+            report.reject(line, Some(payload.email), error);
+            continue;
+        }
+        let new_user = NewUser::from(payload);
+        if !seen_emails.insert(new_user.email.clone()) {
+            report.reject(line, Some(new_user.email), "EMAIL_DUPLICATED_IN_FILE");
+            continue;
+        }
+        batch.push((line, new_user));
+        if batch.len() == constant::IMPORT_BATCH_SIZE {
+            insert_batch(store, &mut batch, report)?;
+        }
+    }
+    insert_batch(store, &mut batch, report)?;
+    // Duplicates are only known once their batch is inserted
+    report.truncate_errors();
+    Ok(())
+}
+
+// Insert a batch, rows whose email is already registered are rejected
+fn insert_batch<S: ImportStore>(
+    store: &mut S,
+    batch: &mut Vec<(u64, NewUser)>,
+    report: &mut ImportReport,
+) -> anyhow::Result<()> {
+    if batch.is_empty() {
+        return Ok(());
+    }
+    let rows: Vec<&NewUser> = batch.iter().map(|(_, user)| user).collect();
+    let inserted = store.insert(&rows)?;
+
+    for (line, user) in batch.drain(..) {
+        if inserted.contains(&user.email) {
+            report.imported += 1;
+        } else {
+            report.reject(line, Some(user.email), "USER_EMAIL_ALREADY_EXISTS");
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{set_rls_bypass, test_connection};
+    use crate::schema::table::organizations;
+    use diesel::sql_query;
+
+    const HEADER: &str = "fullName,email,phoneNumber\n";
+
+    // Registered emails, a rolled back import leaves them as they were
+    #[derive(Default)]
+    struct MemoryStore {
+        emails: HashSet<String>,
+    }
+
+    impl ImportStore for MemoryStore {
+        fn insert(
+            &mut self,
+            rows: &[&NewUser],
+        ) -> anyhow::Result<HashSet<String>> {
+            Ok(rows
+                .iter()
+                .filter(|user| self.emails.insert(user.email.clone()))
+                .map(|user| user.email.clone())
+                .collect())
+        }
+
+        fn rolled_back(
+            &mut self,
+            import: impl FnOnce(&mut Self) -> anyhow::Result<()>,
+        ) -> anyhow::Result<()> {
+            let before = self.emails.clone();
+            let result = import(self);
+            self.emails = before;
+            result
+        }
+    }
+
+    fn store(emails: &[&str]) -> MemoryStore {
+        MemoryStore {
+            emails: emails.iter().map(|email| email.to_string()).collect(),
+        }
+    }
+
+    fn lines(report: &ImportReport) -> Vec<u64> {
+        report.errors.iter().map(|error| error.line).collect()
+    }
+
+    #[test]
+    fn rejects_malformed_and_invalid_rows() {
+        let csv = format!(
+            "{HEADER}Ann,ann@example.com,\nonly-a-name\nBob,not-an-email,\n,carl@example.com,\n"
+        );
+        let mut store = store(&[]);
+        let report = import_csv(&mut store, csv.as_bytes(), false).unwrap();
+        assert_eq!(report.total_rows, 4);
+        assert_eq!(report.imported, 1);
+        assert_eq!(report.rejected, 3);
+        assert_eq!(lines(&report), vec![3, 4, 5]);
+        assert!(report.errors[0].error.starts_with("INVALID_ROW"));
+        assert!(report.errors[1].error.starts_with("INVALID_VALIDATION"));
+        assert!(store.emails.contains("ann@example.com"));
+
+        // Broken framing aborts the import
+        let broken = [HEADER.as_bytes(), b"Ann,\xff,\n"].concat();
+        let err = import_csv(&mut store, broken.as_slice(), false).unwrap_err();
+        assert!(err.downcast_ref::<csv::Error>().is_some());
+    }
+
+    #[test]
+    fn rejects_duplicate_emails() {
+        let csv =
+            format!("{HEADER}Ann,ann@example.com,\nBob,BOB@example.com,\nBobby,bob@example.com,\n");
+        let mut store = store(&["ann@example.com"]);
+        let report = import_csv(&mut store, csv.as_bytes(), false).unwrap();
+        assert_eq!(report.imported, 1);
+        assert_eq!(lines(&report), vec![2, 4]);
+        assert_eq!(report.errors[0].error, "USER_EMAIL_ALREADY_EXISTS");
+        assert_eq!(report.errors[1].error, "EMAIL_DUPLICATED_IN_FILE");
+        assert!(store.emails.contains("bob@example.com"));
+    }
+
+    #[test]
+    fn dry_run_reports_without_inserting() {
+        let csv = format!("{HEADER}Ann,ann@example.com,\nBob,bob@example.com,\n");
+        let mut store = store(&["ann@example.com"]);
+        let report = import_csv(&mut store, csv.as_bytes(), true).unwrap();
+        assert!(report.dry_run);
+        assert_eq!(report.imported, 1);
+        assert_eq!(lines(&report), vec![2]);
+        assert_eq!(store.emails.len(), 1);
+    }
+
+    #[test]
+    fn reports_the_first_rejected_lines() {
+        // The registered emails come first but are only rejected with their batch, after
+        // the invalid rows that follow them
+        let mut csv = HEADER.to_string();
+        for i in 0..10 {
+            csv.push_str(&format!("User {i},user{i}@example.com,\n"));
+        }
+        for i in 0..constant::IMPORT_MAX_REPORTED_ERRORS {
+            csv.push_str(&format!("Invalid {i},invalid-{i},\n"));
+        }
+        let registered: Vec<String> = (0..10).map(|i| format!("user{i}@example.com")).collect();
+        let mut store = MemoryStore {
+            emails: registered.into_iter().collect(),
+        };
+        let report = import_csv(&mut store, csv.as_bytes(), false).unwrap();
+        assert_eq!(
+            report.rejected,
+            10 + constant::IMPORT_MAX_REPORTED_ERRORS as u64
+        );
+        assert!(report.errors_truncated);
+        assert_eq!(report.errors.len(), constant::IMPORT_MAX_REPORTED_ERRORS);
+        assert_eq!(report.errors[0].line, 2);
+        assert!(report.errors.windows(2).all(|w| w[0].line < w[1].line));
+    }
+
+    #[test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    fn overview_counts_memberships_under_row_level_security() {
+        let mut conn = test_connection();
+        conn.begin_test_transaction().unwrap();
+        set_rls_bypass(&mut conn).unwrap();
+        let user_id = generator::id() as i64;
+        diesel::insert_into(users::table)
+            .values(NewUser {
+                id: user_id,
+                full_name: "Overview".to_string(),
+                email: format!("overview{user_id}@example.com"),
+                phone_number: None,
+            })
+            .execute(&mut conn)
+            .unwrap();
+        let organization_id = generator::id() as i64;
+        diesel::insert_into(organizations::table)
+            .values((
+                organizations::id.eq(organization_id),
+                organizations::name.eq("Overview"),
+                organizations::slug.eq(format!("o{organization_id}")),
+            ))
+            .execute(&mut conn)
+            .unwrap();
+        diesel::insert_into(organization_members::table)
+            .values((
+                organization_members::organization_id.eq(organization_id),
+                organization_members::user_id.eq(user_id),
+                organization_members::role.eq(constant::MEMBER_ROLE_OWNER),
+            ))
+            .execute(&mut conn)
+            .unwrap();
+        sql_query("RESET ROLE").execute(&mut conn).unwrap();
+
+        // As in get_overview, no tenant is set
+        set_rls_bypass(&mut conn).unwrap();
+        let overview = load_overview(&mut conn, user_id).unwrap();
+        assert_eq!(overview.organization_count, 1);
+        assert!(matches!(
+            load_overview(&mut conn, generator::id() as i64),
+            Err(DieselError::NotFound)
+        ));
+    }
 }
@@ -0,0 +1,190 @@
+//! Transactional outbox for domain events.
+//!
+//! [`record`] writes an event in the transaction of the change it announces, so the event
+//! exists exactly when the change commits. The [`relay::Relay`] then claims due events with
+//! `FOR UPDATE SKIP LOCKED`, hands them to its [`sink::Sink`]s and marks them delivered,
+//! retrying failures with backoff until they are dead-lettered. Delivery is at least once:
+//! consumers deduplicate by event id.
+//!
+//! # Example
+//!
+//! ```rust
+//! db.transaction(move |conn| {
+//!     let user = insert_user(conn)?;
+//!     outbox::record(conn, USER_REGISTERED, "user", user.id, json!({ "userId": user.id.to_string() }))?;
+//!     Ok(user)
+//! })
+//! .await?;
+//! ```
+pub mod relay;
+pub mod sink;
+
+use crate::database::instrumentation::CountedRunQueryDsl;
+use crate::schema::table::outbox_events;
+use crate::utils::generator;
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use serde_json::Value;
+
+// Values of outbox_events.status
+pub const OUTBOX_PENDING: &str = "pending";
+pub const OUTBOX_DELIVERED: &str = "delivered";
+pub const OUTBOX_DEAD: &str = "dead";
+
+#[derive(Debug, Clone, Queryable, Selectable)]
+#[diesel(table_name = outbox_events)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct OutboxEvent {
+    pub id: i64,
+    pub event_type: String,
+    pub aggregate_type: String,
+    pub aggregate_id: i64,
+    pub payload: Value,
+    pub status: String,
+    /// Deliveries started so far, including the current one
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    pub next_attempt_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    pub delivered_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = outbox_events)]
+struct NewOutboxEvent<'a> {
+    id: i64,
+    event_type: &'a str,
+    aggregate_type: &'a str,
+    aggregate_id: i64,
+    payload: Value,
+}
+
+/// Record `event_type` on an aggregate, returning the event id.
+///
+/// Call inside the transaction of the change, the payload should name ids rather than
+/// copy personal data, it is kept until the delivered event is purged.
+pub fn record(
+    conn: &mut PgConnection,
+    event_type: &str,
+    aggregate_type: &str,
+    aggregate_id: i64,
+    payload: Value,
+) -> QueryResult<i64> {
+    diesel::insert_into(outbox_events::table)
+        .values(NewOutboxEvent {
+            id: generator::id() as i64,
+            event_type,
+            aggregate_type,
+            aggregate_id,
+            payload,
+        })
+        .returning(outbox_events::id)
+        .get_result(conn)
+}
+
+/// Claim up to `limit` due events, oldest first, for `lease`.
+///
+/// Rows locked by another relay are skipped. Each claimed event counts an attempt and is
+/// not due again until the lease ends, so a relay dying mid delivery only delays it.
+pub fn claim(
+    conn: &mut PgConnection,
+    limit: i64,
+    lease: chrono::Duration,
+) -> QueryResult<Vec<OutboxEvent>> {
+    conn.transaction(|conn| {
+        let now = Utc::now();
+        let ids: Vec<i64> = outbox_events::table
+            .filter(outbox_events::status.eq(OUTBOX_PENDING))
+            .filter(outbox_events::next_attempt_at.le(now))
+            .order((outbox_events::next_attempt_at, outbox_events::id))
+            .limit(limit)
+            .select(outbox_events::id)
+            .for_update()
+            .skip_locked()
+            .load_counted(conn)?;
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut events: Vec<OutboxEvent> =
+            diesel::update(outbox_events::table.filter(outbox_events::id.eq_any(&ids)))
+                .set((
+                    outbox_events::attempts.eq(outbox_events::attempts + 1),
+                    outbox_events::next_attempt_at.eq(now + lease),
+                ))
+                .returning(OutboxEvent::as_returning())
+                .load_counted(conn)?;
+        events.sort_by_key(|event| event.id);
+        Ok(events)
+    })
+}
+
+pub fn mark_delivered(
+    conn: &mut PgConnection,
+    event_id: i64,
+) -> QueryResult<()> {
+    diesel::update(outbox_events::table.find(event_id))
+        .set((
+            outbox_events::status.eq(OUTBOX_DELIVERED),
+            outbox_events::delivered_at.eq(Utc::now()),
+            outbox_events::last_error.eq(None::<String>),
+        ))
+        .execute_counted(conn)?;
+    Ok(())
+}
+
+/// Record a failed delivery, due again at `retry_at` or dead-lettered when it is `None`.
+pub fn mark_failed(
+    conn: &mut PgConnection,
+    event_id: i64,
+    error: &str,
+    retry_at: Option<DateTime<Utc>>,
+) -> QueryResult<()> {
+    let target = outbox_events::table.find(event_id);
+    match retry_at {
+        Some(retry_at) => diesel::update(target)
+            .set((
+                outbox_events::last_error.eq(error),
+                outbox_events::next_attempt_at.eq(retry_at),
+            ))
+            .execute_counted(conn)?,
+        None => diesel::update(target)
+            .set((
+                outbox_events::status.eq(OUTBOX_DEAD),
+                outbox_events::last_error.eq(error),
+            ))
+            .execute_counted(conn)?,
+    };
+    Ok(())
+}
+
+/// Put a dead-lettered event back in line with fresh attempts, false when it is not dead.
+pub fn requeue(
+    conn: &mut PgConnection,
+    event_id: i64,
+) -> QueryResult<bool> {
+    let requeued = diesel::update(
+        outbox_events::table
+            .find(event_id)
+            .filter(outbox_events::status.eq(OUTBOX_DEAD)),
+    )
+    .set((
+        outbox_events::status.eq(OUTBOX_PENDING),
+        outbox_events::attempts.eq(0),
+        outbox_events::next_attempt_at.eq(Utc::now()),
+    ))
+    .execute_counted(conn)?;
+    Ok(requeued > 0)
+}
+
+/// Delete events delivered before `delivered_before`, dead ones are kept for inspection.
+pub fn purge_delivered(
+    conn: &mut PgConnection,
+    delivered_before: DateTime<Utc>,
+) -> QueryResult<usize> {
+    diesel::delete(
+        outbox_events::table
+            .filter(outbox_events::status.eq(OUTBOX_DELIVERED))
+            .filter(outbox_events::delivered_at.lt(delivered_before)),
+    )
+    .execute_counted(conn)
+}
@@ -0,0 +1,188 @@
+//! Delivery of claimed outbox events to the sinks.
+use super::sink::Sink;
+use super::{claim, mark_delivered, mark_failed, OutboxEvent};
+use crate::database::Database;
+use chrono::Utc;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Lease on top of the deliveries, for the status updates of the batch
+const LEASE_MARGIN: Duration = Duration::from_secs(30);
+
+/// Tuning of [`Relay`].
+#[derive(Clone, Copy, Debug)]
+pub struct RelaySettings {
+    /// Events claimed per round
+    pub batch_size: i64,
+    /// Deliveries tried before an event is dead-lettered
+    pub max_attempts: i32,
+    /// Longest one sink may take on one event, a slower delivery fails
+    pub delivery_timeout: Duration,
+    /// Wait before the first retry, doubling on each further one
+    pub retry_base: Duration,
+    pub retry_max: Duration,
+}
+
+impl Default for RelaySettings {
+    fn default() -> Self {
+        Self {
+            batch_size: 20,
+            max_attempts: 10,
+            delivery_timeout: Duration::from_secs(10),
+            retry_base: Duration::from_secs(10),
+            retry_max: Duration::from_secs(3600),
+        }
+    }
+}
+
+impl RelaySettings {
+    /// How long a claimed batch is reserved, enough for every sink to time out on every
+    /// event in turn.
+    pub fn lease(
+        &self,
+        sinks: usize,
+    ) -> Duration {
+        let deliveries = (self.batch_size.max(0) as u32).saturating_mul(sinks as u32);
+        self.delivery_timeout
+            .saturating_mul(deliveries)
+            .saturating_add(LEASE_MARGIN)
+    }
+
+    /// Wait after the failed delivery number `attempts`.
+    pub fn retry_delay(
+        &self,
+        attempts: i32,
+    ) -> Duration {
+        let exponent = attempts.saturating_sub(1).clamp(0, 20) as u32;
+        self.retry_base
+            .saturating_mul(1 << exponent)
+            .min(self.retry_max)
+    }
+}
+
+/// Hands claimed events to every sink in turn, an event is delivered once all accepted it.
+///
+/// A sink failing makes the whole event retry, so the sinks before it see it again.
+#[derive(Clone)]
+pub struct Relay {
+    db: Database,
+    sinks: Vec<Arc<dyn Sink>>,
+    settings: RelaySettings,
+}
+
+impl Relay {
+    pub fn new(
+        db: Database,
+        settings: RelaySettings,
+    ) -> Self {
+        Self {
+            db,
+            sinks: Vec::new(),
+            settings,
+        }
+    }
+
+    pub fn sink(
+        mut self,
+        sink: impl Sink + 'static,
+    ) -> Self {
+        self.sinks.push(Arc::new(sink));
+        self
+    }
+
+    /// Claim and deliver one batch, returning the number of claimed events.
+    pub async fn run_once(&self) -> anyhow::Result<usize> {
+        let limit = self.settings.batch_size;
+        let lease = chrono::Duration::from_std(self.settings.lease(self.sinks.len()))?;
+        let events = self
+            .db
+            .transaction(move |conn| Ok(claim(conn, limit, lease)?))
+            .await?;
+        let claimed = events.len();
+        for event in events {
+            let event_id = event.id;
+            // Left claimed, the event is due again once the lease ends
+            if let Err(err) = self.relay(event).await {
+                tracing::error!(event_id, error = %err, "OUTBOX_RELAY_FAILED");
+            }
+        }
+        Ok(claimed)
+    }
+
+    async fn relay(
+        &self,
+        event: OutboxEvent,
+    ) -> anyhow::Result<()> {
+        let event_id = event.id;
+        let failure = self.deliver(&event).await.err();
+        let Some(error) = failure else {
+            tracing::debug!(event_id, event_type = %event.event_type, "OUTBOX_EVENT_DELIVERED");
+            return self
+                .db
+                .transaction(move |conn| Ok(mark_delivered(conn, event_id)?))
+                .await;
+        };
+
+        let retry_at = (event.attempts < self.settings.max_attempts).then(|| {
+            Utc::now()
+                + chrono::Duration::from_std(self.settings.retry_delay(event.attempts))
+                    .unwrap_or_default()
+        });
+        match retry_at {
+            Some(retry_at) => tracing::warn!(
+                event_id,
+                event_type = %event.event_type,
+                attempts = event.attempts,
+                %retry_at,
+                error = %error,
+                "OUTBOX_DELIVERY_FAILED"
+            ),
+            None => tracing::error!(
+                event_id,
+                event_type = %event.event_type,
+                attempts = event.attempts,
+                error = %error,
+                "OUTBOX_EVENT_DEAD"
+            ),
+        }
+        self.db
+            .transaction(move |conn| Ok(mark_failed(conn, event_id, &error, retry_at)?))
+            .await
+    }
+
+    // First failing sink, as `<sink>: <error>`
+    async fn deliver(
+        &self,
+        event: &OutboxEvent,
+    ) -> Result<(), String> {
+        for sink in &self.sinks {
+            match tokio::time::timeout(self.settings.delivery_timeout, sink.deliver(event)).await {
+                Ok(Ok(())) => {}
+                Ok(Err(err)) => return Err(format!("{}: {err:#}", sink.name())),
+                Err(_) => return Err(format!("{}: DELIVERY_TIMED_OUT", sink.name())),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retry_delay_doubles_up_to_the_cap() {
+        let settings = RelaySettings::default();
+        assert_eq!(settings.retry_delay(1), Duration::from_secs(10));
+        assert_eq!(settings.retry_delay(2), Duration::from_secs(20));
+        assert_eq!(settings.retry_delay(4), Duration::from_secs(80));
+        assert_eq!(settings.retry_delay(30), Duration::from_secs(3600));
+    }
+
+    #[test]
+    fn lease_outlasts_every_delivery_of_the_batch() {
+        let settings = RelaySettings::default();
+        assert_eq!(settings.lease(1), Duration::from_secs(20 * 10 + 30));
+        assert_eq!(settings.lease(3), Duration::from_secs(3 * 20 * 10 + 30));
+    }
+}
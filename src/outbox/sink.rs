@@ -0,0 +1,197 @@
+//! Destinations of outbox events.
+use super::OutboxEvent;
+use anyhow::{anyhow, Result};
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use futures_util::future::BoxFuture;
+use serde::Serialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Where the relay delivers events, an error makes it retry the event later.
+#[async_trait]
+pub trait Sink: Send + Sync {
+    /// Shown in the `last_error` of an event the sink failed
+    fn name(&self) -> &str;
+
+    async fn deliver(
+        &self,
+        event: &OutboxEvent,
+    ) -> Result<()>;
+}
+
+type Handler = Arc<dyn Fn(OutboxEvent) -> BoxFuture<'static, Result<()>> + Send + Sync>;
+
+/// Runs the in-process handlers registered for the event type, other events pass.
+#[derive(Default)]
+pub struct HandlerSink {
+    handlers: HashMap<String, Vec<Handler>>,
+}
+
+impl HandlerSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run `handler` for every `event_type` event, it may run more than once per event.
+    pub fn on<F, Fut>(
+        mut self,
+        event_type: &str,
+        handler: F,
+    ) -> Self
+    where
+        F: Fn(OutboxEvent) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<()>> + Send + 'static,
+    {
+        let handler: Handler = Arc::new(move |event| Box::pin(handler(event)));
+        self.handlers
+            .entry(event_type.to_string())
+            .or_default()
+            .push(handler);
+        self
+    }
+}
+
+#[async_trait]
+impl Sink for HandlerSink {
+    fn name(&self) -> &str {
+        "handlers"
+    }
+
+    async fn deliver(
+        &self,
+        event: &OutboxEvent,
+    ) -> Result<()> {
+        for handler in self.handlers.get(&event.event_type).into_iter().flatten() {
+            handler(event.clone()).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Body of a webhook delivery
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct WebhookEvent<'a> {
+    // Snowflake ids exceed the JS safe integer range, expose them as string
+    id: String,
+    #[serde(rename = "type")]
+    event_type: &'a str,
+    aggregate_type: &'a str,
+    aggregate_id: String,
+    payload: &'a Value,
+    created_at: DateTime<Utc>,
+}
+
+/// POSTs every event as JSON to a URL, any non 2xx status is a failed delivery.
+///
+/// With a secret, `x-outbox-signature` carries `sha256=<hex HMAC-SHA256 of the body>` so
+/// the receiver can check the sender. `x-outbox-event-id` lets it drop duplicates.
+pub struct WebhookSink {
+    url: String,
+    secret: Option<String>,
+    http: reqwest::Client,
+}
+
+impl WebhookSink {
+    pub fn new(
+        url: &str,
+        secret: Option<String>,
+    ) -> Self {
+        Self {
+            url: url.to_string(),
+            secret: secret.filter(|secret| !secret.is_empty()),
+            http: reqwest::Client::builder()
+                .timeout(Duration::from_secs(10))
+                .build()
+                .unwrap_or_default(),
+        }
+    }
+}
+
+#[async_trait]
+impl Sink for WebhookSink {
+    fn name(&self) -> &str {
+        &self.url
+    }
+
+    async fn deliver(
+        &self,
+        event: &OutboxEvent,
+    ) -> Result<()> {
+        let body = serde_json::to_vec(&WebhookEvent {
+            id: event.id.to_string(),
+            event_type: &event.event_type,
+            aggregate_type: &event.aggregate_type,
+            aggregate_id: event.aggregate_id.to_string(),
+            payload: &event.payload,
+            created_at: event.created_at,
+        })?;
+        let mut request = self
+            .http
+            .post(&self.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header("x-outbox-event-id", event.id.to_string())
+            .header("x-outbox-event-type", &event.event_type);
+        if let Some(secret) = &self.secret {
+            request = request.header(
+                "x-outbox-signature",
+                format!("sha256={}", hmac_sha256_hex(secret.as_bytes(), &body)),
+            );
+        }
+        let response = request.body(body).send().await?;
+        if !response.status().is_success() {
+            return Err(anyhow!("status {}", response.status()));
+        }
+        Ok(())
+    }
+}
+
+// HMAC (RFC 2104) over SHA-256, whose block size is 64 bytes
+fn hmac_sha256_hex(
+    key: &[u8],
+    message: &[u8],
+) -> String {
+    let mut block = [0u8; 64];
+    if key.len() > block.len() {
+        block[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let pad = |byte: u8| block.map(|b| b ^ byte);
+    let inner = Sha256::new()
+        .chain_update(pad(0x36))
+        .chain_update(message)
+        .finalize();
+    let outer = Sha256::new()
+        .chain_update(pad(0x5c))
+        .chain_update(inner)
+        .finalize();
+    format!("{outer:x}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signs_with_hmac_sha256() {
+        // RFC 4231 test case 2
+        assert_eq!(
+            hmac_sha256_hex(b"Jefe", b"what do ya want for nothing?"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        // Keys longer than a block are hashed first, RFC 4231 test case 6
+        assert_eq!(
+            hmac_sha256_hex(
+                &[0xaa; 131],
+                b"Test Using Larger Than Block-Size Key - Hash Key First"
+            ),
+            "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54"
+        );
+    }
+}
@@ -0,0 +1,43 @@
+//! Append only audit trail.
+//!
+//! Entries are written in the same transaction as the change they describe, so a rolled
+//! back change leaves no entry behind. `details` must not hold personal data, the trail
+//! outlives erased accounts.
+use crate::database::instrumentation::CountedRunQueryDsl;
+use crate::schema::table::audit_logs;
+use crate::utils::generator;
+use diesel::prelude::*;
+use serde_json::Value;
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = audit_logs)]
+struct NewAuditEntry<'a> {
+    id: i64,
+    actor_id: Option<i64>,
+    action: &'a str,
+    subject_type: &'a str,
+    subject_id: i64,
+    details: Value,
+}
+
+/// Record `action` on a subject, `actor_id` is None for background tasks.
+pub fn record(
+    conn: &mut PgConnection,
+    actor_id: Option<i64>,
+    action: &str,
+    subject_type: &str,
+    subject_id: i64,
+    details: Value,
+) -> QueryResult<()> {
+    diesel::insert_into(audit_logs::table)
+        .values(NewAuditEntry {
+            id: generator::id() as i64,
+            actor_id,
+            action,
+            subject_type,
+            subject_id,
+            details,
+        })
+        .execute_counted(conn)?;
+    Ok(())
+}
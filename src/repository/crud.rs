@@ -0,0 +1,630 @@
+//! Generic find, list, insert, update and delete over a Diesel table.
+//!
+//! A model implements [`Entity`] to name its table, primary key and write types, then
+//! services hold a [`Repository`], [`DieselRepository`] in production and
+//! [`MemoryRepository`](super::memory::MemoryRepository) in unit tests. Every call takes a
+//! [`Scope`]: soft deleted rows stay hidden unless the scope includes them, and a scope with
+//! a tenant only sees the rows the entity's [`Entity::tenant_filter`] hook lets through,
+//! inside `Database::tenant_transaction` so row level security applies too.
+//!
+//! Lists are ordered by id, the snowflake ids follow creation time. Endpoints with sorting
+//! and filters keep building their own query from `ListQuery`.
+use super::soft_delete;
+use crate::database::instrumentation::CountedRunQueryDsl;
+use crate::database::Database;
+use crate::utils::list_query::{page_offset, Cursor, ListConfig, ListQuery, Page, SortDirection};
+use anyhow::Result;
+use axum::async_trait;
+use diesel::dsl::{
+    self, AsSelect, Asc, CountStar, Desc, Find, Gt, IntoBoxed, Lt, Returning, Select, Update,
+    Values,
+};
+use diesel::expression::BoxableExpression;
+use diesel::pg::{Pg, PgConnection};
+use diesel::query_builder::{
+    AsChangeset, AsQuery, DeleteStatement, IncompleteInsertStatement, IntoUpdateTarget, Query,
+    QueryId,
+};
+use diesel::query_dsl::methods::{
+    BoxedDsl, ExecuteDsl, FilterDsl, FindDsl, LimitDsl, OffsetDsl, OrderDsl, SelectDsl,
+};
+use diesel::query_dsl::LoadQuery;
+use diesel::sql_types::{BigInt, Bool};
+use diesel::{
+    BoolExpressionMethods, Column, ExpressionMethods, Insertable, OptionalExtension, QueryResult,
+    RunQueryDsl, Selectable, SelectableHelper, Table,
+};
+use std::marker::PhantomData;
+
+/// Boolean expression on the columns of `T`, what the tenant hook returns.
+pub type Predicate<T> = Box<dyn BoxableExpression<T, Pg, SqlType = Bool>>;
+
+/// Boxed select of every column of an entity.
+pub type BoxedQuery<E> = IntoBoxed<'static, Select<<E as Entity>::Table, AsSelect<E, Pg>>, Pg>;
+
+/// Row of an entity by id, filtered by a scope, the target of scoped updates and deletes.
+pub type ScopedRow<E> =
+    dsl::Filter<Find<<E as Entity>::Table, i64>, Predicate<<E as Entity>::Table>>;
+
+/// Model stored in one table with a `BIGINT` primary key.
+pub trait Entity: Selectable<Pg> + Clone + Send + Sync + 'static {
+    type Table: Table + Default + Send + 'static;
+    /// Primary key column
+    type Id: Column<Table = Self::Table, SqlType = BigInt> + Default + Send + 'static;
+    /// Insertable of a new row, built with its generated id
+    type New: Clone + Send + 'static;
+    /// Changeset applied by [`Repository::update`]
+    type Changes: Clone + Send + 'static;
+
+    /// Soft delete hook, the table name when it follows `repository::soft_delete`
+    const SOFT_DELETE: Option<&'static str> = None;
+
+    fn id(&self) -> i64;
+
+    /// Tenant scoping hook, the rows of `tenant_id` for tables following
+    /// `repository::tenant`. Entities that are not tenant scoped ignore the tenant.
+    fn tenant_filter(tenant_id: i64) -> Option<Predicate<Self::Table>> {
+        let _ = tenant_id;
+        None
+    }
+}
+
+/// Rows a repository call may see.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Scope {
+    pub tenant_id: Option<i64>,
+    /// Include soft deleted rows, admin only
+    pub include_deleted: bool,
+}
+
+impl Scope {
+    pub fn tenant(tenant_id: i64) -> Self {
+        Scope {
+            tenant_id: Some(tenant_id),
+            include_deleted: false,
+        }
+    }
+
+    pub fn include_deleted(
+        mut self,
+        include_deleted: bool,
+    ) -> Self {
+        self.include_deleted = include_deleted;
+        self
+    }
+}
+
+/// Page asked from [`Repository::list`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pagination {
+    /// Newest first with the total count
+    Offset { page: i64, limit: i64 },
+    /// Keyset pagination on the id, `after` is the id in the cursor of the previous page
+    Cursor {
+        after: Option<i64>,
+        limit: i64,
+        direction: SortDirection,
+    },
+}
+
+impl Pagination {
+    fn limit(&self) -> i64 {
+        match self {
+            Pagination::Offset { limit, .. } | Pagination::Cursor { limit, .. } => *limit,
+        }
+    }
+
+    /// Rows before an offset page, a page past the i64 range skips every row.
+    pub(super) fn offset(
+        page: i64,
+        limit: i64,
+    ) -> i64 {
+        page_offset(page, limit).map_or(i64::MAX, |offset| offset.max(0))
+    }
+}
+
+impl<C: ListConfig> From<&ListQuery<C>> for Pagination {
+    fn from(query: &ListQuery<C>) -> Self {
+        match query.cursor {
+            Some(cursor) => Pagination::Cursor {
+                after: cursor.map(|cursor| cursor.id),
+                limit: query.limit,
+                direction: query.cursor_direction(),
+            },
+            None => Pagination::Offset {
+                page: query.page,
+                limit: query.limit,
+            },
+        }
+    }
+}
+
+/// Page of a cursor listing, fetched with one extra row to know whether another follows.
+pub(super) fn cursor_page<E: Entity>(
+    mut items: Vec<E>,
+    limit: i64,
+) -> Page<E> {
+    let next_cursor = if items.len() as i64 > limit {
+        items.truncate(limit as usize);
+        items.last().map(|item| Cursor { id: item.id() }.encode())
+    } else {
+        None
+    };
+    Page {
+        items,
+        total: None,
+        next_cursor,
+    }
+}
+
+/// CRUD over the rows of one entity.
+///
+/// `update` and `delete` only touch rows the scope can see and return `None` / `false`
+/// otherwise, so services answer 404 for rows of other tenants.
+#[async_trait]
+pub trait Repository<E: Entity>: Send + Sync {
+    async fn find(
+        &self,
+        scope: Scope,
+        id: i64,
+    ) -> Result<Option<E>>;
+
+    async fn list(
+        &self,
+        scope: Scope,
+        pagination: Pagination,
+    ) -> Result<Page<E>>;
+
+    async fn insert(
+        &self,
+        scope: Scope,
+        new: E::New,
+    ) -> Result<E>;
+
+    async fn update(
+        &self,
+        scope: Scope,
+        id: i64,
+        changes: E::Changes,
+    ) -> Result<Option<E>>;
+
+    /// Soft delete when the entity supports it, delete for good otherwise.
+    async fn delete(
+        &self,
+        scope: Scope,
+        id: i64,
+    ) -> Result<bool>;
+
+    /// Undo a soft delete, false when the row is not deleted or not soft deletable.
+    async fn restore(
+        &self,
+        scope: Scope,
+        id: i64,
+    ) -> Result<bool>;
+}
+
+/// Queries behind [`DieselRepository`], implemented for every entity whose table and
+/// model fit the generic statements.
+pub trait DieselEntity: Entity {
+    fn find(
+        conn: &mut PgConnection,
+        scope: Scope,
+        id: i64,
+    ) -> QueryResult<Option<Self>>;
+
+    fn load_page(
+        conn: &mut PgConnection,
+        scope: Scope,
+        pagination: Pagination,
+    ) -> QueryResult<Page<Self>>;
+
+    fn insert(
+        conn: &mut PgConnection,
+        new: Self::New,
+    ) -> QueryResult<Self>;
+
+    /// Update the row when `scope` sees it, in the same statement.
+    fn update(
+        conn: &mut PgConnection,
+        scope: Scope,
+        id: i64,
+        changes: Self::Changes,
+    ) -> QueryResult<Option<Self>>;
+
+    /// Delete the row when `scope` sees it, without a window for it to change in between.
+    fn delete(
+        conn: &mut PgConnection,
+        scope: Scope,
+        id: i64,
+    ) -> QueryResult<bool>;
+}
+
+impl<E> DieselEntity for E
+where
+    E: Entity,
+    E::SelectExpression: QueryId,
+    E::Table: SelectDsl<AsSelect<E, Pg>> + FindDsl<i64>,
+    Select<E::Table, AsSelect<E, Pg>>: BoxedDsl<'static, Pg>,
+    BoxedQuery<E>: FilterDsl<Predicate<E::Table>, Output = BoxedQuery<E>>
+        + FilterDsl<dsl::Eq<E::Id, i64>, Output = BoxedQuery<E>>
+        + FilterDsl<Gt<E::Id, i64>, Output = BoxedQuery<E>>
+        + FilterDsl<Lt<E::Id, i64>, Output = BoxedQuery<E>>
+        + OrderDsl<Asc<E::Id>, Output = BoxedQuery<E>>
+        + OrderDsl<Desc<E::Id>, Output = BoxedQuery<E>>
+        + LimitDsl<Output = BoxedQuery<E>>
+        + OffsetDsl<Output = BoxedQuery<E>>
+        + SelectDsl<CountStar>
+        + RunQueryDsl<PgConnection>
+        + LoadQuery<'static, PgConnection, E>,
+    Select<BoxedQuery<E>, CountStar>:
+        RunQueryDsl<PgConnection> + LoadQuery<'static, PgConnection, i64>,
+    E::New: Insertable<E::Table>,
+    Returning<Values<IncompleteInsertStatement<E::Table>, E::New>, AsSelect<E, Pg>>:
+        Query + RunQueryDsl<PgConnection> + LoadQuery<'static, PgConnection, E>,
+    Find<E::Table, i64>: FilterDsl<Predicate<E::Table>>,
+    ScopedRow<E>: IntoUpdateTarget<Table = E::Table>,
+    E::Changes: AsChangeset<Target = E::Table>,
+    Update<ScopedRow<E>, E::Changes>: AsQuery,
+    Returning<Update<ScopedRow<E>, E::Changes>, AsSelect<E, Pg>>:
+        Query + RunQueryDsl<PgConnection> + LoadQuery<'static, PgConnection, E>,
+    DeleteStatement<E::Table, <ScopedRow<E> as IntoUpdateTarget>::WhereClause>:
+        ExecuteDsl<PgConnection>,
+{
+    fn find(
+        conn: &mut PgConnection,
+        scope: Scope,
+        id: i64,
+    ) -> QueryResult<Option<Self>> {
+        let query = FilterDsl::filter(visible::<E>(scope), E::Id::default().eq(id));
+        LimitDsl::limit(query, 1).get_result(conn).optional()
+    }
+
+    fn load_page(
+        conn: &mut PgConnection,
+        scope: Scope,
+        pagination: Pagination,
+    ) -> QueryResult<Page<Self>> {
+        let newest_first = |query| OrderDsl::order(query, E::Id::default().desc());
+        match pagination {
+            Pagination::Offset { page, limit } => {
+                let total =
+                    SelectDsl::select(visible::<E>(scope), dsl::count_star()).get_result(conn)?;
+                let query = LimitDsl::limit(newest_first(visible::<E>(scope)), limit);
+                let items =
+                    OffsetDsl::offset(query, Pagination::offset(page, limit)).load_counted(conn)?;
+                Ok(Page {
+                    items,
+                    total: Some(total),
+                    next_cursor: None,
+                })
+            }
+            Pagination::Cursor {
+                after,
+                limit,
+                direction,
+            } => {
+                let query = visible::<E>(scope);
+                let query = match direction {
+                    SortDirection::Asc => {
+                        let query = OrderDsl::order(query, E::Id::default().asc());
+                        match after {
+                            Some(id) => FilterDsl::filter(query, E::Id::default().gt(id)),
+                            None => query,
+                        }
+                    }
+                    SortDirection::Desc => {
+                        let query = newest_first(query);
+                        match after {
+                            Some(id) => FilterDsl::filter(query, E::Id::default().lt(id)),
+                            None => query,
+                        }
+                    }
+                };
+                let items = LimitDsl::limit(query, limit + 1).load_counted(conn)?;
+                Ok(cursor_page(items, limit))
+            }
+        }
+    }
+
+    fn insert(
+        conn: &mut PgConnection,
+        new: Self::New,
+    ) -> QueryResult<Self> {
+        diesel::insert_into(E::Table::default())
+            .values(new)
+            .returning(E::as_returning())
+            .get_result(conn)
+    }
+
+    fn update(
+        conn: &mut PgConnection,
+        scope: Scope,
+        id: i64,
+        changes: Self::Changes,
+    ) -> QueryResult<Option<Self>> {
+        diesel::update(scoped_row::<E>(scope, id))
+            .set(changes)
+            .returning(E::as_returning())
+            .get_result(conn)
+            .optional()
+    }
+
+    fn delete(
+        conn: &mut PgConnection,
+        scope: Scope,
+        id: i64,
+    ) -> QueryResult<bool> {
+        match E::SOFT_DELETE {
+            // The soft delete statement takes no predicate, the row lock keeps it visible
+            // until the write
+            Some(table) => {
+                soft_delete::lock_row(conn, table, id)?;
+                if E::find(conn, scope, id)?.is_none() {
+                    return Ok(false);
+                }
+                soft_delete::set_deleted(conn, table, id, true)
+            }
+            None => {
+                let deleted = diesel::delete(scoped_row::<E>(scope, id)).execute_counted(conn)?;
+                Ok(deleted > 0)
+            }
+        }
+    }
+}
+
+/// Filters of `scope` as one predicate, the soft delete one and the tenant hook's.
+fn scope_predicate<E: Entity>(scope: Scope) -> Predicate<E::Table> {
+    let live = match (E::SOFT_DELETE, scope.include_deleted) {
+        (Some(table), false) => Some(dsl::sql::<Bool>(&format!("{table}.deleted_at IS NULL"))),
+        _ => None,
+    };
+    let tenant = scope.tenant_id.and_then(E::tenant_filter);
+    match (live, tenant) {
+        (Some(live), Some(tenant)) => Box::new(live.and(tenant)),
+        (Some(live), None) => Box::new(live),
+        (None, Some(tenant)) => tenant,
+        (None, None) => Box::new(dsl::sql::<Bool>("TRUE")),
+    }
+}
+
+fn scoped_row<E>(
+    scope: Scope,
+    id: i64,
+) -> ScopedRow<E>
+where
+    E: Entity,
+    E::Table: FindDsl<i64>,
+    Find<E::Table, i64>: FilterDsl<Predicate<E::Table>>,
+{
+    FilterDsl::filter(
+        FindDsl::find(E::Table::default(), id),
+        scope_predicate::<E>(scope),
+    )
+}
+
+/// Rows of `E` visible in `scope`.
+fn visible<E>(scope: Scope) -> BoxedQuery<E>
+where
+    E: Entity,
+    E::SelectExpression: QueryId,
+    E::Table: SelectDsl<AsSelect<E, Pg>>,
+    Select<E::Table, AsSelect<E, Pg>>: BoxedDsl<'static, Pg>,
+    BoxedQuery<E>: FilterDsl<Predicate<E::Table>, Output = BoxedQuery<E>>,
+{
+    let query = SelectDsl::select(E::Table::default(), E::as_select());
+    let query = BoxedDsl::internal_into_boxed(query);
+    FilterDsl::filter(query, scope_predicate::<E>(scope))
+}
+
+/// [`Repository`] over a Diesel table through [`Database`].
+pub struct DieselRepository<E> {
+    db: Database,
+    _entity: PhantomData<fn() -> E>,
+}
+
+impl<E> DieselRepository<E> {
+    pub fn new(db: Database) -> Self {
+        DieselRepository {
+            db,
+            _entity: PhantomData,
+        }
+    }
+}
+
+// Manual impl, the entity does not need to be Clone for the repository to be
+impl<E> Clone for DieselRepository<E> {
+    fn clone(&self) -> Self {
+        DieselRepository::new(self.db.clone())
+    }
+}
+
+impl<E: DieselEntity> DieselRepository<E> {
+    /// Run a write in a transaction, the tenant's one when the scope has a tenant.
+    async fn write<F, T>(
+        &self,
+        scope: Scope,
+        operation: F,
+    ) -> Result<T>
+    where
+        F: FnMut(&mut PgConnection) -> Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        match scope.tenant_id {
+            Some(tenant_id) => self.db.tenant_transaction(tenant_id, operation).await,
+            None => self.db.transaction(operation).await,
+        }
+    }
+}
+
+#[async_trait]
+impl<E: DieselEntity> Repository<E> for DieselRepository<E> {
+    async fn find(
+        &self,
+        scope: Scope,
+        id: i64,
+    ) -> Result<Option<E>> {
+        let operation = move |conn: &mut PgConnection| Ok(E::find(conn, scope, id)?);
+        match scope.tenant_id {
+            Some(tenant_id) => self.db.tenant_transaction(tenant_id, operation).await,
+            None => self.db.execute(operation).await,
+        }
+    }
+
+    async fn list(
+        &self,
+        scope: Scope,
+        pagination: Pagination,
+    ) -> Result<Page<E>> {
+        debug_assert!(pagination.limit() > 0);
+        let operation = move |conn: &mut PgConnection| Ok(E::load_page(conn, scope, pagination)?);
+        match scope.tenant_id {
+            Some(tenant_id) => self.db.tenant_transaction(tenant_id, operation).await,
+            None => self.db.execute(operation).await,
+        }
+    }
+
+    async fn insert(
+        &self,
+        scope: Scope,
+        new: E::New,
+    ) -> Result<E> {
+        self.write(scope, move |conn| Ok(E::insert(conn, new.clone())?))
+            .await
+    }
+
+    async fn update(
+        &self,
+        scope: Scope,
+        id: i64,
+        changes: E::Changes,
+    ) -> Result<Option<E>> {
+        self.write(scope, move |conn| {
+            Ok(E::update(
+                conn,
+                scope.include_deleted(false),
+                id,
+                changes.clone(),
+            )?)
+        })
+        .await
+    }
+
+    async fn delete(
+        &self,
+        scope: Scope,
+        id: i64,
+    ) -> Result<bool> {
+        self.write(scope, move |conn| {
+            Ok(E::delete(conn, scope.include_deleted(false), id)?)
+        })
+        .await
+    }
+
+    async fn restore(
+        &self,
+        scope: Scope,
+        id: i64,
+    ) -> Result<bool> {
+        let Some(table) = E::SOFT_DELETE else {
+            return Ok(false);
+        };
+        self.write(scope, move |conn| {
+            soft_delete::lock_row(conn, table, id)?;
+            if E::find(conn, scope.include_deleted(true), id)?.is_none() {
+                return Ok(false);
+            }
+            Ok(soft_delete::set_deleted(conn, table, id, false)?)
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::test_connection;
+    use crate::modules::user::user_model::{NewUser, User, UserChanges};
+    use crate::utils::generator;
+    use chrono::Utc;
+    use diesel::Connection;
+
+    #[test]
+    fn diesel_repository_fits_users() {
+        fn assert_repository<R: Repository<User>>() {}
+        assert_repository::<DieselRepository<User>>();
+    }
+
+    #[test]
+    fn pagination_follows_list_query() {
+        struct Config;
+        impl ListConfig for Config {
+            const SORT_FIELDS: &'static [&'static str] = &["id"];
+            const FILTER_FIELDS: &'static [(
+                &'static str,
+                &'static [crate::utils::list_query::FilterOp],
+            )] = &[];
+        }
+        let offset = ListQuery::<Config>::from_pairs(vec![("page".into(), "2".into())]).unwrap();
+        assert_eq!(
+            Pagination::from(&offset),
+            Pagination::Offset { page: 2, limit: 20 }
+        );
+        let cursor = ListQuery::<Config>::from_pairs(vec![
+            ("cursor".into(), Cursor { id: 7 }.encode()),
+            ("sort".into(), "id".into()),
+        ])
+        .unwrap();
+        assert_eq!(
+            Pagination::from(&cursor),
+            Pagination::Cursor {
+                after: Some(7),
+                limit: 20,
+                direction: SortDirection::Asc,
+            }
+        );
+    }
+
+    #[test]
+    fn offset_does_not_overflow() {
+        assert_eq!(Pagination::offset(1, 20), 0);
+        assert_eq!(Pagination::offset(3, 20), 40);
+        assert_eq!(Pagination::offset(0, 20), 0);
+        assert_eq!(Pagination::offset(i64::MAX, 20), i64::MAX);
+    }
+
+    #[test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    fn writes_only_rows_of_the_scope() {
+        let mut conn = test_connection();
+        conn.begin_test_transaction().unwrap();
+        let id = generator::id() as i64;
+        let new = NewUser {
+            id,
+            full_name: "Scoped Write".to_string(),
+            email: format!("scoped{id}@example.com"),
+            phone_number: None,
+        };
+        <User as DieselEntity>::insert(&mut conn, new.clone()).unwrap();
+        let changes = UserChanges {
+            full_name: "Renamed".to_string(),
+            email: new.email,
+            phone_number: None,
+            updated_at: Utc::now(),
+        };
+        let scope = Scope::default();
+
+        let updated = User::update(&mut conn, scope, id, changes.clone()).unwrap();
+        assert_eq!(updated.unwrap().full_name, "Renamed");
+        assert!(User::delete(&mut conn, scope, id).unwrap());
+        // Deleted rows are out of the scope of writes
+        assert!(User::update(&mut conn, scope, id, changes.clone())
+            .unwrap()
+            .is_none());
+        assert!(!User::delete(&mut conn, scope, id).unwrap());
+        let missing = generator::id() as i64;
+        assert!(User::update(&mut conn, scope, missing, changes)
+            .unwrap()
+            .is_none());
+        assert!(!User::delete(&mut conn, scope, missing).unwrap());
+    }
+}
@@ -0,0 +1,375 @@
+//! In-memory [`Repository`] for unit tests of services.
+//!
+//! Follows the same scope rules as [`DieselRepository`](super::crud::DieselRepository):
+//! soft deleted rows are hidden unless included, tenant scoped rows only show to their
+//! tenant, and inserting an existing id fails with a unique violation like the table would.
+use super::crud::{cursor_page, Entity, Pagination, Repository, Scope};
+use crate::utils::list_query::{Page, SortDirection};
+use anyhow::Result;
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// Entity the in-memory repository can create and change without a database.
+pub trait MemoryEntity: Entity {
+    /// Row stored by `insert`, with the defaults the table would fill in
+    fn create(new: Self::New) -> Self;
+
+    fn apply(
+        &mut self,
+        changes: Self::Changes,
+    );
+
+    /// Tenant of the row, compared with the scope where the table uses `tenant_filter`
+    fn tenant_id(&self) -> Option<i64> {
+        None
+    }
+
+    /// Keep the model's `deleted_at` in step with soft deletes and restores
+    fn set_deleted_at(
+        &mut self,
+        deleted_at: Option<DateTime<Utc>>,
+    ) {
+        let _ = deleted_at;
+    }
+}
+
+struct Row<E> {
+    entity: E,
+    deleted: bool,
+}
+
+impl<E: MemoryEntity> Row<E> {
+    fn visible(
+        &self,
+        scope: Scope,
+    ) -> bool {
+        let tenant = match (scope.tenant_id, self.entity.tenant_id()) {
+            (Some(tenant_id), Some(row_tenant_id)) => tenant_id == row_tenant_id,
+            _ => true,
+        };
+        tenant && (scope.include_deleted || !self.deleted)
+    }
+}
+
+/// [`Repository`] keeping rows in a map, clones share the rows.
+pub struct MemoryRepository<E> {
+    rows: Arc<RwLock<BTreeMap<i64, Row<E>>>>,
+}
+
+impl<E> Default for MemoryRepository<E> {
+    fn default() -> Self {
+        MemoryRepository {
+            rows: Arc::default(),
+        }
+    }
+}
+
+impl<E> Clone for MemoryRepository<E> {
+    fn clone(&self) -> Self {
+        MemoryRepository {
+            rows: self.rows.clone(),
+        }
+    }
+}
+
+impl<E: MemoryEntity> MemoryRepository<E> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Repository already holding `entities`, e.g. the fixtures of a test.
+    pub fn with_rows(entities: impl IntoIterator<Item = E>) -> Self {
+        let rows = entities
+            .into_iter()
+            .map(|entity| {
+                let row = Row {
+                    entity,
+                    deleted: false,
+                };
+                (row.entity.id(), row)
+            })
+            .collect();
+        MemoryRepository {
+            rows: Arc::new(RwLock::new(rows)),
+        }
+    }
+
+    /// Number of stored rows, soft deleted ones included.
+    pub async fn len(&self) -> usize {
+        self.rows.read().await.len()
+    }
+
+    pub async fn is_empty(&self) -> bool {
+        self.rows.read().await.is_empty()
+    }
+}
+
+#[async_trait]
+impl<E: MemoryEntity> Repository<E> for MemoryRepository<E> {
+    async fn find(
+        &self,
+        scope: Scope,
+        id: i64,
+    ) -> Result<Option<E>> {
+        let rows = self.rows.read().await;
+        Ok(rows
+            .get(&id)
+            .filter(|row| row.visible(scope))
+            .map(|row| row.entity.clone()))
+    }
+
+    async fn list(
+        &self,
+        scope: Scope,
+        pagination: Pagination,
+    ) -> Result<Page<E>> {
+        let rows = self.rows.read().await;
+        let visible = rows.values().filter(|row| row.visible(scope));
+        match pagination {
+            Pagination::Offset { page, limit } => {
+                let items: Vec<E> = visible.map(|row| row.entity.clone()).collect();
+                let total = items.len() as i64;
+                let items = items
+                    .into_iter()
+                    .rev()
+                    .skip(usize::try_from(Pagination::offset(page, limit)).unwrap_or(usize::MAX))
+                    .take(usize::try_from(limit).unwrap_or(0))
+                    .collect();
+                Ok(Page {
+                    items,
+                    total: Some(total),
+                    next_cursor: None,
+                })
+            }
+            Pagination::Cursor {
+                after,
+                limit,
+                direction,
+            } => {
+                let take = (limit + 1) as usize;
+                let items: Vec<E> = match direction {
+                    SortDirection::Asc => visible
+                        .filter(|row| after.is_none_or(|after| row.entity.id() > after))
+                        .take(take)
+                        .map(|row| row.entity.clone())
+                        .collect(),
+                    SortDirection::Desc => visible
+                        .rev()
+                        .filter(|row| after.is_none_or(|after| row.entity.id() < after))
+                        .take(take)
+                        .map(|row| row.entity.clone())
+                        .collect(),
+                };
+                Ok(cursor_page(items, limit))
+            }
+        }
+    }
+
+    async fn insert(
+        &self,
+        _scope: Scope,
+        new: E::New,
+    ) -> Result<E> {
+        let entity = E::create(new);
+        let mut rows = self.rows.write().await;
+        if rows.contains_key(&entity.id()) {
+            return Err(DieselError::DatabaseError(
+                DatabaseErrorKind::UniqueViolation,
+                Box::new(format!("duplicate key value, id {}", entity.id())),
+            )
+            .into());
+        }
+        rows.insert(
+            entity.id(),
+            Row {
+                entity: entity.clone(),
+                deleted: false,
+            },
+        );
+        Ok(entity)
+    }
+
+    async fn update(
+        &self,
+        scope: Scope,
+        id: i64,
+        changes: E::Changes,
+    ) -> Result<Option<E>> {
+        let mut rows = self.rows.write().await;
+        let Some(row) = rows
+            .get_mut(&id)
+            .filter(|row| row.visible(scope.include_deleted(false)))
+        else {
+            return Ok(None);
+        };
+        row.entity.apply(changes);
+        Ok(Some(row.entity.clone()))
+    }
+
+    async fn delete(
+        &self,
+        scope: Scope,
+        id: i64,
+    ) -> Result<bool> {
+        let mut rows = self.rows.write().await;
+        if !rows
+            .get(&id)
+            .is_some_and(|row| row.visible(scope.include_deleted(false)))
+        {
+            return Ok(false);
+        }
+        if E::SOFT_DELETE.is_none() {
+            return Ok(rows.remove(&id).is_some());
+        }
+        if let Some(row) = rows.get_mut(&id) {
+            row.deleted = true;
+            row.entity.set_deleted_at(Some(Utc::now()));
+        }
+        Ok(true)
+    }
+
+    async fn restore(
+        &self,
+        scope: Scope,
+        id: i64,
+    ) -> Result<bool> {
+        let mut rows = self.rows.write().await;
+        match rows
+            .get_mut(&id)
+            .filter(|row| row.deleted && row.visible(scope.include_deleted(true)))
+        {
+            Some(row) => {
+                row.deleted = false;
+                row.entity.set_deleted_at(None);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::user::user_model::{NewUser, User, UserChanges};
+    use crate::utils::errors::HttpError;
+    use axum::http::StatusCode;
+
+    fn new_user(id: i64) -> NewUser {
+        NewUser {
+            id,
+            full_name: format!("User {id}"),
+            email: format!("user{id}@example.com"),
+            phone_number: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn crud_round_trip() {
+        let repository = MemoryRepository::<User>::new();
+        let scope = Scope::default();
+        let user = repository.insert(scope, new_user(1)).await.unwrap();
+        assert_eq!(user.email, "user1@example.com");
+
+        let duplicate = repository.insert(scope, new_user(1)).await.unwrap_err();
+        assert_eq!(HttpError::from(duplicate).status, StatusCode::CONFLICT);
+
+        let changes = UserChanges {
+            full_name: "Renamed".into(),
+            email: user.email.clone(),
+            phone_number: None,
+            updated_at: Utc::now(),
+        };
+        let updated = repository.update(scope, 1, changes.clone()).await.unwrap();
+        assert_eq!(updated.unwrap().full_name, "Renamed");
+        assert!(repository
+            .update(scope, 2, changes)
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn soft_delete_hides_rows_until_restored() {
+        let repository = MemoryRepository::<User>::new();
+        let scope = Scope::default();
+        repository.insert(scope, new_user(1)).await.unwrap();
+
+        assert!(repository.delete(scope, 1).await.unwrap());
+        assert!(!repository.delete(scope, 1).await.unwrap());
+        assert!(repository.find(scope, 1).await.unwrap().is_none());
+        let deleted = repository
+            .find(scope.include_deleted(true), 1)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(deleted.deleted_at.is_some());
+        assert_eq!(repository.len().await, 1);
+
+        assert!(repository.restore(scope, 1).await.unwrap());
+        assert!(repository.find(scope, 1).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn lists_pages_newest_first() {
+        let repository = MemoryRepository::<User>::new();
+        for id in 1..=5 {
+            repository
+                .insert(Scope::default(), new_user(id))
+                .await
+                .unwrap();
+        }
+        repository.delete(Scope::default(), 5).await.unwrap();
+
+        let page = repository
+            .list(Scope::default(), Pagination::Offset { page: 1, limit: 3 })
+            .await
+            .unwrap();
+        let ids: Vec<i64> = page.items.iter().map(|user| user.id).collect();
+        assert_eq!(ids, vec![4, 3, 2]);
+        assert_eq!(page.total, Some(4));
+
+        let past_the_end = repository
+            .list(
+                Scope::default(),
+                Pagination::Offset {
+                    page: i64::MAX,
+                    limit: 3,
+                },
+            )
+            .await
+            .unwrap();
+        assert!(past_the_end.items.is_empty());
+
+        let first = repository
+            .list(
+                Scope::default(),
+                Pagination::Cursor {
+                    after: None,
+                    limit: 2,
+                    direction: SortDirection::Desc,
+                },
+            )
+            .await
+            .unwrap();
+        let second = repository
+            .list(
+                Scope::default(),
+                Pagination::Cursor {
+                    after: first.items.last().map(|user| user.id),
+                    limit: 2,
+                    direction: SortDirection::Desc,
+                },
+            )
+            .await
+            .unwrap();
+        assert!(first.next_cursor.is_some());
+        let ids: Vec<i64> = second.items.iter().map(|user| user.id).collect();
+        assert_eq!(ids, vec![2, 1]);
+        assert!(second.next_cursor.is_none());
+    }
+}
@@ -0,0 +1,5 @@
+pub mod audit;
+pub mod crud;
+pub mod memory;
+pub mod soft_delete;
+pub mod tenant;
@@ -0,0 +1,126 @@
+//! Soft delete convention.
+//!
+//! A soft deletable table has a nullable `deleted_at TIMESTAMPTZ` column and a `BIGINT id`
+//! primary key. Deleting a row only stamps `deleted_at`, reads exclude stamped rows unless
+//! an admin asks for them with `include_deleted`, and [`purge_all`] removes rows for good
+//! once the retention period is over (see `tasks::purge`).
+use crate::database::instrumentation::CountedRunQueryDsl;
+use crate::schema::table::users;
+use chrono::{DateTime, Utc};
+use diesel::prelude::*;
+use diesel::sql_query;
+use diesel::sql_types::{Bigint, Timestamptz};
+use serde::Deserialize;
+use utoipa::IntoParams;
+
+/// Table following the soft delete convention.
+pub trait SoftDelete {
+    const TABLE: &'static str;
+}
+
+impl SoftDelete for users::table {
+    const TABLE: &'static str = "users";
+}
+
+/// Tables purged by [`purge_all`], children before parents.
+const SOFT_DELETE_TABLES: &[&str] = &[users::table::TABLE];
+
+/// Query option of single resource endpoints, list endpoints read it through `ListQuery`.
+#[derive(Debug, Default, Deserialize, IntoParams)]
+pub struct DeletedScope {
+    /// Include soft deleted rows, admin only
+    #[serde(default)]
+    pub include_deleted: bool,
+}
+
+/// Stamp `deleted_at` on a live row, returns false when there is none with this id.
+pub fn soft_delete<T: SoftDelete>(
+    conn: &mut PgConnection,
+    id: i64,
+) -> QueryResult<bool> {
+    set_deleted(conn, T::TABLE, id, true)
+}
+
+/// Clear `deleted_at` on a deleted row, returns false when there is none with this id.
+pub fn restore<T: SoftDelete>(
+    conn: &mut PgConnection,
+    id: i64,
+) -> QueryResult<bool> {
+    set_deleted(conn, T::TABLE, id, false)
+}
+
+/// Stamp or clear `deleted_at` on a row of `table`, returns false when it was already so.
+pub(crate) fn set_deleted(
+    conn: &mut PgConnection,
+    table: &str,
+    id: i64,
+    deleted: bool,
+) -> QueryResult<bool> {
+    let statement = if deleted {
+        "SET deleted_at = NOW() WHERE id = $1 AND deleted_at IS NULL"
+    } else {
+        "SET deleted_at = NULL WHERE id = $1 AND deleted_at IS NOT NULL"
+    };
+    let updated = sql_query(format!("UPDATE {table} {statement}"))
+        .bind::<Bigint, _>(id)
+        .execute_counted(conn)?;
+    Ok(updated > 0)
+}
+
+/// Lock a row of `table` until the end of the transaction, so a check made on it still
+/// holds when the following write runs.
+pub(crate) fn lock_row(
+    conn: &mut PgConnection,
+    table: &str,
+    id: i64,
+) -> QueryResult<()> {
+    sql_query(format!("SELECT id FROM {table} WHERE id = $1 FOR UPDATE"))
+        .bind::<Bigint, _>(id)
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Hard delete rows of one table deleted before `deleted_before`.
+pub fn purge<T: SoftDelete>(
+    conn: &mut PgConnection,
+    deleted_before: DateTime<Utc>,
+) -> QueryResult<usize> {
+    purge_table(conn, T::TABLE, deleted_before)
+}
+
+/// Hard delete rows of every soft deletable table deleted before `deleted_before`.
+pub fn purge_all(
+    conn: &mut PgConnection,
+    deleted_before: DateTime<Utc>,
+) -> QueryResult<usize> {
+    let mut purged = 0;
+    for table in SOFT_DELETE_TABLES {
+        purged += purge_table(conn, table, deleted_before)?;
+    }
+    Ok(purged)
+}
+
+fn purge_table(
+    conn: &mut PgConnection,
+    table: &str,
+    deleted_before: DateTime<Utc>,
+) -> QueryResult<usize> {
+    sql_query(format!("DELETE FROM {table} WHERE deleted_at < $1"))
+        .bind::<Timestamptz, _>(deleted_before)
+        .execute_counted(conn)
+}
+
+/// Exclude soft deleted rows from a boxed query unless `include_deleted` is set.
+///
+/// `exclude_deleted!(users::table.into_boxed(), users::deleted_at, scope.include_deleted)`
+#[macro_export]
+macro_rules! exclude_deleted {
+    ($query:expr, $column:expr, $include_deleted:expr) => {{
+        let query = $query;
+        if $include_deleted {
+            query
+        } else {
+            query.filter($column.is_null())
+        }
+    }};
+}
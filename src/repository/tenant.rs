@@ -0,0 +1,17 @@
+//! Tenant scoping convention.
+//!
+//! A tenant scoped table has a `BIGINT` column referencing `organizations (id)`. Services
+//! start every query on such a table from [`tenant_scoped!`](crate::tenant_scoped), and run
+//! it through `Database::tenant_transaction` so the row level security policies of the table
+//! apply as well. The policies hide every row outside of it, cross tenant work goes through
+//! `Database::unscoped_transaction`.
+
+/// Boxed query over the rows of one tenant.
+///
+/// `tenant_scoped!(organization_members::table, organization_members::organization_id, tenant.id)`
+#[macro_export]
+macro_rules! tenant_scoped {
+    ($table:expr, $column:expr, $tenant_id:expr) => {
+        $table.filter($column.eq($tenant_id)).into_boxed()
+    };
+}
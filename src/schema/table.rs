@@ -1,2 +1,191 @@
+// @generated automatically by Diesel CLI.
 
+diesel::table! {
+    audit_logs (id) {
+        id -> Int8,
+        actor_id -> Nullable<Int8>,
+        #[max_length = 64]
+        action -> Varchar,
+        #[max_length = 64]
+        subject_type -> Varchar,
+        subject_id -> Int8,
+        details -> Jsonb,
+        created_at -> Timestamptz,
+    }
+}
 
+diesel::table! {
+    data_exports (id) {
+        id -> Int8,
+        user_id -> Int8,
+        #[max_length = 8]
+        format -> Varchar,
+        #[max_length = 16]
+        status -> Varchar,
+        #[max_length = 128]
+        file_key -> Nullable<Varchar>,
+        created_at -> Timestamptz,
+        completed_at -> Nullable<Timestamptz>,
+        expires_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    erasure_requests (id) {
+        id -> Int8,
+        user_id -> Int8,
+        #[max_length = 16]
+        status -> Varchar,
+        execute_after -> Timestamptz,
+        created_at -> Timestamptz,
+        cancelled_at -> Nullable<Timestamptz>,
+        completed_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    invitations (id) {
+        id -> Int8,
+        organization_id -> Int8,
+        #[max_length = 255]
+        email -> Varchar,
+        #[max_length = 16]
+        role -> Varchar,
+        invited_by -> Nullable<Int8>,
+        expires_at -> Timestamptz,
+        accepted_at -> Nullable<Timestamptz>,
+        revoked_at -> Nullable<Timestamptz>,
+        sent_at -> Timestamptz,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    jobs (id) {
+        id -> Int8,
+        #[max_length = 64]
+        queue -> Varchar,
+        #[max_length = 64]
+        job_type -> Varchar,
+        payload -> Jsonb,
+        #[max_length = 16]
+        status -> Varchar,
+        attempts -> Int4,
+        max_attempts -> Int4,
+        last_error -> Nullable<Text>,
+        #[max_length = 255]
+        unique_key -> Nullable<Varchar>,
+        run_at -> Timestamptz,
+        locked_until -> Nullable<Timestamptz>,
+        created_at -> Timestamptz,
+        completed_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    magic_links (id) {
+        id -> Int8,
+        #[max_length = 255]
+        email -> Varchar,
+        expires_at -> Timestamptz,
+        consumed_at -> Nullable<Timestamptz>,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    organization_members (organization_id, user_id) {
+        organization_id -> Int8,
+        user_id -> Int8,
+        #[max_length = 16]
+        role -> Varchar,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    organizations (id) {
+        id -> Int8,
+        #[max_length = 255]
+        name -> Varchar,
+        #[max_length = 63]
+        slug -> Varchar,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    outbox_events (id) {
+        id -> Int8,
+        #[max_length = 64]
+        event_type -> Varchar,
+        #[max_length = 64]
+        aggregate_type -> Varchar,
+        aggregate_id -> Int8,
+        payload -> Jsonb,
+        #[max_length = 16]
+        status -> Varchar,
+        attempts -> Int4,
+        last_error -> Nullable<Text>,
+        next_attempt_at -> Timestamptz,
+        created_at -> Timestamptz,
+        delivered_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    user_identities (id) {
+        id -> Int8,
+        user_id -> Int8,
+        #[max_length = 64]
+        provider -> Varchar,
+        #[max_length = 255]
+        subject -> Varchar,
+        #[max_length = 255]
+        email -> Nullable<Varchar>,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    users (id) {
+        id -> Int8,
+        #[max_length = 255]
+        full_name -> Varchar,
+        #[max_length = 255]
+        email -> Varchar,
+        #[max_length = 32]
+        phone_number -> Nullable<Varchar>,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+        #[max_length = 16]
+        role -> Varchar,
+        deleted_at -> Nullable<Timestamptz>,
+        #[max_length = 32]
+        avatar_key -> Nullable<Varchar>,
+        version -> Int4,
+    }
+}
+
+diesel::joinable!(data_exports -> users (user_id));
+diesel::joinable!(erasure_requests -> users (user_id));
+diesel::joinable!(invitations -> organizations (organization_id));
+diesel::joinable!(invitations -> users (invited_by));
+diesel::joinable!(organization_members -> organizations (organization_id));
+diesel::joinable!(organization_members -> users (user_id));
+diesel::joinable!(user_identities -> users (user_id));
+
+diesel::allow_tables_to_appear_in_same_query!(
+    audit_logs,
+    data_exports,
+    erasure_requests,
+    invitations,
+    jobs,
+    magic_links,
+    organization_members,
+    organizations,
+    outbox_events,
+    user_identities,
+    users,
+);
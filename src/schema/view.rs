@@ -1,2 +1,19 @@
+// Views are not picked up by `diesel print-schema`, keep them in step with their migration.
+//
+// A view is declared like a table, with a column that is unique per row as its key so
+// `find` works. It is read-only: never insert into or update one through Diesel.
 
-
+diesel::table! {
+    user_overviews (id) {
+        id -> Int8,
+        #[max_length = 255]
+        full_name -> Varchar,
+        #[max_length = 255]
+        email -> Varchar,
+        #[max_length = 16]
+        role -> Varchar,
+        created_at -> Timestamptz,
+        organization_count -> Int8,
+        identity_count -> Int8,
+    }
+}
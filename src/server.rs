@@ -1,21 +1,53 @@
+use crate::database::instrumentation;
+use crate::middlewares::{database_middlewares, tenant_middlewares};
 use crate::modules::AppRoute;
 use crate::{constant, utils::errors::HttpError, AppState};
-use axum::body::{to_bytes, Body};
-use axum::http::HeaderValue;
-use axum::middleware::from_fn;
+use axum::body::{to_bytes, Body, HttpBody};
+use axum::http::{HeaderValue, Method, StatusCode};
 use axum::middleware::Next;
+use axum::middleware::{from_fn, from_fn_with_state};
 use axum::response::Response;
 use axum::{error_handling::HandleErrorLayer, extract::Request, response::IntoResponse};
+use futures_util::StreamExt;
 use std::net::SocketAddr;
 use std::sync::Arc;
 use std::time::Duration;
+use tokio_util::sync::CancellationToken;
 use tower::{buffer::BufferLayer, limit::RateLimitLayer, ServiceBuilder};
 use tower_http::{cors::CorsLayer, trace::TraceLayer};
 use ulid::Ulid;
 
+// Success log of a response, written when dropped. A streamed body, such as an export, keeps
+// it until the body is sent or the client goes away, so the queries it ran are counted.
+struct SuccessLog {
+    method: Method,
+    trace_id: Ulid,
+    path: String,
+    status: StatusCode,
+    queries: instrumentation::QueryTotals,
+}
+
+impl Drop for SuccessLog {
+    fn drop(&mut self) {
+        tracing::info!(
+            method = %self.method,
+            trace_id = %self.trace_id,
+            path = %self.path,
+            status = %self.status.as_u16(),
+            queries = self.queries.queries(),
+            rows = self.queries.rows(),
+            "HTTP_SUCCESS_RESPONSE"
+        );
+    }
+}
+
 pub struct ApplicationServer;
 impl ApplicationServer {
-    pub async fn serve(app_state: Arc<AppState>) -> Result<(), Box<dyn std::error::Error>> {
+    // Serve until a shutdown signal, which also cancels `shutdown` so background work drains
+    pub async fn serve(
+        app_state: Arc<AppState>,
+        shutdown: CancellationToken,
+    ) -> Result<(), Box<dyn std::error::Error>> {
         // Define layered services
         let port: u16 = app_state.env.port;
         let timeout_secs = app_state.env.timeout;
@@ -32,6 +64,11 @@ impl ApplicationServer {
             .layer(RateLimitLayer::new(1024, Duration::from_secs(1)));
         // register routes
         let app = AppRoute::register()
+            .layer(from_fn_with_state(
+                app_state.clone(),
+                tenant_middlewares::resolve_tenant,
+            ))
+            .layer(from_fn(database_middlewares::read_your_writes))
             .with_state(app_state.clone())
             .layer(route_layer)
             .fallback(Self::handle_404);
@@ -39,7 +76,7 @@ impl ApplicationServer {
         let listener: tokio::net::TcpListener = tokio::net::TcpListener::bind(addr).await?;
         tracing::info!("SERVER_LAUNCH_SUCCESS: listening on {}", addr);
         axum::serve(listener, app)
-            .with_graceful_shutdown(Self::shutdown_signal())
+            .with_graceful_shutdown(Self::shutdown_signal(shutdown))
             .await
             .map_err(|err| {
                 tracing::error!("SERVER_ERROR: {err}");
@@ -57,6 +94,7 @@ impl ApplicationServer {
             )
             .allow_methods(constant::METHOD_ALLOW)
             .allow_headers(constant::HEADER_ALLOW)
+            .expose_headers(constant::HEADER_EXPOSE)
     }
     async fn handle_timeout_error(
         err: Box<dyn std::error::Error + Send + Sync>
@@ -77,7 +115,8 @@ impl ApplicationServer {
 
         let method = req.method().clone();
         let uri = req.uri().clone();
-        let mut response = next.run(req).await;
+        let (mut response, queries) =
+            instrumentation::track_queries(trace_id.to_string(), next.run(req)).await;
         // attach trace id header
         match HeaderValue::from_str(&trace_id.to_string()) {
             Ok(header_value) => {
@@ -107,6 +146,8 @@ impl ApplicationServer {
                         trace_id = %trace_id,
                         path = %uri.path(),
                         status = %status.as_u16(),
+                        queries = queries.queries(),
+                        rows = queries.rows(),
                         body = %body_str,
                         "HTTP_ERROR_RESPONSE"
                     );
@@ -127,18 +168,27 @@ impl ApplicationServer {
                 }
             }
         } else {
-            tracing::info!(
-                %method,
-                trace_id = %trace_id,
-                path = %uri.path(),
-                status = %status.as_u16(),
-                "HTTP_SUCCESS_RESPONSE"
-            );
+            let log = SuccessLog {
+                method,
+                trace_id,
+                path: uri.path().to_string(),
+                status,
+                queries,
+            };
+            // Bodies of a known size are complete, the others may still be querying
+            if response.body().size_hint().exact().is_none() {
+                let (parts, body) = response.into_parts();
+                let body = body.into_data_stream().map(move |chunk| {
+                    let _log = &log;
+                    chunk
+                });
+                response = Response::from_parts(parts, Body::from_stream(body));
+            }
         }
         response
     }
 
-    async fn shutdown_signal() {
+    async fn shutdown_signal(shutdown: CancellationToken) {
         let ctrl_c = async {
             tokio::signal::ctrl_c()
                 .await
@@ -158,8 +208,10 @@ impl ApplicationServer {
         tokio::select! {
             _ = ctrl_c => {},
             _ = terminate => {},
+            _ = shutdown.cancelled() => {},
         }
         tracing::info!("Shutdown signal received, starting graceful shutdown");
+        shutdown.cancel();
     }
     async fn handle_404() -> impl IntoResponse {
         HttpError::not_found("The requested resource was not found")
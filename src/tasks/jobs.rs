@@ -0,0 +1,27 @@
+use crate::database::Database;
+use crate::jobs;
+use chrono::Utc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+/// Hourly delete jobs completed before `retention`, failed ones stay for inspection.
+pub fn spawn(
+    db: Database,
+    retention: chrono::Duration,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(Duration::from_secs(3600));
+        loop {
+            ticker.tick().await;
+            let completed_before = Utc::now() - retention;
+            match db
+                .transaction(move |conn| Ok(jobs::purge_completed(conn, completed_before)?))
+                .await
+            {
+                Ok(0) => {}
+                Ok(purged) => tracing::info!(purged, %completed_before, "JOBS_PURGED"),
+                Err(err) => tracing::error!(error = %err, "JOBS_PURGE_FAILED"),
+            }
+        }
+    })
+}
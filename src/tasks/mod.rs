@@ -0,0 +1,6 @@
+//! Background tasks spawned next to the HTTP server from `main.rs`.
+pub mod jobs;
+pub mod outbox;
+pub mod privacy;
+pub mod purge;
+pub mod replicas;
@@ -0,0 +1,45 @@
+use crate::database::Database;
+use crate::outbox::{self, relay::Relay};
+use chrono::Utc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+/// Relay outbox events every `interval`, and hourly purge those delivered before `retention`.
+///
+/// A full batch is followed by the next one right away, so a backlog drains quickly.
+pub fn spawn(
+    db: Database,
+    relay: Relay,
+    interval: Duration,
+    retention: chrono::Duration,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        let mut purge = tokio::time::interval(Duration::from_secs(3600));
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => loop {
+                    match relay.run_once().await {
+                        Ok(claimed) if claimed > 0 => continue,
+                        Ok(_) => break,
+                        Err(err) => {
+                            tracing::error!(error = %err, "OUTBOX_RELAY_FAILED");
+                            break;
+                        }
+                    }
+                },
+                _ = purge.tick() => {
+                    let delivered_before = Utc::now() - retention;
+                    match db
+                        .transaction(move |conn| Ok(outbox::purge_delivered(conn, delivered_before)?))
+                        .await
+                    {
+                        Ok(0) => {}
+                        Ok(purged) => tracing::info!(purged, %delivered_before, "OUTBOX_PURGED"),
+                        Err(err) => tracing::error!(error = %err, "OUTBOX_PURGE_FAILED"),
+                    }
+                }
+            }
+        }
+    })
+}
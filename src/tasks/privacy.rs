@@ -0,0 +1,27 @@
+use crate::database::Database;
+use crate::modules::privacy::privacy_service;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+/// Periodically execute erasure requests past their grace period and delete expired exports.
+pub fn spawn(
+    db: Database,
+    interval: Duration,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            match privacy_service::run_due_erasures(&db).await {
+                Ok(0) => {}
+                Ok(erased) => tracing::info!(erased, "USER_DATA_ERASED"),
+                Err(err) => tracing::error!(error = %err, "USER_DATA_ERASURE_FAILED"),
+            }
+            match privacy_service::remove_expired_exports(&db).await {
+                Ok(0) => {}
+                Ok(removed) => tracing::info!(removed, "DATA_EXPORTS_EXPIRED"),
+                Err(err) => tracing::error!(error = %err, "DATA_EXPORT_CLEANUP_FAILED"),
+            }
+        }
+    })
+}
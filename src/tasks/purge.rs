@@ -0,0 +1,28 @@
+use crate::database::Database;
+use crate::repository::soft_delete;
+use chrono::Utc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+/// Periodically hard delete soft deleted rows older than `retention`.
+pub fn spawn(
+    db: Database,
+    retention: chrono::Duration,
+    interval: Duration,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            let deleted_before = Utc::now() - retention;
+            match db
+                .transaction(move |conn| Ok(soft_delete::purge_all(conn, deleted_before)?))
+                .await
+            {
+                Ok(0) => {}
+                Ok(purged) => tracing::info!(purged, %deleted_before, "SOFT_DELETE_PURGED"),
+                Err(err) => tracing::error!(error = %err, "SOFT_DELETE_PURGE_FAILED"),
+            }
+        }
+    })
+}
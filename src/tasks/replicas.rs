@@ -0,0 +1,26 @@
+use crate::database::Database;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+/// Periodically check the read replicas, dropping unreachable or lagging ones from rotation.
+pub fn spawn(
+    db: Database,
+    interval: Duration,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        let mut previous = None;
+        loop {
+            ticker.tick().await;
+            let healthy = db.check_replicas().await;
+            if previous != Some(healthy) {
+                if healthy == 0 {
+                    tracing::warn!("DATABASE_NO_HEALTHY_REPLICA");
+                } else {
+                    tracing::info!(healthy, "DATABASE_HEALTHY_REPLICAS");
+                }
+                previous = Some(healthy);
+            }
+        }
+    })
+}
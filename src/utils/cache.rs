@@ -45,6 +45,19 @@ impl Cache {
         let mut store = self.store.write().await;
         store.remove(key);
     }
+    // Remove a key, the keys starting with a prefix ending in `*`, or everything for "" and "*"
+    pub async fn invalidate(&self, pattern: &str) -> usize {
+        let mut store = self.store.write().await;
+        let before = store.len();
+        match pattern.strip_suffix('*') {
+            _ if pattern.is_empty() => store.clear(),
+            Some(prefix) => store.retain(|key, _| !key.starts_with(prefix)),
+            None => {
+                store.remove(pattern);
+            }
+        }
+        before - store.len()
+    }
     pub async fn clear(&self) {
         let mut store = self.store.write().await;
         store.clear();
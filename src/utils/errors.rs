@@ -1,5 +1,6 @@
 use std::fmt;
 use axum::http::StatusCode;
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
 use axum::Json;
 use axum::response::{IntoResponse, Response};
 use serde_json::json;
@@ -59,6 +60,24 @@ impl HttpError {
             status: StatusCode::FORBIDDEN,
         }
     }
+    pub fn too_many_requests(message: impl Into<String>) -> Self {
+        HttpError {
+            message: message.into(),
+            status: StatusCode::TOO_MANY_REQUESTS,
+        }
+    }
+    pub fn unprocessable_entity(message: impl Into<String>) -> Self {
+        HttpError {
+            message: message.into(),
+            status: StatusCode::UNPROCESSABLE_ENTITY,
+        }
+    }
+    pub fn service_unavailable(message: impl Into<String>) -> Self {
+        HttpError {
+            message: message.into(),
+            status: StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
 
     pub fn into_http_response(self) -> Response {
         let body = json!({
@@ -83,3 +102,141 @@ impl IntoResponse for HttpError {
         self.into_http_response()
     }
 }
+
+// Database failures are logged in full, the response only carries a generic message
+impl From<DieselError> for HttpError {
+    fn from(err: DieselError) -> Self {
+        match &err {
+            DieselError::NotFound => {
+                tracing::debug!(error = %err, "DATABASE_ROW_NOT_FOUND");
+                HttpError::not_found("RESOURCE_NOT_FOUND")
+            }
+            DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, info) => {
+                tracing::warn!(error = %err, constraint = info.constraint_name(), "DATABASE_UNIQUE_VIOLATION");
+                HttpError::unique_constraint_violation("RESOURCE_ALREADY_EXISTS")
+            }
+            DieselError::DatabaseError(DatabaseErrorKind::ForeignKeyViolation, info) => {
+                tracing::warn!(error = %err, constraint = info.constraint_name(), "DATABASE_FOREIGN_KEY_VIOLATION");
+                HttpError::unprocessable_entity("INVALID_REFERENCE")
+            }
+            _ => {
+                tracing::error!(error = %err, "DATABASE_QUERY_FAILED");
+                HttpError::server_error("UNEXPECTED_ERROR_OCCURRED")
+            }
+        }
+    }
+}
+// r2d2 only fails to hand out a connection when none frees up before the timeout
+impl From<diesel::r2d2::PoolError> for HttpError {
+    fn from(err: diesel::r2d2::PoolError) -> Self {
+        tracing::error!(error = %err, "DATABASE_POOL_TIMEOUT");
+        HttpError::service_unavailable("DATABASE_UNAVAILABLE")
+    }
+}
+#[cfg(feature = "async-pool")]
+impl From<deadpool::managed::PoolError<diesel::ConnectionError>> for HttpError {
+    fn from(err: deadpool::managed::PoolError<diesel::ConnectionError>) -> Self {
+        use deadpool::managed::PoolError;
+        match err {
+            PoolError::Timeout(_) => {
+                tracing::error!(error = %err, "DATABASE_POOL_TIMEOUT");
+                HttpError::service_unavailable("DATABASE_UNAVAILABLE")
+            }
+            _ => {
+                tracing::error!(error = %err, "DATABASE_POOL_FAILED");
+                HttpError::server_error("UNEXPECTED_ERROR_OCCURRED")
+            }
+        }
+    }
+}
+// Errors out of `Database` calls, an HttpError returned by the operation passes through
+impl From<anyhow::Error> for HttpError {
+    fn from(err: anyhow::Error) -> Self {
+        let err = match err.downcast::<HttpError>() {
+            Ok(http_error) => return http_error,
+            Err(err) => err,
+        };
+        let err = match err.downcast::<DieselError>() {
+            Ok(diesel_error) => return diesel_error.into(),
+            Err(err) => err,
+        };
+        let err = match err.downcast::<diesel::r2d2::PoolError>() {
+            Ok(pool_error) => return pool_error.into(),
+            Err(err) => err,
+        };
+        #[cfg(feature = "async-pool")]
+        let err = match err.downcast::<deadpool::managed::PoolError<diesel::ConnectionError>>() {
+            Ok(pool_error) => return pool_error.into(),
+            Err(err) => err,
+        };
+        tracing::error!(error = %err, "UNEXPECTED_ERROR");
+        HttpError::server_error("UNEXPECTED_ERROR_OCCURRED")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use diesel::result::DatabaseErrorInformation;
+
+    struct Info;
+    impl DatabaseErrorInformation for Info {
+        fn message(&self) -> &str {
+            "duplicate key value violates unique constraint"
+        }
+        fn details(&self) -> Option<&str> {
+            Some("Key (email)=(someone@example.com) already exists.")
+        }
+        fn hint(&self) -> Option<&str> {
+            None
+        }
+        fn table_name(&self) -> Option<&str> {
+            Some("users")
+        }
+        fn column_name(&self) -> Option<&str> {
+            None
+        }
+        fn constraint_name(&self) -> Option<&str> {
+            Some("users_email_key")
+        }
+        fn statement_position(&self) -> Option<i32> {
+            None
+        }
+    }
+
+    fn database_error(kind: DatabaseErrorKind) -> anyhow::Error {
+        DieselError::DatabaseError(kind, Box::new(Info)).into()
+    }
+
+    #[test]
+    fn maps_database_errors_to_status() {
+        let cases = [
+            (
+                database_error(DatabaseErrorKind::UniqueViolation),
+                StatusCode::CONFLICT,
+            ),
+            (
+                database_error(DatabaseErrorKind::ForeignKeyViolation),
+                StatusCode::UNPROCESSABLE_ENTITY,
+            ),
+            (
+                database_error(DatabaseErrorKind::SerializationFailure),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ),
+            (DieselError::NotFound.into(), StatusCode::NOT_FOUND),
+            (
+                anyhow::anyhow!("connection refused"),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ),
+            (
+                HttpError::forbidden("FORBIDDEN").into(),
+                StatusCode::FORBIDDEN,
+            ),
+        ];
+        for (err, status) in cases {
+            let http_error = HttpError::from(err);
+            assert_eq!(http_error.status, status);
+            assert!(!http_error.message.contains("someone@example.com"));
+        }
+    }
+}
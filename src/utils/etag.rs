@@ -0,0 +1,290 @@
+//! Optimistic concurrency with ETags.
+//!
+//! A resource opts in with an integer `version` column bumped on every write and by
+//! implementing [`Versioned`]. Reads answer through [`conditional_response`] so a client
+//! sending `If-None-Match` with the current tag gets a 304, writes take [`IfMatch`] and only
+//! touch the row when its version is still the one the client read, otherwise 412.
+use crate::utils::errors::HttpError;
+use axum::{
+    async_trait,
+    extract::FromRequestParts,
+    http::{header, request::Parts, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use std::fmt;
+
+/// Resource carrying an optimistic lock version.
+pub trait Versioned {
+    fn id(&self) -> i64;
+    fn version(&self) -> i32;
+
+    fn etag(&self) -> ETag {
+        ETag::versioned(self.id(), self.version())
+    }
+}
+
+/// Strong entity tag, displayed with the surrounding quotes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ETag(String);
+
+impl ETag {
+    /// Opaque tag, e.g. a content hash.
+    pub fn new(tag: impl Into<String>) -> Self {
+        ETag(tag.into())
+    }
+
+    pub fn versioned(
+        id: i64,
+        version: i32,
+    ) -> Self {
+        ETag(format!("{id}-{version}"))
+    }
+
+    // Parse a tag sent back by a client, with whether it is weak
+    fn parse(raw: &str) -> Option<(Self, bool)> {
+        let raw = raw.trim();
+        let (raw, weak) = match raw.strip_prefix("W/") {
+            Some(raw) => (raw, true),
+            None => (raw, false),
+        };
+        raw.strip_prefix('"')
+            .and_then(|tag| tag.strip_suffix('"'))
+            .map(|tag| (ETag::new(tag), weak))
+    }
+
+    // Version of a tag built by `versioned` for this id
+    fn version_of(
+        &self,
+        id: i64,
+    ) -> Option<i32> {
+        let (tag_id, version) = self.0.split_once('-')?;
+        (tag_id.parse::<i64>().ok()? == id)
+            .then(|| version.parse().ok())
+            .flatten()
+    }
+
+    pub fn header_value(&self) -> HeaderValue {
+        HeaderValue::from_str(&self.to_string())
+            .unwrap_or_else(|_| HeaderValue::from_static("\"\""))
+    }
+}
+
+impl fmt::Display for ETag {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+    ) -> fmt::Result {
+        write!(f, "\"{}\"", self.0)
+    }
+}
+
+// Tags of a conditional header with their weakness, `None` for `*`
+fn parse_tags(
+    headers: &HeaderMap,
+    name: header::HeaderName,
+) -> Option<Option<Vec<(ETag, bool)>>> {
+    let values: Vec<&str> = headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .collect();
+    if values.is_empty() {
+        return None;
+    }
+    if values.iter().any(|value| value.trim() == "*") {
+        return Some(None);
+    }
+    Some(Some(
+        values
+            .iter()
+            .flat_map(|value| value.split(','))
+            .filter_map(ETag::parse)
+            .collect(),
+    ))
+}
+
+/// Required `If-Match` header of update and delete handlers, 428 when missing.
+///
+/// `If-Match` uses the strong comparison (RFC 7232 section 3.1), weak tags never match.
+#[derive(Debug, Clone)]
+pub struct IfMatch(Option<Vec<ETag>>);
+
+impl IfMatch {
+    fn from_tags(tags: Option<Vec<(ETag, bool)>>) -> Self {
+        IfMatch(tags.map(|tags| {
+            tags.into_iter()
+                .filter(|(_, weak)| !weak)
+                .map(|(tag, _)| tag)
+                .collect()
+        }))
+    }
+}
+
+impl IfMatch {
+    /// Version the client expects the row to be at, `None` when it sent `*`.
+    pub fn expected_version(
+        &self,
+        id: i64,
+    ) -> Result<Option<i32>, HttpError> {
+        match &self.0 {
+            None => Ok(None),
+            Some(tags) => tags
+                .iter()
+                .find_map(|tag| tag.version_of(id))
+                .map(Some)
+                .ok_or_else(precondition_failed),
+        }
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for IfMatch {
+    type Rejection = HttpError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        _state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        parse_tags(&parts.headers, header::IF_MATCH)
+            .map(IfMatch::from_tags)
+            .ok_or_else(|| HttpError::new("IF_MATCH_REQUIRED", StatusCode::PRECONDITION_REQUIRED))
+    }
+}
+
+/// Optional `If-None-Match` header of read handlers, compared weakly.
+#[derive(Debug, Clone, Default)]
+pub struct IfNoneMatch(Option<Option<Vec<ETag>>>);
+
+impl IfNoneMatch {
+    fn from_tags(tags: Option<Option<Vec<(ETag, bool)>>>) -> Self {
+        IfNoneMatch(
+            tags.map(|tags| tags.map(|tags| tags.into_iter().map(|(tag, _)| tag).collect())),
+        )
+    }
+}
+
+impl IfNoneMatch {
+    pub fn matches(
+        &self,
+        etag: &ETag,
+    ) -> bool {
+        match &self.0 {
+            None => false,
+            Some(None) => true,
+            Some(Some(tags)) => tags.contains(etag),
+        }
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for IfNoneMatch {
+    type Rejection = HttpError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        _state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        Ok(IfNoneMatch::from_tags(parse_tags(
+            &parts.headers,
+            header::IF_NONE_MATCH,
+        )))
+    }
+}
+
+/// Error of a write whose `If-Match` version is no longer current.
+pub fn precondition_failed() -> HttpError {
+    HttpError::new("PRECONDITION_FAILED", StatusCode::PRECONDITION_FAILED)
+}
+
+/// Attach the `ETag` header to a response.
+pub fn with_etag(
+    etag: &ETag,
+    response: impl IntoResponse,
+) -> Response {
+    let mut response = response.into_response();
+    response
+        .headers_mut()
+        .insert(header::ETAG, etag.header_value());
+    response
+}
+
+/// 304 with the tag when the client copy is current, otherwise the response with its tag.
+pub fn conditional_response(
+    if_none_match: &IfNoneMatch,
+    etag: &ETag,
+    response: impl IntoResponse,
+) -> Response {
+    if if_none_match.matches(etag) {
+        return with_etag(etag, StatusCode::NOT_MODIFIED);
+    }
+    with_etag(etag, response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(
+        name: header::HeaderName,
+        value: &'static str,
+    ) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(name, HeaderValue::from_static(value));
+        headers
+    }
+
+    #[test]
+    fn if_match_expected_version() {
+        let tags = parse_tags(
+            &headers(header::IF_MATCH, "\"7-3\", \"8-1\""),
+            header::IF_MATCH,
+        );
+        let if_match = IfMatch::from_tags(tags.unwrap());
+        assert_eq!(if_match.expected_version(7).unwrap(), Some(3));
+        assert_eq!(if_match.expected_version(8).unwrap(), Some(1));
+        assert_eq!(
+            if_match.expected_version(9).unwrap_err().status,
+            StatusCode::PRECONDITION_FAILED
+        );
+
+        let any = IfMatch::from_tags(
+            parse_tags(&headers(header::IF_MATCH, "*"), header::IF_MATCH).unwrap(),
+        );
+        assert_eq!(any.expected_version(9).unwrap(), None);
+    }
+
+    #[test]
+    fn if_match_ignores_weak_tags() {
+        let tags = parse_tags(
+            &headers(header::IF_MATCH, "W/\"7-3\", \"8-1\""),
+            header::IF_MATCH,
+        );
+        let if_match = IfMatch::from_tags(tags.unwrap());
+        assert_eq!(
+            if_match.expected_version(7).unwrap_err().status,
+            StatusCode::PRECONDITION_FAILED
+        );
+        assert_eq!(if_match.expected_version(8).unwrap(), Some(1));
+    }
+
+    #[test]
+    fn if_none_match_uses_weak_comparison() {
+        let etag = ETag::versioned(7, 3);
+        assert_eq!(etag.to_string(), "\"7-3\"");
+        let header = headers(header::IF_NONE_MATCH, "W/\"7-3\"");
+        assert!(IfNoneMatch::from_tags(parse_tags(&header, header::IF_NONE_MATCH)).matches(&etag));
+        assert!(
+            !IfNoneMatch::from_tags(parse_tags(&header, header::IF_NONE_MATCH))
+                .matches(&ETag::versioned(7, 4))
+        );
+        assert!(!IfNoneMatch::default().matches(&etag));
+
+        let response = conditional_response(
+            &IfNoneMatch::from_tags(parse_tags(&header, header::IF_NONE_MATCH)),
+            &etag,
+            "body",
+        );
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers()[header::ETAG], "\"7-3\"");
+    }
+}
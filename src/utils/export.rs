@@ -0,0 +1,248 @@
+//! Streaming file exports.
+//!
+//! [`export_response`] runs a query on a blocking thread and sends the serialized rows to
+//! the client in chunks through a bounded channel, so memory stays flat whatever the size
+//! of the result set and a slow client slows the query down instead of piling up rows.
+//! Load rows with `load_iter_counted::<_, PgRowByRowLoadingMode>` to keep Postgres results
+//! streaming as well.
+use crate::database::instrumentation::QueryScope;
+use crate::database::Database;
+use crate::utils::errors::HttpError;
+use axum::body::{Body, Bytes};
+use axum::http::{header, HeaderMap, HeaderValue};
+use axum::response::{IntoResponse, Response};
+use diesel::PgConnection;
+use serde::Serialize;
+use std::io;
+use tokio::sync::mpsc;
+
+// Size of the chunks written to the response body
+const CHUNK_BYTES: usize = 64 * 1024;
+// Chunks buffered ahead of the client
+const CHANNEL_CHUNKS: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    Ndjson,
+}
+
+impl ExportFormat {
+    /// Pick the format from the `format` query param, then the `Accept` header, CSV by default.
+    pub fn negotiate(
+        format: Option<&str>,
+        headers: &HeaderMap,
+    ) -> Result<Self, HttpError> {
+        if let Some(format) = format {
+            return match format {
+                "csv" => Ok(ExportFormat::Csv),
+                "ndjson" => Ok(ExportFormat::Ndjson),
+                _ => Err(HttpError::bad_request(format!(
+                    "EXPORT_UNSUPPORTED_FORMAT:{format}"
+                ))),
+            };
+        }
+        let accept = headers
+            .get(header::ACCEPT)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+        if accept.contains("application/x-ndjson") || accept.contains("application/ndjson") {
+            Ok(ExportFormat::Ndjson)
+        } else {
+            Ok(ExportFormat::Csv)
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Ndjson => "application/x-ndjson",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Ndjson => "ndjson",
+        }
+    }
+}
+
+/// Serializes rows and hands full chunks over to the response body.
+pub struct RowSink {
+    format: ExportFormat,
+    csv: csv::Writer<Vec<u8>>,
+    buffer: Vec<u8>,
+    sender: mpsc::Sender<Result<Bytes, io::Error>>,
+}
+
+impl RowSink {
+    fn new(
+        format: ExportFormat,
+        sender: mpsc::Sender<Result<Bytes, io::Error>>,
+    ) -> Self {
+        RowSink {
+            format,
+            csv: csv::Writer::from_writer(Vec::with_capacity(CHUNK_BYTES)),
+            buffer: Vec::with_capacity(CHUNK_BYTES),
+            sender,
+        }
+    }
+
+    /// Serialize one row, fails once the client went away so the query can stop early.
+    pub fn push<T: Serialize>(
+        &mut self,
+        row: &T,
+    ) -> anyhow::Result<()> {
+        let buffered = match self.format {
+            ExportFormat::Csv => {
+                // The writer adds the header before the first row
+                self.csv.serialize(row)?;
+                self.csv.flush()?;
+                self.csv.get_ref().len()
+            }
+            ExportFormat::Ndjson => {
+                serde_json::to_writer(&mut self.buffer, row)?;
+                self.buffer.push(b'\n');
+                self.buffer.len()
+            }
+        };
+        if buffered >= CHUNK_BYTES {
+            self.send()?;
+        }
+        Ok(())
+    }
+
+    fn send(&mut self) -> anyhow::Result<()> {
+        let chunk = match self.format {
+            ExportFormat::Csv => {
+                // The header is already out, continue with a headerless writer
+                let next = csv::WriterBuilder::new()
+                    .has_headers(false)
+                    .from_writer(Vec::with_capacity(CHUNK_BYTES));
+                std::mem::replace(&mut self.csv, next)
+                    .into_inner()
+                    .map_err(|err| anyhow::anyhow!(err.to_string()))?
+            }
+            ExportFormat::Ndjson => std::mem::take(&mut self.buffer),
+        };
+        if chunk.is_empty() {
+            return Ok(());
+        }
+        self.sender
+            .blocking_send(Ok(Bytes::from(chunk)))
+            .map_err(|_| anyhow::anyhow!("EXPORT_CLIENT_DISCONNECTED"))
+    }
+}
+
+/// Stream the rows pushed by `query` as a file download named `{file_name}.{extension}`.
+///
+/// Headers are sent before the query runs, a failure midway aborts the body so the client
+/// sees a truncated transfer rather than a file that looks complete.
+pub fn export_response<F>(
+    db: &Database,
+    format: ExportFormat,
+    file_name: &str,
+    query: F,
+) -> Response
+where
+    F: FnOnce(&mut PgConnection, &mut RowSink) -> anyhow::Result<()> + Send + 'static,
+{
+    let (sender, receiver) = mpsc::channel(CHANNEL_CHUNKS);
+    let db = db.clone();
+    // Counted with the request, whose log waits for the end of the body
+    let scope = QueryScope::capture();
+    tokio::task::spawn_blocking(move || {
+        scope.run(|| {
+            let mut sink = RowSink::new(format, sender.clone());
+            let result = db
+                .get_connection()
+                .and_then(|mut conn| query(&mut conn, &mut sink))
+                .and_then(|_| sink.send());
+            if let Err(err) = result {
+                tracing::error!(error = %err, "EXPORT_FAILED");
+                let _ = sender.blocking_send(Err(io::Error::other("EXPORT_FAILED")));
+            }
+        })
+    });
+
+    let stream = futures_util::stream::unfold(receiver, |mut receiver| async move {
+        receiver.recv().await.map(|chunk| (chunk, receiver))
+    });
+    let disposition = format!(
+        "attachment; filename=\"{file_name}.{}\"",
+        format.extension()
+    );
+    (
+        [
+            (
+                header::CONTENT_TYPE,
+                HeaderValue::from_static(format.content_type()),
+            ),
+            (
+                header::CONTENT_DISPOSITION,
+                HeaderValue::from_str(&disposition)
+                    .unwrap_or_else(|_| HeaderValue::from_static("attachment")),
+            ),
+        ],
+        Body::from_stream(stream),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn negotiates_format() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(
+            ExportFormat::negotiate(None, &headers).unwrap(),
+            ExportFormat::Csv
+        );
+        headers.insert(
+            header::ACCEPT,
+            HeaderValue::from_static("application/x-ndjson"),
+        );
+        assert_eq!(
+            ExportFormat::negotiate(None, &headers).unwrap(),
+            ExportFormat::Ndjson
+        );
+        // The query param wins over the header
+        assert_eq!(
+            ExportFormat::negotiate(Some("csv"), &headers).unwrap(),
+            ExportFormat::Csv
+        );
+        assert!(ExportFormat::negotiate(Some("xml"), &headers).is_err());
+    }
+
+    #[tokio::test]
+    async fn sink_writes_csv_header_once() {
+        let (sender, mut receiver) = mpsc::channel(CHANNEL_CHUNKS);
+        tokio::task::spawn_blocking(move || {
+            let mut sink = RowSink::new(ExportFormat::Csv, sender);
+            #[derive(Serialize)]
+            struct Row {
+                id: i64,
+                name: &'static str,
+            }
+            sink.push(&Row { id: 1, name: "a" }).unwrap();
+            sink.push(&Row {
+                id: 2,
+                name: "b, c",
+            })
+            .unwrap();
+            sink.send().unwrap();
+            // Later chunks carry rows only
+            sink.push(&Row { id: 3, name: "d" }).unwrap();
+            sink.send().unwrap();
+        })
+        .await
+        .unwrap();
+        let chunk = receiver.recv().await.unwrap().unwrap();
+        assert_eq!(&chunk[..], b"id,name\n1,a\n2,\"b, c\"\n");
+        let chunk = receiver.recv().await.unwrap().unwrap();
+        assert_eq!(&chunk[..], b"3,d\n");
+    }
+}
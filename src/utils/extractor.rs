@@ -20,8 +20,8 @@ impl<T> Deref for BodyJson<T> {
     }
 }
 
-// Helper function to format validation errors
-fn format_validation_errors(errors: &ValidationErrors) -> String {
+// Helper function to format validation errors, also used to report rejected import rows
+pub fn format_validation_errors(errors: &ValidationErrors) -> String {
     // if validation error are empty, return errors
     let map_error = errors
         .field_errors()
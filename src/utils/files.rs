@@ -29,7 +29,7 @@ pub async fn save_file<P: AsRef<Path>>(
     let path = get_path(file_name.as_ref());
 
     // Check if file exists and overwrite is false
-    if overwrite && path.exists() {
+    if !overwrite && path.exists() {
         return Err(Box::new(std::io::Error::new(
             std::io::ErrorKind::AlreadyExists,
             "FILE_EXISTS",
@@ -69,7 +69,7 @@ pub async fn delete_file<P: AsRef<Path>>(file_path: P) -> Result<(), Box<dyn std
 
     // Walk up the directory tree and remove empty folders until PREFIX_PATH or non-empty dir
     let mut current = path.parent();
-    let root = Path::new(PREFIX_PATH).canonicalize()?;
+    let root = Path::new(PREFIX_PATH);
 
     while let Some(dir) = current {
         // Stop at the storage root
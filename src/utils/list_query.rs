@@ -0,0 +1,489 @@
+//! Query string extractor shared by list endpoints.
+//!
+//! Supported parameters:
+//! - `page` and `limit` for offset pagination, or `cursor` and `limit` for cursor pagination
+//! - `sort=-created_at,full_name` where a leading `-` means descending
+//! - filters as `field=value` (equals) or `field[op]=value` with `op` one of `eq`, `in`
+//!   (comma separated values), `gte` and `like`
+//! - `include_deleted=true` to include soft deleted rows, callers must restrict it to admins
+//! - `format` is reserved for export endpoints (see `utils::export`) and ignored here
+//!
+//! Fields and operators are whitelisted per resource through [`ListConfig`], anything else
+//! is rejected with 400. Use [`filter_column!`](crate::filter_column),
+//! [`filter_text_column!`](crate::filter_text_column) and [`sort_column!`](crate::sort_column)
+//! to translate the parsed query into Diesel expressions with bound parameters.
+use crate::utils::errors::HttpError;
+use crate::utils::responses::{cursor_response_formatted, pagination_response_formatted};
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, Query},
+    http::request::Parts,
+};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::marker::PhantomData;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterOp {
+    Eq,
+    In,
+    Gte,
+    Like,
+}
+
+impl FilterOp {
+    fn parse(op: &str) -> Option<Self> {
+        match op {
+            "eq" => Some(FilterOp::Eq),
+            "in" => Some(FilterOp::In),
+            "gte" => Some(FilterOp::Gte),
+            "like" => Some(FilterOp::Like),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortDirection {
+    Asc,
+    Desc,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sort {
+    pub field: String,
+    pub direction: SortDirection,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Filter {
+    pub field: String,
+    pub op: FilterOp,
+    pub values: Vec<String>,
+}
+
+impl Filter {
+    /// Parse the single value of an `eq`, `gte` or `like` filter.
+    pub fn value<T: FromStr>(&self) -> Result<T, HttpError> {
+        self.values
+            .first()
+            .and_then(|value| value.parse().ok())
+            .ok_or_else(|| self.invalid())
+    }
+
+    /// Parse every value of an `in` filter.
+    pub fn values<T: FromStr>(&self) -> Result<Vec<T>, HttpError> {
+        self.values
+            .iter()
+            .map(|value| value.parse().map_err(|_| self.invalid()))
+            .collect()
+    }
+
+    /// Value of a `like` filter as a contains pattern with LIKE wildcards escaped.
+    pub fn like_pattern(&self) -> Result<String, HttpError> {
+        let value = self.value::<String>()?;
+        let escaped = value
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_");
+        Ok(format!("%{escaped}%"))
+    }
+
+    pub fn invalid(&self) -> HttpError {
+        HttpError::bad_request(format!("INVALID_LIST_QUERY:FILTER_VALUE:{}", self.field))
+    }
+}
+
+/// Position after the last item of a page, encoded as opaque base64url JSON.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Cursor {
+    pub id: i64,
+}
+
+impl Cursor {
+    pub fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(self).unwrap_or_default())
+    }
+
+    pub fn decode(raw: &str) -> Result<Self, HttpError> {
+        URL_SAFE_NO_PAD
+            .decode(raw)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .ok_or_else(|| HttpError::bad_request("INVALID_LIST_QUERY:CURSOR"))
+    }
+}
+
+/// Whitelist of what a list endpoint accepts.
+pub trait ListConfig: Send + Sync + 'static {
+    /// Fields accepted by `sort`
+    const SORT_FIELDS: &'static [&'static str];
+    /// Fields accepted as filters with the operators allowed on each of them
+    const FILTER_FIELDS: &'static [(&'static str, &'static [FilterOp])];
+    /// Column used for cursor pagination, must be unique and ordered
+    const CURSOR_FIELD: &'static str = "id";
+    const DEFAULT_LIMIT: i64 = 20;
+    const MAX_LIMIT: i64 = 100;
+}
+
+#[derive(Debug)]
+pub struct ListQuery<C: ListConfig> {
+    pub page: i64,
+    pub limit: i64,
+    /// Set when the client asked for cursor pagination (`cursor` present, may be empty)
+    pub cursor: Option<Option<Cursor>>,
+    pub sort: Vec<Sort>,
+    pub filters: Vec<Filter>,
+    pub include_deleted: bool,
+    _config: PhantomData<fn() -> C>,
+}
+
+// Manual impl, the config marker type does not need to be Clone
+impl<C: ListConfig> Clone for ListQuery<C> {
+    fn clone(&self) -> Self {
+        ListQuery {
+            page: self.page,
+            limit: self.limit,
+            cursor: self.cursor,
+            sort: self.sort.clone(),
+            filters: self.filters.clone(),
+            include_deleted: self.include_deleted,
+            _config: PhantomData,
+        }
+    }
+}
+
+/// Rows before page `page` (from 1) of `limit` rows, `None` when it does not fit an i64.
+pub fn page_offset(
+    page: i64,
+    limit: i64,
+) -> Option<i64> {
+    page.checked_sub(1)?.checked_mul(limit)
+}
+
+impl<C: ListConfig> ListQuery<C> {
+    /// Rows before the page, [`ListQuery::from_pairs`] rejects pages past the i64 range.
+    pub fn offset(&self) -> i64 {
+        page_offset(self.page, self.limit).unwrap_or(i64::MAX)
+    }
+
+    pub fn is_cursor(&self) -> bool {
+        self.cursor.is_some()
+    }
+
+    /// Direction of the cursor column, descending (newest first) unless sorted otherwise.
+    pub fn cursor_direction(&self) -> SortDirection {
+        self.sort
+            .first()
+            .map(|sort| sort.direction)
+            .unwrap_or(SortDirection::Desc)
+    }
+
+    pub fn from_pairs(pairs: Vec<(String, String)>) -> Result<Self, HttpError> {
+        let invalid =
+            |reason: String| HttpError::bad_request(format!("INVALID_LIST_QUERY:{reason}"));
+        let mut page = 1;
+        let mut limit = C::DEFAULT_LIMIT;
+        let mut cursor = None;
+        let mut sort = Vec::new();
+        let mut filters = Vec::new();
+        let mut include_deleted = false;
+
+        for (key, value) in pairs {
+            match key.as_str() {
+                "page" => {
+                    page = value
+                        .parse::<i64>()
+                        .ok()
+                        .filter(|page| *page >= 1)
+                        .ok_or_else(|| invalid("PAGE".to_string()))?;
+                }
+                "limit" => {
+                    limit = value
+                        .parse::<i64>()
+                        .ok()
+                        .filter(|limit| (1..=C::MAX_LIMIT).contains(limit))
+                        .ok_or_else(|| invalid("LIMIT".to_string()))?;
+                }
+                "include_deleted" => {
+                    include_deleted = value
+                        .parse()
+                        .map_err(|_| invalid("INCLUDE_DELETED".to_string()))?;
+                }
+                "format" => {}
+                "cursor" if value.is_empty() => cursor = Some(None),
+                "cursor" => cursor = Some(Some(Cursor::decode(&value)?)),
+                "sort" => {
+                    for item in value.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+                        let (field, direction) = match item.strip_prefix('-') {
+                            Some(field) => (field, SortDirection::Desc),
+                            None => (item.trim_start_matches('+'), SortDirection::Asc),
+                        };
+                        if !C::SORT_FIELDS.contains(&field) {
+                            return Err(invalid(format!("SORT:{field}")));
+                        }
+                        sort.push(Sort {
+                            field: field.to_string(),
+                            direction,
+                        });
+                    }
+                }
+                _ => {
+                    // `field[op]` or plain `field` meaning equals
+                    let (field, op) = match key.split_once('[') {
+                        Some((field, rest)) => {
+                            let op = rest
+                                .strip_suffix(']')
+                                .and_then(FilterOp::parse)
+                                .ok_or_else(|| invalid(format!("FILTER:{key}")))?;
+                            (field, op)
+                        }
+                        None => (key.as_str(), FilterOp::Eq),
+                    };
+                    let allowed = C::FILTER_FIELDS
+                        .iter()
+                        .find(|(name, _)| *name == field)
+                        .map(|(_, ops)| ops.contains(&op))
+                        .unwrap_or(false);
+                    if !allowed {
+                        return Err(invalid(format!("FILTER:{key}")));
+                    }
+                    let values = match op {
+                        FilterOp::In => value
+                            .split(',')
+                            .map(|v| v.trim().to_string())
+                            .filter(|v| !v.is_empty())
+                            .collect(),
+                        _ => vec![value],
+                    };
+                    if values.is_empty() {
+                        return Err(invalid(format!("FILTER:{key}")));
+                    }
+                    filters.push(Filter {
+                        field: field.to_string(),
+                        op,
+                        values,
+                    });
+                }
+            }
+        }
+
+        // Checked once both are known, the parameters come in any order
+        if page_offset(page, limit).is_none() {
+            return Err(invalid("PAGE".to_string()));
+        }
+
+        // Keyset pagination only works on the unique cursor column
+        if cursor.is_some() && (sort.len() > 1 || sort.iter().any(|s| s.field != C::CURSOR_FIELD)) {
+            return Err(invalid("CURSOR_SORT".to_string()));
+        }
+
+        Ok(ListQuery {
+            page,
+            limit,
+            cursor,
+            sort,
+            filters,
+            include_deleted,
+            _config: PhantomData,
+        })
+    }
+}
+
+/// One page of results, `total` is only counted for offset pagination
+#[derive(Debug, Clone)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub total: Option<i64>,
+    pub next_cursor: Option<String>,
+}
+
+impl<T> Page<T> {
+    pub fn map<U, F: FnMut(T) -> U>(
+        self,
+        f: F,
+    ) -> Page<U> {
+        Page {
+            items: self.items.into_iter().map(f).collect(),
+            total: self.total,
+            next_cursor: self.next_cursor,
+        }
+    }
+}
+
+impl<T: Serialize> Page<T> {
+    /// Format with the response shape matching the pagination mode of the query.
+    pub fn into_json<C: ListConfig>(
+        self,
+        query: &ListQuery<C>,
+    ) -> serde_json::Value {
+        if query.is_cursor() {
+            cursor_response_formatted(self.items, query.limit, self.next_cursor)
+        } else {
+            pagination_response_formatted(
+                self.items,
+                query.page,
+                query.limit,
+                self.total.unwrap_or_default(),
+            )
+        }
+    }
+}
+
+#[async_trait]
+impl<S, C> FromRequestParts<S> for ListQuery<C>
+where
+    S: Send + Sync,
+    C: ListConfig,
+{
+    type Rejection = HttpError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        _state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        let Query(pairs) = Query::<Vec<(String, String)>>::try_from_uri(&parts.uri)
+            .map_err(|e| HttpError::bad_request(format!("INVALID_LIST_QUERY:{}", e)))?;
+        Self::from_pairs(pairs)
+    }
+}
+
+/// Apply a filter on a non text column, `like` is rejected.
+#[macro_export]
+macro_rules! filter_column {
+    ($query:expr, $column:expr, $filter:expr, $ty:ty) => {{
+        use $crate::utils::list_query::FilterOp;
+        let filter: &$crate::utils::list_query::Filter = $filter;
+        match filter.op {
+            FilterOp::Eq => $query.filter($column.eq(filter.value::<$ty>()?)),
+            FilterOp::In => $query.filter($column.eq_any(filter.values::<$ty>()?)),
+            FilterOp::Gte => $query.filter($column.ge(filter.value::<$ty>()?)),
+            FilterOp::Like => return Err(filter.invalid().into()),
+        }
+    }};
+}
+
+/// Apply a filter on a text column, `like` is a case insensitive contains match.
+#[macro_export]
+macro_rules! filter_text_column {
+    ($query:expr, $column:expr, $filter:expr) => {{
+        use $crate::utils::list_query::FilterOp;
+        let filter: &$crate::utils::list_query::Filter = $filter;
+        match filter.op {
+            FilterOp::Like => $query.filter($column.ilike(filter.like_pattern()?)),
+            _ => $crate::filter_column!($query, $column, filter, String),
+        }
+    }};
+}
+
+/// Append an ORDER BY on the given column.
+#[macro_export]
+macro_rules! sort_column {
+    ($query:expr, $column:expr, $direction:expr) => {{
+        match $direction {
+            $crate::utils::list_query::SortDirection::Asc => $query.then_order_by($column.asc()),
+            $crate::utils::list_query::SortDirection::Desc => $query.then_order_by($column.desc()),
+        }
+    }};
+}
+
+// --- Unit Tests ---
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TestConfig;
+    impl ListConfig for TestConfig {
+        const SORT_FIELDS: &'static [&'static str] = &["id", "name", "created_at"];
+        const FILTER_FIELDS: &'static [(&'static str, &'static [FilterOp])] = &[
+            ("name", &[FilterOp::Eq, FilterOp::Like]),
+            ("status", &[FilterOp::Eq, FilterOp::In]),
+            ("created_at", &[FilterOp::Gte]),
+        ];
+    }
+
+    fn parse(query: &str) -> Result<ListQuery<TestConfig>, HttpError> {
+        let uri: axum::http::Uri = format!("/items?{query}").parse().unwrap();
+        let Query(pairs) = Query::<Vec<(String, String)>>::try_from_uri(&uri).unwrap();
+        ListQuery::from_pairs(pairs)
+    }
+
+    #[test]
+    fn defaults_to_first_page() {
+        let query = parse("").unwrap();
+        assert_eq!(query.page, 1);
+        assert_eq!(query.limit, TestConfig::DEFAULT_LIMIT);
+        assert_eq!(query.offset(), 0);
+        assert!(!query.is_cursor());
+        assert!(!query.include_deleted);
+    }
+
+    #[test]
+    fn parses_include_deleted() {
+        assert!(parse("include_deleted=true").unwrap().include_deleted);
+        assert!(!parse("include_deleted=false").unwrap().include_deleted);
+        assert!(parse("include_deleted=yes").is_err());
+    }
+
+    #[test]
+    fn parses_sort_and_filters() {
+        let query =
+            parse("page=3&limit=10&sort=-created_at,name&name[like]=tho&status[in]=a,%20b&created_at[gte]=2024-01-01T00:00:00Z")
+                .unwrap();
+        assert_eq!(query.offset(), 20);
+        assert_eq!(
+            query.sort,
+            vec![
+                Sort {
+                    field: "created_at".into(),
+                    direction: SortDirection::Desc
+                },
+                Sort {
+                    field: "name".into(),
+                    direction: SortDirection::Asc
+                }
+            ]
+        );
+        assert_eq!(query.filters.len(), 3);
+        assert_eq!(query.filters[0].like_pattern().unwrap(), "%tho%");
+        assert_eq!(query.filters[1].values::<String>().unwrap(), vec!["a", "b"]);
+        assert!(query.filters[2]
+            .value::<chrono::DateTime<chrono::Utc>>()
+            .is_ok());
+    }
+
+    #[test]
+    fn rejects_unknown_fields_operators_and_limits() {
+        assert!(parse("sort=password").is_err());
+        assert!(parse("password=x").is_err());
+        assert!(parse("name[gte]=x").is_err());
+        assert!(parse("name[regex]=x").is_err());
+        assert!(parse("limit=0").is_err());
+        assert!(parse("limit=1000").is_err());
+        assert!(parse("page=0").is_err());
+        assert!(parse("page=9223372036854775807").is_err());
+        assert!(parse("limit=100&page=92233720368547760").is_err());
+        assert!(parse("cursor=not-a-cursor").is_err());
+        assert!(parse("cursor=&sort=name").is_err());
+    }
+
+    #[test]
+    fn escapes_like_wildcards() {
+        let query = parse("name[like]=50%25_off").unwrap();
+        assert_eq!(query.filters[0].like_pattern().unwrap(), "%50\\%\\_off%");
+    }
+
+    #[test]
+    fn cursor_round_trip() {
+        let cursor = Cursor { id: 42 };
+        let query = parse(&format!("cursor={}&sort=id", cursor.encode())).unwrap();
+        assert_eq!(query.cursor, Some(Some(cursor)));
+        assert_eq!(query.cursor_direction(), SortDirection::Asc);
+        assert_eq!(
+            parse("cursor=").unwrap().cursor_direction(),
+            SortDirection::Desc
+        );
+    }
+}
@@ -24,7 +24,7 @@ impl Logger {
                 Some(guard)
             }
 
-            // Production: separate INFO (access) and ERROR logs, still echo INFO to stdout
+            // Production: separate INFO (access) and WARN/ERROR logs, still echo INFO to stdout
             AppEnv::Production => {
                 let access_file = rolling::daily("logs", "access.log");
                 let error_file = rolling::daily("logs", "error.log");
@@ -40,7 +40,7 @@ impl Logger {
                 let error_layer = tracing_subscriber::fmt::layer()
                     .with_writer(error_file)
                     .with_ansi(false)
-                    .with_filter(filter_fn(|meta| meta.level() == &Level::ERROR));
+                    .with_filter(filter_fn(|meta| meta.level() <= &Level::WARN));
 
                 tracing_subscriber::registry()
                     .with(access_layer)
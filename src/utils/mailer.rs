@@ -0,0 +1,124 @@
+use lettre::message::header::ContentType;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{Message, SmtpTransport, Transport};
+use std::fmt;
+
+/// Custom error type for mail delivery
+#[derive(Debug)]
+pub enum MailerError {
+    InvalidConfig(String),
+    InvalidAddress(String),
+    SendFailed(String),
+}
+
+impl fmt::Display for MailerError {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+    ) -> fmt::Result {
+        match self {
+            MailerError::InvalidConfig(msg) => write!(f, "MAILER_INVALID_CONFIG: {}", msg),
+            MailerError::InvalidAddress(msg) => write!(f, "MAILER_INVALID_ADDRESS: {}", msg),
+            MailerError::SendFailed(msg) => write!(f, "MAILER_SEND_FAILED: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for MailerError {}
+
+/// SMTP mailer built from the `MAIL_SMTP` setting (`USERNAME;PASSWORD;HOST;PORT`).
+#[derive(Clone)]
+pub struct Mailer {
+    transport: SmtpTransport,
+    from: Mailbox,
+}
+
+impl Mailer {
+    pub fn from_config(
+        mail_smtp: &str,
+        mail_from: &str,
+    ) -> Result<Self, MailerError> {
+        let parts: Vec<&str> = mail_smtp.split(';').map(str::trim).collect();
+        let [username, password, host, port] = parts.as_slice() else {
+            return Err(MailerError::InvalidConfig(
+                "EXPECTED_USERNAME;PASSWORD;HOST;PORT".into(),
+            ));
+        };
+        let port: u16 = port
+            .parse()
+            .map_err(|_| MailerError::InvalidConfig(format!("INVALID_PORT:{port}")))?;
+
+        // 465 is implicit TLS, 587 upgrades with STARTTLS, anything else (e.g. a local
+        // mail catcher on 1025) is plain text
+        let builder = match port {
+            465 => SmtpTransport::relay(host),
+            587 => SmtpTransport::starttls_relay(host),
+            _ => Ok(SmtpTransport::builder_dangerous(*host)),
+        }
+        .map_err(|e| MailerError::InvalidConfig(e.to_string()))?
+        .port(port);
+        let builder = if username.is_empty() {
+            builder
+        } else {
+            builder.credentials(Credentials::new(username.to_string(), password.to_string()))
+        };
+
+        let from = mail_from
+            .parse::<Mailbox>()
+            .map_err(|e| MailerError::InvalidAddress(e.to_string()))?;
+        Ok(Mailer {
+            transport: builder.build(),
+            from,
+        })
+    }
+
+    /// Send a plain text email, the blocking SMTP exchange runs off the async runtime.
+    pub async fn send(
+        &self,
+        to: &str,
+        subject: &str,
+        body: String,
+    ) -> Result<(), MailerError> {
+        let to = to
+            .parse::<Mailbox>()
+            .map_err(|e| MailerError::InvalidAddress(e.to_string()))?;
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(to)
+            .subject(subject)
+            .header(ContentType::TEXT_PLAIN)
+            .body(body)
+            .map_err(|e| MailerError::InvalidAddress(e.to_string()))?;
+
+        let transport = self.transport.clone();
+        tokio::task::spawn_blocking(move || transport.send(&message))
+            .await
+            .map_err(|e| MailerError::SendFailed(e.to_string()))?
+            .map_err(|e| MailerError::SendFailed(e.to_string()))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_smtp_config() {
+        assert!(Mailer::from_config("user;pass;localhost;1025", "no-reply@example.com").is_ok());
+        assert!(Mailer::from_config(";;localhost;1025", "App <no-reply@example.com>").is_ok());
+        assert!(matches!(
+            Mailer::from_config("USERNAME;PASSWORD;HOST;PORT", "no-reply@example.com"),
+            Err(MailerError::InvalidConfig(_))
+        ));
+        assert!(matches!(
+            Mailer::from_config("", "no-reply@example.com"),
+            Err(MailerError::InvalidConfig(_))
+        ));
+        assert!(matches!(
+            Mailer::from_config("user;pass;localhost;1025", "not-an-address"),
+            Err(MailerError::InvalidAddress(_))
+        ));
+    }
+}
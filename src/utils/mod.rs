@@ -1,13 +1,20 @@
 pub mod cache;
 pub mod encrypt;
 pub mod errors;
+pub mod etag;
+pub mod export;
 pub mod extractor;
 pub mod files;
 pub mod generator;
 pub mod generator_account;
+pub mod list_query;
 pub mod logger;
+pub mod mailer;
 pub mod nric;
+pub mod oidc;
+pub mod patch;
 pub mod responses;
 pub mod string;
 pub mod structify;
+pub mod thumbnail;
 pub mod token;
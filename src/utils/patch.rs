@@ -0,0 +1,207 @@
+//! `PATCH` request bodies.
+//!
+//! [`BodyPatch`] accepts either a JSON Merge Patch (`application/merge-patch+json`,
+//! RFC 7396) or a JSON Patch (`application/json-patch+json`, RFC 6902). The handler loads
+//! the current resource, [`BodyPatch::apply`]s the patch to its JSON representation and gets
+//! back the patched value deserialized and validated with the same rules as `BodyJson`.
+use crate::utils::errors::HttpError;
+use crate::utils::extractor::format_validation_errors;
+use axum::{
+    async_trait,
+    body::{Body, Bytes},
+    extract::{FromRequest, Request},
+    http::{header, StatusCode},
+};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use validator::Validate;
+
+pub const MERGE_PATCH_CONTENT_TYPE: &str = "application/merge-patch+json";
+pub const JSON_PATCH_CONTENT_TYPE: &str = "application/json-patch+json";
+
+#[derive(Debug, Clone)]
+pub enum BodyPatch {
+    Merge(Value),
+    Json(json_patch::Patch),
+}
+
+impl BodyPatch {
+    /// Apply the patch to `current`, then deserialize and validate the result.
+    pub fn apply<T>(
+        &self,
+        current: &T,
+    ) -> Result<T, HttpError>
+    where
+        T: Serialize + DeserializeOwned + Validate,
+    {
+        let mut document = serde_json::to_value(current).map_err(|err| {
+            tracing::error!(error = %err, "PATCH_SERIALIZE_FAILED");
+            HttpError::server_error("PATCH_SERIALIZE_FAILED")
+        })?;
+        match self {
+            BodyPatch::Merge(patch) => json_patch::merge(&mut document, patch),
+            // Operations are applied atomically, a failing one leaves the document untouched
+            BodyPatch::Json(patch) => json_patch::patch(&mut document, patch).map_err(|err| {
+                HttpError::new(
+                    format!("INVALID_PATCH_OPERATION:{err}"),
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                )
+            })?,
+        }
+
+        let patched: T = serde_json::from_value(document).map_err(|err| {
+            HttpError::new(
+                format!("INVALID_PATCH_RESULT:{err}"),
+                StatusCode::UNPROCESSABLE_ENTITY,
+            )
+        })?;
+        patched.validate().map_err(|err| {
+            HttpError::bad_request(format!(
+                "INVALID_VALIDATION | {}",
+                format_validation_errors(&err)
+            ))
+        })?;
+        Ok(patched)
+    }
+}
+
+#[async_trait]
+impl<S> FromRequest<S, Body> for BodyPatch
+where
+    S: Send + Sync,
+{
+    type Rejection = HttpError;
+
+    async fn from_request(
+        req: Request<Body>,
+        state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        let content_type = req
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.split(';').next())
+            .map(|value| value.trim().to_ascii_lowercase())
+            .unwrap_or_default();
+        let is_merge = match content_type.as_str() {
+            MERGE_PATCH_CONTENT_TYPE => true,
+            JSON_PATCH_CONTENT_TYPE => false,
+            _ => {
+                return Err(HttpError::new(
+                    "PATCH_UNSUPPORTED_CONTENT_TYPE",
+                    StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                ))
+            }
+        };
+
+        let body = Bytes::from_request(req, state)
+            .await
+            .map_err(|e| HttpError::bad_request(format!("INVALID_BODY_REQUEST:{}", e)))?;
+        let invalid =
+            |e: serde_json::Error| HttpError::bad_request(format!("INVALID_BODY_REQUEST:{}", e));
+        if is_merge {
+            Ok(BodyPatch::Merge(
+                serde_json::from_slice(&body).map_err(invalid)?,
+            ))
+        } else {
+            Ok(BodyPatch::Json(
+                serde_json::from_slice(&body).map_err(invalid)?,
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize, Validate)]
+    struct Profile {
+        #[validate(length(min = 3, message = "Name must be at least 3 characters"))]
+        name: String,
+        nickname: Option<String>,
+        tags: Vec<String>,
+    }
+
+    fn profile() -> Profile {
+        Profile {
+            name: "Thomas".into(),
+            nickname: Some("tom".into()),
+            tags: vec!["a".into()],
+        }
+    }
+
+    async fn extract(
+        content_type: &str,
+        body: &str,
+    ) -> Result<BodyPatch, HttpError> {
+        let req = Request::builder()
+            .header(header::CONTENT_TYPE, content_type)
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        BodyPatch::from_request(req, &()).await
+    }
+
+    #[tokio::test]
+    async fn merge_patch_sets_and_removes_fields() {
+        let patch = extract(
+            MERGE_PATCH_CONTENT_TYPE,
+            r#"{"name":"Thom","nickname":null}"#,
+        )
+        .await
+        .unwrap();
+        let patched = patch.apply(&profile()).unwrap();
+        assert_eq!(patched.name, "Thom");
+        assert_eq!(patched.nickname, None);
+        assert_eq!(patched.tags, vec!["a".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn json_patch_applies_operations() {
+        let patch = extract(
+            "application/json-patch+json; charset=utf-8",
+            r#"[{"op":"test","path":"/name","value":"Thomas"},{"op":"add","path":"/tags/-","value":"b"}]"#,
+        )
+        .await
+        .unwrap();
+        assert_eq!(patch.apply(&profile()).unwrap().tags, vec!["a", "b"]);
+
+        let failing = extract(
+            JSON_PATCH_CONTENT_TYPE,
+            r#"[{"op":"test","path":"/name","value":"Someone else"}]"#,
+        )
+        .await
+        .unwrap();
+        let err = failing.apply(&profile()).unwrap_err();
+        assert_eq!(err.status, StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[tokio::test]
+    async fn patched_value_is_validated() {
+        let patch = extract(MERGE_PATCH_CONTENT_TYPE, r#"{"name":"ab"}"#)
+            .await
+            .unwrap();
+        let err = patch.apply(&profile()).unwrap_err();
+        assert_eq!(err.status, StatusCode::BAD_REQUEST);
+        assert!(err.message.starts_with("INVALID_VALIDATION"));
+
+        let wrong_type = extract(MERGE_PATCH_CONTENT_TYPE, r#"{"tags":"b"}"#)
+            .await
+            .unwrap();
+        assert_eq!(
+            wrong_type.apply(&profile()).unwrap_err().status,
+            StatusCode::UNPROCESSABLE_ENTITY
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_other_content_types() {
+        let err = extract("application/json", "{}").await.unwrap_err();
+        assert_eq!(err.status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        let err = extract(JSON_PATCH_CONTENT_TYPE, r#"{"op":"add"}"#)
+            .await
+            .unwrap_err();
+        assert_eq!(err.status, StatusCode::BAD_REQUEST);
+    }
+}
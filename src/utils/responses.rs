@@ -129,7 +129,12 @@ pub fn pagination_response_formatted<T: Serialize>(
     limit: i64,
     total: i64,
 ) -> serde_json::Value {
-    let total_pages = (total as f64 / limit as f64).ceil() as i64;
+    // Guard against a zero limit instead of dividing into infinity
+    let total_pages = if limit > 0 {
+        (total as f64 / limit as f64).ceil() as i64
+    } else {
+        0
+    };
     json!({
         "items": data,
         "pagination": {
@@ -140,3 +145,17 @@ pub fn pagination_response_formatted<T: Serialize>(
         }
     })
 }
+
+pub fn cursor_response_formatted<T: Serialize>(
+    data: Vec<T>,
+    limit: i64,
+    next_cursor: Option<String>,
+) -> serde_json::Value {
+    json!({
+        "items": data,
+        "pagination": {
+            "limit": limit,
+            "next_cursor": next_cursor
+        }
+    })
+}
@@ -0,0 +1,120 @@
+use image::imageops::FilterType;
+use image::{ImageFormat, ImageReader, Limits};
+use std::fmt;
+use std::io::Cursor;
+
+/// Custom error type for image thumbnails
+#[derive(Debug)]
+pub enum ThumbnailError {
+    UnsupportedFormat,
+    TooLarge,
+    Decode(String),
+    Encode(String),
+}
+
+impl fmt::Display for ThumbnailError {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+    ) -> fmt::Result {
+        match self {
+            ThumbnailError::UnsupportedFormat => write!(f, "IMAGE_UNSUPPORTED_FORMAT"),
+            ThumbnailError::TooLarge => write!(f, "IMAGE_TOO_LARGE"),
+            ThumbnailError::Decode(msg) => write!(f, "IMAGE_DECODE_FAILED: {}", msg),
+            ThumbnailError::Encode(msg) => write!(f, "IMAGE_ENCODE_FAILED: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for ThumbnailError {}
+
+// Formats accepted for uploads, detected from the content rather than the client header
+const ACCEPTED_FORMATS: [ImageFormat; 3] = [ImageFormat::Jpeg, ImageFormat::Png, ImageFormat::WebP];
+
+/// Detect the image format from the magic bytes, `None` for anything not accepted.
+pub fn detect_format(bytes: &[u8]) -> Option<ImageFormat> {
+    image::guess_format(bytes)
+        .ok()
+        .filter(|format| ACCEPTED_FORMATS.contains(format))
+}
+
+/// Center crop and resize to square PNG thumbnails, one per size in the same order.
+///
+/// Decoding is bounded by `max_dimension` so a small file cannot expand into a huge bitmap.
+/// CPU bound, call it from `spawn_blocking`.
+pub fn square_thumbnails(
+    bytes: &[u8],
+    sizes: &[u32],
+    max_dimension: u32,
+) -> Result<Vec<Vec<u8>>, ThumbnailError> {
+    let format = detect_format(bytes).ok_or(ThumbnailError::UnsupportedFormat)?;
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(max_dimension);
+    limits.max_image_height = Some(max_dimension);
+
+    let mut reader = ImageReader::with_format(Cursor::new(bytes), format);
+    reader.limits(limits);
+    let image = reader.decode().map_err(|err| match err {
+        image::ImageError::Limits(_) => ThumbnailError::TooLarge,
+        err => ThumbnailError::Decode(err.to_string()),
+    })?;
+
+    sizes
+        .iter()
+        .map(|size| {
+            let mut output = Vec::new();
+            image
+                .resize_to_fill(*size, *size, FilterType::Lanczos3)
+                .write_to(&mut Cursor::new(&mut output), ImageFormat::Png)
+                .map_err(|err| ThumbnailError::Encode(err.to_string()))?;
+            Ok(output)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{DynamicImage, RgbImage};
+
+    fn encode(
+        width: u32,
+        height: u32,
+        format: ImageFormat,
+    ) -> Vec<u8> {
+        let mut output = Vec::new();
+        DynamicImage::ImageRgb8(RgbImage::new(width, height))
+            .write_to(&mut Cursor::new(&mut output), format)
+            .unwrap();
+        output
+    }
+
+    #[test]
+    fn resizes_to_square_thumbnails() {
+        let source = encode(300, 200, ImageFormat::Jpeg);
+        let thumbnails = square_thumbnails(&source, &[64, 128], 4096).unwrap();
+        assert_eq!(thumbnails.len(), 2);
+        for (thumbnail, size) in thumbnails.iter().zip([64, 128]) {
+            let decoded = image::load_from_memory(thumbnail).unwrap();
+            assert_eq!((decoded.width(), decoded.height()), (size, size));
+            assert_eq!(detect_format(thumbnail), Some(ImageFormat::Png));
+        }
+    }
+
+    #[test]
+    fn rejects_unsupported_and_oversized_images() {
+        assert!(matches!(
+            square_thumbnails(b"GIF89a not really", &[64], 4096),
+            Err(ThumbnailError::UnsupportedFormat)
+        ));
+        assert!(matches!(
+            square_thumbnails(b"plain text", &[64], 4096),
+            Err(ThumbnailError::UnsupportedFormat)
+        ));
+        let source = encode(200, 100, ImageFormat::Png);
+        assert!(matches!(
+            square_thumbnails(&source, &[64], 150),
+            Err(ThumbnailError::TooLarge)
+        ));
+    }
+}
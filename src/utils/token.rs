@@ -8,11 +8,57 @@ pub struct TokenClaims {
     pub sub: String,
     pub iat: usize,
     pub exp: usize,
+    // Organization id of tenant scoped access tokens
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
 }
 
+// Lifetime of tokens issued by create_token and create_refresh_token
+pub const ACCESS_TOKEN_TTL_SECS: i64 = 12 * 60 * 60; // 12 hours
+pub const REFRESH_TOKEN_TTL_SECS: i64 = 30 * 24 * 60 * 60; // 30 days
+
 pub fn create_token(
     data: String,
     secret: &[u8],
+) -> Result<String, Error> {
+    create_token_with_ttl(data, secret, Duration::seconds(ACCESS_TOKEN_TTL_SECS))
+}
+
+// Refresh tokens must be signed with a different secret than access tokens
+pub fn create_refresh_token(
+    data: String,
+    secret: &[u8],
+) -> Result<String, Error> {
+    create_token_with_ttl(data, secret, Duration::seconds(REFRESH_TOKEN_TTL_SECS))
+}
+
+// Access token bound to one organization, resolved as the request tenant
+pub fn create_tenant_token(
+    data: String,
+    tenant: String,
+    secret: &[u8],
+) -> Result<String, Error> {
+    create_claims_token(
+        data,
+        Some(tenant),
+        secret,
+        Duration::seconds(ACCESS_TOKEN_TTL_SECS),
+    )
+}
+
+pub fn create_token_with_ttl(
+    data: String,
+    secret: &[u8],
+    ttl: Duration,
+) -> Result<String, Error> {
+    create_claims_token(data, None, secret, ttl)
+}
+
+fn create_claims_token(
+    data: String,
+    tenant: Option<String>,
+    secret: &[u8],
+    ttl: Duration,
 ) -> Result<String, Error> {
     // Validate input early
     if data.is_empty() {
@@ -23,7 +69,8 @@ pub fn create_token(
     let claims = TokenClaims {
         sub: data,
         iat: now.timestamp() as usize,
-        exp: (now + Duration::hours(12)).timestamp() as usize,
+        exp: (now + ttl).timestamp() as usize,
+        tenant,
     };
 
     encode(
@@ -37,6 +84,20 @@ pub fn decode_token<T: AsRef<str>>(
     token: T,
     secret: &[u8],
 ) -> Result<(String, String), HttpError> {
+    let claims = decode_claims(token, secret)?;
+    // Parse token data
+    let (user_id, email) = claims
+        .sub
+        .split_once("|")
+        .ok_or_else(|| HttpError::unauthorized("INVALID_TOKEN"))?;
+
+    Ok((user_id.to_string(), email.to_string()))
+}
+
+pub fn decode_claims<T: AsRef<str>>(
+    token: T,
+    secret: &[u8],
+) -> Result<TokenClaims, HttpError> {
     let token_ref = token.as_ref();
 
     match decode::<TokenClaims>(
@@ -49,14 +110,7 @@ pub fn decode_token<T: AsRef<str>>(
             if token_data.claims.exp < token_data.claims.iat {
                 return Err(HttpError::unauthorized("EXPIRED_SIGNATURE"));
             }
-            // Parse token data
-            let (user_id, email) = token_data
-                .claims
-                .sub
-                .split_once("|")
-                .ok_or_else(|| HttpError::unauthorized("INVALID_TOKEN"))?;
-
-            Ok((user_id.to_string(), email.to_string()))
+            Ok(token_data.claims)
         }
         Err(err) => match err.kind() {
             ErrorKind::ExpiredSignature => Err(HttpError::unauthorized("EXPIRED_SIGNATURE")),